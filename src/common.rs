@@ -7,7 +7,7 @@ use std::{cmp::Ordering, fmt::Debug};
 
 use serde::{de, Deserializer, Serializer};
 use std::convert::*;
-use strum_macros::{Display, EnumString};
+use strum_macros::Display;
 
 pub(crate) static SYMM_UNIT_INTERVAL: LazyLock<NumInterval<f32>> =
     LazyLock::new(|| NumInterval::new(-1.0, 1.0));
@@ -75,17 +75,215 @@ define_control_types! {
         RelHWheelHiRes => REL_HWHEEL_HI_RES,
     }
     button {
+        // Joystick
+        BtnTrigger => BTN_TRIGGER,
+        BtnThumb => BTN_THUMB,
+        BtnThumb2 => BTN_THUMB2,
+        BtnTop => BTN_TOP,
+        BtnTop2 => BTN_TOP2,
+        BtnPinkie => BTN_PINKIE,
+        BtnBase => BTN_BASE,
+        BtnBase2 => BTN_BASE2,
+        BtnBase3 => BTN_BASE3,
+        BtnBase4 => BTN_BASE4,
+        BtnBase5 => BTN_BASE5,
+        BtnBase6 => BTN_BASE6,
+        BtnDead => BTN_DEAD,
+        // Gamepad
         BtnSouth => BTN_SOUTH,
         BtnEast => BTN_EAST,
-        BtnWest => BTN_WEST,
+        BtnC => BTN_C,
         BtnNorth => BTN_NORTH,
-        BtnStart => BTN_START,
+        BtnWest => BTN_WEST,
+        BtnZ => BTN_Z,
+        BtnTl => BTN_TL,
+        BtnTr => BTN_TR,
+        BtnTl2 => BTN_TL2,
+        BtnTr2 => BTN_TR2,
         BtnSelect => BTN_SELECT,
+        BtnStart => BTN_START,
+        BtnMode => BTN_MODE,
+        BtnThumbl => BTN_THUMBL,
+        BtnThumbr => BTN_THUMBR,
+        BtnDpadUp => BTN_DPAD_UP,
+        BtnDpadDown => BTN_DPAD_DOWN,
+        BtnDpadLeft => BTN_DPAD_LEFT,
+        BtnDpadRight => BTN_DPAD_RIGHT,
+        // Mouse
         BtnLeft => BTN_LEFT,
         BtnRight => BTN_RIGHT,
         BtnMiddle => BTN_MIDDLE,
         BtnSide => BTN_SIDE,
         BtnExtra => BTN_EXTRA,
+        BtnForward => BTN_FORWARD,
+        BtnBack => BTN_BACK,
+        BtnTask => BTN_TASK,
+        // Extra buttons of button boxes and wheels
+        BtnTriggerHappy1 => BTN_TRIGGER_HAPPY1,
+        BtnTriggerHappy2 => BTN_TRIGGER_HAPPY2,
+        BtnTriggerHappy3 => BTN_TRIGGER_HAPPY3,
+        BtnTriggerHappy4 => BTN_TRIGGER_HAPPY4,
+        BtnTriggerHappy5 => BTN_TRIGGER_HAPPY5,
+        BtnTriggerHappy6 => BTN_TRIGGER_HAPPY6,
+        BtnTriggerHappy7 => BTN_TRIGGER_HAPPY7,
+        BtnTriggerHappy8 => BTN_TRIGGER_HAPPY8,
+        BtnTriggerHappy9 => BTN_TRIGGER_HAPPY9,
+        BtnTriggerHappy10 => BTN_TRIGGER_HAPPY10,
+        BtnTriggerHappy11 => BTN_TRIGGER_HAPPY11,
+        BtnTriggerHappy12 => BTN_TRIGGER_HAPPY12,
+        BtnTriggerHappy13 => BTN_TRIGGER_HAPPY13,
+        BtnTriggerHappy14 => BTN_TRIGGER_HAPPY14,
+        BtnTriggerHappy15 => BTN_TRIGGER_HAPPY15,
+        BtnTriggerHappy16 => BTN_TRIGGER_HAPPY16,
+        BtnTriggerHappy17 => BTN_TRIGGER_HAPPY17,
+        BtnTriggerHappy18 => BTN_TRIGGER_HAPPY18,
+        BtnTriggerHappy19 => BTN_TRIGGER_HAPPY19,
+        BtnTriggerHappy20 => BTN_TRIGGER_HAPPY20,
+        BtnTriggerHappy21 => BTN_TRIGGER_HAPPY21,
+        BtnTriggerHappy22 => BTN_TRIGGER_HAPPY22,
+        BtnTriggerHappy23 => BTN_TRIGGER_HAPPY23,
+        BtnTriggerHappy24 => BTN_TRIGGER_HAPPY24,
+        BtnTriggerHappy25 => BTN_TRIGGER_HAPPY25,
+        BtnTriggerHappy26 => BTN_TRIGGER_HAPPY26,
+        BtnTriggerHappy27 => BTN_TRIGGER_HAPPY27,
+        BtnTriggerHappy28 => BTN_TRIGGER_HAPPY28,
+        BtnTriggerHappy29 => BTN_TRIGGER_HAPPY29,
+        BtnTriggerHappy30 => BTN_TRIGGER_HAPPY30,
+        BtnTriggerHappy31 => BTN_TRIGGER_HAPPY31,
+        BtnTriggerHappy32 => BTN_TRIGGER_HAPPY32,
+        BtnTriggerHappy33 => BTN_TRIGGER_HAPPY33,
+        BtnTriggerHappy34 => BTN_TRIGGER_HAPPY34,
+        BtnTriggerHappy35 => BTN_TRIGGER_HAPPY35,
+        BtnTriggerHappy36 => BTN_TRIGGER_HAPPY36,
+        BtnTriggerHappy37 => BTN_TRIGGER_HAPPY37,
+        BtnTriggerHappy38 => BTN_TRIGGER_HAPPY38,
+        BtnTriggerHappy39 => BTN_TRIGGER_HAPPY39,
+        BtnTriggerHappy40 => BTN_TRIGGER_HAPPY40,
+    }
+    key {
+        KeyEsc => KEY_ESC,
+        Key1 => KEY_1 as "KEY_1",
+        Key2 => KEY_2 as "KEY_2",
+        Key3 => KEY_3 as "KEY_3",
+        Key4 => KEY_4 as "KEY_4",
+        Key5 => KEY_5 as "KEY_5",
+        Key6 => KEY_6 as "KEY_6",
+        Key7 => KEY_7 as "KEY_7",
+        Key8 => KEY_8 as "KEY_8",
+        Key9 => KEY_9 as "KEY_9",
+        Key0 => KEY_0 as "KEY_0",
+        KeyMinus => KEY_MINUS,
+        KeyEqual => KEY_EQUAL,
+        KeyBackspace => KEY_BACKSPACE,
+        KeyTab => KEY_TAB,
+        KeyQ => KEY_Q,
+        KeyW => KEY_W,
+        KeyE => KEY_E,
+        KeyR => KEY_R,
+        KeyT => KEY_T,
+        KeyY => KEY_Y,
+        KeyU => KEY_U,
+        KeyI => KEY_I,
+        KeyO => KEY_O,
+        KeyP => KEY_P,
+        KeyLeftbrace => KEY_LEFTBRACE,
+        KeyRightbrace => KEY_RIGHTBRACE,
+        KeyEnter => KEY_ENTER,
+        KeyLeftctrl => KEY_LEFTCTRL,
+        KeyA => KEY_A,
+        KeyS => KEY_S,
+        KeyD => KEY_D,
+        KeyF => KEY_F,
+        KeyG => KEY_G,
+        KeyH => KEY_H,
+        KeyJ => KEY_J,
+        KeyK => KEY_K,
+        KeyL => KEY_L,
+        KeySemicolon => KEY_SEMICOLON,
+        KeyApostrophe => KEY_APOSTROPHE,
+        KeyGrave => KEY_GRAVE,
+        KeyLeftshift => KEY_LEFTSHIFT,
+        KeyBackslash => KEY_BACKSLASH,
+        KeyZ => KEY_Z,
+        KeyX => KEY_X,
+        KeyC => KEY_C,
+        KeyV => KEY_V,
+        KeyB => KEY_B,
+        KeyN => KEY_N,
+        KeyM => KEY_M,
+        KeyComma => KEY_COMMA,
+        KeyDot => KEY_DOT,
+        KeySlash => KEY_SLASH,
+        KeyRightshift => KEY_RIGHTSHIFT,
+        KeyKpasterisk => KEY_KPASTERISK,
+        KeyLeftalt => KEY_LEFTALT,
+        KeySpace => KEY_SPACE,
+        KeyCapslock => KEY_CAPSLOCK,
+        KeyF1 => KEY_F1,
+        KeyF2 => KEY_F2,
+        KeyF3 => KEY_F3,
+        KeyF4 => KEY_F4,
+        KeyF5 => KEY_F5,
+        KeyF6 => KEY_F6,
+        KeyF7 => KEY_F7,
+        KeyF8 => KEY_F8,
+        KeyF9 => KEY_F9,
+        KeyF10 => KEY_F10,
+        KeyNumlock => KEY_NUMLOCK,
+        KeyScrolllock => KEY_SCROLLLOCK,
+        KeyKp7 => KEY_KP7,
+        KeyKp8 => KEY_KP8,
+        KeyKp9 => KEY_KP9,
+        KeyKpminus => KEY_KPMINUS,
+        KeyKp4 => KEY_KP4,
+        KeyKp5 => KEY_KP5,
+        KeyKp6 => KEY_KP6,
+        KeyKpplus => KEY_KPPLUS,
+        KeyKp1 => KEY_KP1,
+        KeyKp2 => KEY_KP2,
+        KeyKp3 => KEY_KP3,
+        KeyKp0 => KEY_KP0,
+        KeyKpdot => KEY_KPDOT,
+        KeyF11 => KEY_F11,
+        KeyF12 => KEY_F12,
+        KeyKpenter => KEY_KPENTER,
+        KeyRightctrl => KEY_RIGHTCTRL,
+        KeyKpslash => KEY_KPSLASH,
+        KeySysrq => KEY_SYSRQ,
+        KeyRightalt => KEY_RIGHTALT,
+        KeyHome => KEY_HOME,
+        KeyUp => KEY_UP,
+        KeyPageup => KEY_PAGEUP,
+        KeyLeft => KEY_LEFT,
+        KeyRight => KEY_RIGHT,
+        KeyEnd => KEY_END,
+        KeyDown => KEY_DOWN,
+        KeyPagedown => KEY_PAGEDOWN,
+        KeyInsert => KEY_INSERT,
+        KeyDelete => KEY_DELETE,
+        KeyMute => KEY_MUTE,
+        KeyVolumedown => KEY_VOLUMEDOWN,
+        KeyVolumeup => KEY_VOLUMEUP,
+        KeyPause => KEY_PAUSE,
+        KeyLeftmeta => KEY_LEFTMETA,
+        KeyRightmeta => KEY_RIGHTMETA,
+        KeyCompose => KEY_COMPOSE,
+        KeyNextsong => KEY_NEXTSONG,
+        KeyPlaypause => KEY_PLAYPAUSE,
+        KeyPrevioussong => KEY_PREVIOUSSONG,
+        KeyStopcd => KEY_STOPCD,
+        KeyF13 => KEY_F13,
+        KeyF14 => KEY_F14,
+        KeyF15 => KEY_F15,
+        KeyF16 => KEY_F16,
+        KeyF17 => KEY_F17,
+        KeyF18 => KEY_F18,
+        KeyF19 => KEY_F19,
+        KeyF20 => KEY_F20,
+        KeyF21 => KEY_F21,
+        KeyF22 => KEY_F22,
+        KeyF23 => KEY_F23,
+        KeyF24 => KEY_F24,
     }
     midi {
         PitchWheel,
@@ -666,6 +864,31 @@ mod tests {
         assert!(btn_controls.contains(&ControlType::BtnNorth));
         assert!(btn_controls.contains(&ControlType::BtnLeft));
         assert!(btn_controls.contains(&ControlType::BtnRight));
+        assert!(btn_controls.contains(&ControlType::BtnTl));
+        assert!(btn_controls.contains(&ControlType::BtnThumbr));
+    }
+
+    #[test]
+    fn test_key_iterator() {
+        let key_controls: Vec<ControlType> = ControlType::iter_key().collect();
+
+        assert!(!key_controls.is_empty());
+
+        for control in &key_controls {
+            assert!(control.is_key(), "{:?} should be key", control);
+            assert!(!control.is_button(), "{:?} should not be button", control);
+            assert!(
+                !control.is_relative(),
+                "{:?} should not be relative",
+                control
+            );
+        }
+
+        assert!(key_controls.contains(&ControlType::KeyW));
+        assert!(key_controls.contains(&ControlType::KeyA));
+        assert!(key_controls.contains(&ControlType::KeyS));
+        assert!(key_controls.contains(&ControlType::KeyD));
+        assert!(key_controls.contains(&ControlType::KeySpace));
     }
 
     #[test]
@@ -681,6 +904,10 @@ mod tests {
         let btn_iter = ControlType::iter_button();
         let btn_count = btn_iter.len();
         assert_eq!(btn_count, btn_iter.count());
+
+        let key_iter = ControlType::iter_key();
+        let key_count = key_iter.len();
+        assert_eq!(key_count, key_iter.count());
     }
 
     #[test]
@@ -755,6 +982,22 @@ mod tests {
         assert_eq!(control, ControlType::BtnLeft);
     }
 
+    #[test]
+    fn test_from_evdev_key() {
+        use evdev::{EventType, InputEvent, KeyCode};
+
+        let event = InputEvent::new(EventType::KEY.0, KeyCode::KEY_W.0, 1);
+        let control: ControlType = event.into();
+        assert_eq!(control, ControlType::KeyW);
+
+        let event = InputEvent::new(EventType::KEY.0, KeyCode::KEY_LEFTCTRL.0, 2);
+        let control: ControlType = event.into();
+        assert_eq!(control, ControlType::KeyLeftctrl);
+
+        let code: u16 = ControlType::Key1.into();
+        assert_eq!(code, KeyCode::KEY_1.0);
+    }
+
     #[test]
     fn test_to_u16_absolute() {
         use evdev::AbsoluteAxisCode;
@@ -869,6 +1112,22 @@ mod tests {
             ControlType::BtnLeft
         );
 
+        // Test parsing keyboard keys (including names with digits)
+        assert_eq!(ControlType::from_str("KEY_W").unwrap(), ControlType::KeyW);
+        assert_eq!(ControlType::from_str("KEY_1").unwrap(), ControlType::Key1);
+        assert_eq!(
+            ControlType::from_str("KEY_F12").unwrap(),
+            ControlType::KeyF12
+        );
+        assert_eq!(
+            ControlType::from_str("KEY_LEFTSHIFT").unwrap(),
+            ControlType::KeyLeftshift
+        );
+        assert_eq!(
+            ControlType::from_str("KEY_KP0").unwrap(),
+            ControlType::KeyKp0
+        );
+
         // Test invalid string
         assert!(ControlType::from_str("INVALID").is_err());
     }
@@ -881,6 +1140,52 @@ mod tests {
         assert_eq!(ControlType::Unhandled.to_string(), "UNHANDLED");
     }
 
+    #[test]
+    fn test_raw_codes() {
+        use evdev::{EventType, InputEvent};
+        use std::str::FromStr;
+
+        // Joystick and extra buttons are named.
+        assert_eq!(
+            ControlType::from_str("BTN_TRIGGER_HAPPY40").unwrap(),
+            ControlType::BtnTriggerHappy40
+        );
+        assert_eq!(
+            ControlType::from_str("BTN_BASE2").unwrap(),
+            ControlType::BtnBase2
+        );
+        assert_eq!(
+            ControlType::from_str("BTN_DPAD_UP").unwrap(),
+            ControlType::BtnDpadUp
+        );
+
+        // A raw code having a name resolves to the named control.
+        assert_eq!(
+            ControlType::from_str("KEY:0x2c0").unwrap(),
+            ControlType::BtnTriggerHappy1
+        );
+        assert_eq!(ControlType::from_str("ABS:0").unwrap(), ControlType::AbsX);
+
+        let raw_abs = ControlType::from_str("ABS:0x29").unwrap();
+        assert_eq!(raw_abs, ControlType::RawAbsolute(0x29));
+        assert!(raw_abs.is_absolute());
+        assert_eq!(raw_abs.to_string(), "ABS:0x29");
+        assert_eq!(<u16 as From<ControlType>>::from(raw_abs), 0x29);
+
+        // Raw KEY codes in the button blocks are buttons, the others keys.
+        let raw_button = ControlType::from_str("KEY:0x2e8").unwrap();
+        assert!(!raw_button.is_button() && raw_button.is_key());
+        let raw_button = ControlType::from_str("KEY:0x10a").unwrap();
+        assert!(raw_button.is_button() && !raw_button.is_key());
+
+        let event = InputEvent::new(EventType::KEY.0, 0x1d4, 1);
+        assert_eq!(ControlType::from(event), ControlType::RawKey(0x1d4));
+
+        assert!(ControlType::from_str("ABS:0x40").is_err());
+        assert!(ControlType::from_str("SYN:0x0").is_err());
+        assert!(ControlType::from_str("KEY:twelve").is_err());
+    }
+
     #[test]
     fn test_serialization() {
         use serde_yaml;
@@ -992,6 +1297,15 @@ mod tests {
             btn_set.len(),
             "Duplicate in button iterator"
         );
+
+        // Check keyboard key controls
+        let key_controls: Vec<ControlType> = ControlType::iter_key().collect();
+        let key_set: HashSet<_> = key_controls.iter().collect();
+        assert_eq!(
+            key_controls.len(),
+            key_set.len(),
+            "Duplicate in key iterator"
+        );
     }
 
     #[test]
@@ -3,16 +3,18 @@ use chrono::{DateTime, Utc};
 use log::info;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value as YamlValue;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicUsize;
-use std::sync::Mutex;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use yaml_merge_keys::merge_keys_serde;
 
 use regex::Regex;
 
-use crate::common::NumInterval;
+use crate::common::{ControlType, NumInterval};
 use crate::schemas::*;
 
 pub const APP_VERSION_STR: &str = "3.3";
@@ -29,6 +31,8 @@ pub const APP_DEFAULT_NO_HOT_RELOAD: &str = "false";
 pub const APP_DEFAULT_LATENCY_STR: &str = "normal";
 pub const APP_DEFAULT_MAX_LOG_LEVEL: &str = "debug";
 const INCLUDE_YAML_KEY: &str = "_include";
+/// Device key of the `internal_controls` in mapping sources, destinations and references.
+pub(crate) const INTERNAL_DEVICE_KEY: &str = "internal";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
@@ -39,7 +43,24 @@ pub(crate) struct Config {
     #[serde(default)]
     pub(crate) mouse_devices: Option<HashMap<String, MouseDevice>>,
     #[serde(default)]
+    pub(crate) keyboard_devices: Option<HashMap<String, KeyboardDevice>>,
+    #[serde(default)]
+    pub(crate) gamepad_devices: Option<HashMap<String, GamepadDevice>>,
+    #[serde(default)]
+    pub(crate) touch_devices: Option<HashMap<String, TouchDevice>>,
+    #[serde(default)]
+    pub(crate) osc_sources: Option<HashMap<String, OscSource>>,
+    #[serde(default)]
+    pub(crate) json_sources: Option<HashMap<String, JsonSource>>,
+    #[serde(default)]
+    pub(crate) generators: Option<HashMap<String, GeneratorSource>>,
+    #[serde(default)]
+    pub(crate) internal_controls: Option<HashMap<String, InternalControl>>,
+    #[serde(default)]
     pub(crate) virtual_joysticks: HashMap<String, VirtualJoystick>,
+    /// MIDI ports mappings send to, addressed like virtual joysticks.
+    #[serde(default)]
+    pub(crate) midi_outputs: Option<HashMap<String, MidiOutputDevice>>,
     #[serde(default)]
     pub(crate) mappings: Vec<Mapping>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -56,7 +77,15 @@ impl Default for Config {
             global: GlobalSettings::default(),
             midi_devices: None,
             mouse_devices: None,
+            keyboard_devices: None,
+            gamepad_devices: None,
+            touch_devices: None,
+            osc_sources: None,
+            json_sources: None,
+            generators: None,
+            internal_controls: None,
             virtual_joysticks: HashMap::new(),
+            midi_outputs: None,
             mappings: Vec::new(),
             created_by: Some(APP_LONG_NAME.to_string()),
             created_date: Some(Utc::now()),
@@ -89,23 +118,127 @@ pub(crate) struct ResolvedMapping {
 #[derive(Debug, Clone)]
 pub(crate) struct ResolvedMappingSource {
     pub(crate) device_key: String,
+    /// Physical device selected from a source device entry with `instances: true`.
+    pub(crate) instance: Option<DeviceInstanceSelector>,
     pub(crate) control_key: String,
     pub(crate) control: ControlReference,
 }
 
+/// Selects one of the devices matched by an entry with `instances: true`,
+/// as in `device: left_hand_mouse[1]` or `device: "left_hand_mouse[usb-0000:00:14.0-2/input0]"`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DeviceInstanceSelector {
+    /// Instance number, devices present on engine start are numbered from 0 in order of
+    /// their ids, devices plugged in later get the next numbers.
+    Index(usize),
+    /// Device phys path (or node path for devices not reporting one).
+    Id(String),
+}
+
+impl DeviceInstanceSelector {
+    pub(crate) fn matches(&self, index: usize, id: &str) -> bool {
+        match self {
+            DeviceInstanceSelector::Index(i) => *i == index,
+            DeviceInstanceSelector::Id(s) => s == id,
+        }
+    }
+}
+
+/// Whether a mapping source takes the events of a device instance (number and id).
+/// NB: a mapping without a selector takes only the first instance (number 0),
+/// NB: otherwise all the instances would drive the same destination in turns.
+pub(crate) fn instance_selected(
+    selector: Option<&DeviceInstanceSelector>,
+    instance: Option<(usize, &str)>,
+) -> bool {
+    match (selector, instance) {
+        (None, None) => true,
+        (None, Some((index, _))) => index == 0,
+        (Some(selector), Some((index, id))) => selector.matches(index, id),
+        (Some(_), None) => false,
+    }
+}
+
+/// The control type of a shorthand naming it directly rather than a predefine,
+/// e.g. `BTN_TRIGGER_HAPPY5` or a raw code like `KEY:0x2c0`.
+fn shorthand_control_type(name: &str) -> Result<Option<ControlType>> {
+    if name.contains(':') {
+        // NB: surely meant as a raw code, so tell what's wrong with it.
+        return ControlType::from_str(name).map(Some);
+    }
+    Ok(ControlType::from_str(name).ok().filter(|control_type| {
+        control_type.is_absolute()
+            || control_type.is_relative()
+            || control_type.is_button()
+            || control_type.is_key()
+    }))
+}
+
+/// Splits a mapping source device reference into the device key and the instance selector.
+pub(crate) fn split_device_instance(device: &str) -> (&str, Option<DeviceInstanceSelector>) {
+    let Some((key, selector)) = device
+        .strip_suffix(']')
+        .and_then(|device| device.split_once('['))
+    else {
+        return (device, None);
+    };
+    let selector = match selector.parse::<usize>() {
+        Ok(index) => DeviceInstanceSelector::Index(index),
+        Err(_) => DeviceInstanceSelector::Id(selector.to_string()),
+    };
+    (key, Some(selector))
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum ControlReference {
     Midi(MidiControl),
     Mouse(ResolvedMouseControl),
+    Keyboard(ResolvedKeyboardControl),
+    Gamepad(ResolvedGamepadControl),
+    Touch(ResolvedTouchControl),
+    Osc(ResolvedOscControl),
+    Json(ResolvedJsonControl),
+    Generator(ResolvedGeneratorControl),
+    Internal(ResolvedInternalControl),
 }
 
 #[derive(Debug)]
 pub(crate) struct ResolvedMappingDestination {
     pub(crate) device_key: String,
-    #[allow(dead_code)]
-    pub(crate) joystick: VirtualJoystick,
     pub(crate) control_key: String,
-    pub(crate) control: ResolvedJoystickControl,
+    pub(crate) control: DestinationReference,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum DestinationReference {
+    Joystick(ResolvedJoystickControl),
+    Internal(ResolvedInternalControl),
+    Hat {
+        hat: ResolvedHatSwitch,
+        direction: HatDirection,
+    },
+    MidiOutput(ResolvedMidiOutputControl),
+}
+
+impl DestinationReference {
+    pub(crate) fn range(&self) -> NumInterval<i32> {
+        match self {
+            DestinationReference::Joystick(control) => control.range,
+            DestinationReference::Internal(control) => control.range,
+            // NB: a hat direction is pressed or not, like a button.
+            DestinationReference::Hat { .. } => NumInterval::new(0, 1),
+            DestinationReference::MidiOutput(control) => control.range,
+        }
+    }
+
+    pub(crate) fn idle_tick_enabled_flag(&self) -> &AtomicBool {
+        match self {
+            DestinationReference::Joystick(control) => &control.idle_tick_enabled_flag,
+            DestinationReference::Internal(control) => &control.idle_tick_enabled_flag,
+            DestinationReference::Hat { hat, .. } => &hat.idle_tick_enabled_flag,
+            DestinationReference::MidiOutput(control) => &control.idle_tick_enabled_flag,
+        }
+    }
 }
 
 impl PartialEq for ResolvedMappingSource {
@@ -161,7 +294,19 @@ pub(crate) struct ConfigManager {
     mappings: Vec<ResolvedMapping>,
     resolved_midi_devices: BTreeMap<String, crate::schemas::ResolvedMidiDevice>,
     resolved_mouse_devices: BTreeMap<String, crate::schemas::ResolvedMouseDevice>,
+    resolved_keyboard_devices: BTreeMap<String, crate::schemas::ResolvedKeyboardDevice>,
+    resolved_gamepad_devices: BTreeMap<String, crate::schemas::ResolvedGamepadDevice>,
+    resolved_touch_devices: BTreeMap<String, crate::schemas::ResolvedTouchDevice>,
+    resolved_osc_sources: BTreeMap<String, crate::schemas::ResolvedOscSource>,
+    resolved_json_sources: BTreeMap<String, crate::schemas::ResolvedJsonSource>,
+    resolved_generators: BTreeMap<String, crate::schemas::ResolvedGeneratorSource>,
+    resolved_internal_controls: BTreeMap<String, crate::schemas::ResolvedInternalControl>,
+    /// Internal controls ordered so that each one comes after the controls it's computed from.
+    internal_control_order: Vec<String>,
     resolved_virtual_joysticks: BTreeMap<String, crate::schemas::ResolvedVirtualJoystick>,
+    resolved_midi_outputs: BTreeMap<String, crate::schemas::ResolvedMidiOutputDevice>,
+    /// Feedback of the enabled MIDI devices, by device key.
+    resolved_midi_feedback: BTreeMap<String, crate::schemas::ResolvedMidiFeedback>,
     debug: bool,
 }
 
@@ -199,7 +344,17 @@ impl ConfigManager {
             mappings: Vec::new(),
             resolved_midi_devices: BTreeMap::new(),
             resolved_mouse_devices: BTreeMap::new(),
+            resolved_keyboard_devices: BTreeMap::new(),
+            resolved_gamepad_devices: BTreeMap::new(),
+            resolved_touch_devices: BTreeMap::new(),
+            resolved_osc_sources: BTreeMap::new(),
+            resolved_json_sources: BTreeMap::new(),
+            resolved_generators: BTreeMap::new(),
+            resolved_internal_controls: BTreeMap::new(),
+            internal_control_order: Vec::new(),
             resolved_virtual_joysticks: BTreeMap::new(),
+            resolved_midi_outputs: BTreeMap::new(),
+            resolved_midi_feedback: BTreeMap::new(),
             debug,
         })
     }
@@ -394,6 +549,9 @@ impl ConfigManager {
         self.resolve_all_devices()?;
 
         self.mappings = self.resolve_mappings()?;
+        self.internal_control_order = self
+            .resolve_internal_control_order()
+            .context("Failed to order internal controls")?;
         Ok(())
     }
 
@@ -416,7 +574,20 @@ impl ConfigManager {
     fn resolve_all_devices(&mut self) -> Result<()> {
         self.resolved_midi_devices.clear();
         self.resolved_mouse_devices.clear();
+        self.resolved_keyboard_devices.clear();
+        self.resolved_gamepad_devices.clear();
+        self.resolved_touch_devices.clear();
+        self.resolved_osc_sources.clear();
+        self.resolved_json_sources.clear();
+        self.resolved_generators.clear();
+        self.resolved_internal_controls.clear();
         self.resolved_virtual_joysticks.clear();
+        self.resolved_midi_outputs.clear();
+        self.resolved_midi_feedback.clear();
+
+        if self.config.internal_controls.is_some() {
+            self.check_internal_device_key_is_free()?;
+        }
 
         if let Some(midi_devices) = &self.config.midi_devices {
             for (device_key, device) in midi_devices {
@@ -438,6 +609,56 @@ impl ConfigManager {
             }
         }
 
+        if let Some(keyboard_devices) = &self.config.keyboard_devices {
+            for (device_key, device) in keyboard_devices {
+                let resolved = self.resolve_keyboard_device(device).with_context(|| {
+                    format!("Failed to resolve keyboard device '{}'", device_key)
+                })?;
+                self.resolved_keyboard_devices
+                    .insert(device_key.clone(), resolved);
+            }
+        }
+
+        if let Some(gamepad_devices) = &self.config.gamepad_devices {
+            for (device_key, device) in gamepad_devices {
+                let resolved = self.resolve_gamepad_device(device).with_context(|| {
+                    format!("Failed to resolve gamepad device '{}'", device_key)
+                })?;
+                self.resolved_gamepad_devices
+                    .insert(device_key.clone(), resolved);
+            }
+        }
+
+        if let Some(touch_devices) = &self.config.touch_devices {
+            for (device_key, device) in touch_devices {
+                let resolved = self
+                    .resolve_touch_device(device)
+                    .with_context(|| format!("Failed to resolve touch device '{}'", device_key))?;
+                self.resolved_touch_devices
+                    .insert(device_key.clone(), resolved);
+            }
+        }
+
+        if let Some(osc_sources) = &self.config.osc_sources {
+            for (source_key, source) in osc_sources {
+                let resolved = self
+                    .resolve_osc_source(source)
+                    .with_context(|| format!("Failed to resolve OSC source '{}'", source_key))?;
+                self.resolved_osc_sources
+                    .insert(source_key.clone(), resolved);
+            }
+        }
+
+        if let Some(json_sources) = &self.config.json_sources {
+            for (source_key, source) in json_sources {
+                let resolved = self
+                    .resolve_json_source(source)
+                    .with_context(|| format!("Failed to resolve JSON source '{}'", source_key))?;
+                self.resolved_json_sources
+                    .insert(source_key.clone(), resolved);
+            }
+        }
+
         for (joystick_key, joystick) in &self.config.virtual_joysticks {
             let resolved = self.resolve_virtual_joystick(joystick).with_context(|| {
                 format!("Failed to resolve virtual joystick '{}'", joystick_key)
@@ -446,6 +667,64 @@ impl ConfigManager {
                 .insert(joystick_key.clone(), resolved);
         }
 
+        if let Some(midi_outputs) = &self.config.midi_outputs {
+            for (device_key, device) in midi_outputs {
+                if self.config.virtual_joysticks.contains_key(device_key)
+                    || device_key == INTERNAL_DEVICE_KEY
+                {
+                    bail!(
+                        "MIDI output '{}' has the key of a virtual joystick or `{}`",
+                        device_key,
+                        INTERNAL_DEVICE_KEY
+                    );
+                }
+                let resolved = self
+                    .resolve_midi_output(device)
+                    .with_context(|| format!("Failed to resolve MIDI output '{}'", device_key))?;
+                self.resolved_midi_outputs
+                    .insert(device_key.clone(), resolved);
+            }
+        }
+
+        if let Some(internal_controls) = &self.config.internal_controls {
+            for (control_key, control) in internal_controls {
+                let resolved = self.resolve_internal_control(control).with_context(|| {
+                    format!("Failed to resolve internal control '{}'", control_key)
+                })?;
+                self.resolved_internal_controls
+                    .insert(control_key.clone(), resolved);
+            }
+        }
+
+        // NB: after the virtual joysticks and internal controls, parameters can reference their controls.
+        if let Some(generators) = &self.config.generators {
+            for (source_key, source) in generators {
+                let resolved = self
+                    .resolve_generator_source(source)
+                    .with_context(|| format!("Failed to resolve generator '{}'", source_key))?;
+                self.resolved_generators
+                    .insert(source_key.clone(), resolved);
+            }
+        }
+
+        // NB: same for the feedback sources.
+        if let Some(midi_devices) = &self.config.midi_devices {
+            for (device_key, device) in midi_devices {
+                let Some(feedback) = &device.feedback else {
+                    continue;
+                };
+                let resolved = self
+                    .resolve_midi_feedback(device_key, device, feedback)
+                    .with_context(|| {
+                        format!("Failed to resolve feedback of MIDI device '{}'", device_key)
+                    })?;
+                if device.enabled && feedback.enabled {
+                    self.resolved_midi_feedback
+                        .insert(device_key.clone(), resolved);
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -457,12 +736,16 @@ impl ConfigManager {
             }
             let source = self.resolve_source(&mapping.source)?;
             let destination = self.resolve_destination(&mapping.destination)?;
+            let counts_per_cm = self
+                .resolved_mouse_devices
+                .get(&source.device_key)
+                .and_then(|device| device.counts_per_cm);
             let transformation = self
-                .resolve_transformation(&mapping.transformation)
+                .resolve_transformation(&mapping.transformation, counts_per_cm)
                 .with_context(|| {
                     format!(
-                        "Failed to resolve transformation for mapping {:?}",
-                        mapping.name
+                        "Failed to resolve transformation for mapping {:?} of source device '{}'",
+                        mapping.name, source.device_key
                     )
                 })?;
 
@@ -478,13 +761,42 @@ impl ConfigManager {
         Ok(resolved)
     }
 
+    /// Orders the internal controls so that each one is computed after the internal controls its
+    /// mappings read, as their source or through references.
+    fn resolve_internal_control_order(&self) -> Result<Vec<String>> {
+        let mut dependencies: BTreeMap<&str, BTreeSet<&str>> = self
+            .resolved_internal_controls
+            .keys()
+            .map(|control_key| (control_key.as_str(), BTreeSet::new()))
+            .collect();
+        for mapping in &self.mappings {
+            let DestinationReference::Internal(_) = mapping.destination.control else {
+                continue;
+            };
+            let inputs = dependencies
+                .entry(mapping.destination.control_key.as_str())
+                .or_default();
+            for (device, control) in mapping_input_controls(mapping) {
+                if device == INTERNAL_DEVICE_KEY {
+                    inputs.insert(control);
+                }
+            }
+        }
+        Ok(dependency_order(&dependencies)?
+            .into_iter()
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// `counts_per_cm` of the source mouse converts physical distances to input counts.
     fn resolve_transformation(
         &self,
         transformation: &Transformation,
+        counts_per_cm: Option<f32>,
     ) -> Result<ResolvedTransformation> {
         let mut resolved_steps = Vec::new();
         for step in transformation {
-            resolved_steps.push(self.resolve_transformation_step(step)?);
+            resolved_steps.push(self.resolve_transformation_step(step, counts_per_cm)?);
         }
         Ok(resolved_steps)
     }
@@ -492,6 +804,7 @@ impl ConfigManager {
     fn resolve_transformation_step(
         &self,
         step: &TransformationStep,
+        counts_per_cm: Option<f32>,
     ) -> Result<ResolvedTransformationStep> {
         static CURRENT_STATE_ID: AtomicUsize = AtomicUsize::new(0);
         match step {
@@ -499,8 +812,14 @@ impl ConfigManager {
                 runtime_state_id: CURRENT_STATE_ID
                     .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
                 steering: ResolvedSteeringTransform {
-                    counts_to_lock: steering.counts_to_lock,
-                    deadzone_counts: steering.deadzone_counts,
+                    counts_to_lock: steering
+                        .counts_to_lock
+                        .to_counts(counts_per_cm)
+                        .context("Invalid steering `counts_to_lock`")?,
+                    deadzone_counts: steering
+                        .deadzone_counts
+                        .to_counts(counts_per_cm)
+                        .context("Invalid steering `deadzone_counts`")?,
                     smoothing_alpha: steering.smoothing_alpha,
                     auto_center_halflife: steering.auto_center_halflife,
                     hold_factor: self.resolve_hold_factor(&steering.hold_factor)?,
@@ -532,7 +851,23 @@ impl ConfigManager {
                 Ok(ResolvedTransformationStep::Integrate {
                     runtime_state_id: CURRENT_STATE_ID
                         .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
-                    integrate: integrate.clone(),
+                    integrate: ResolvedIntegrateTransform {
+                        range: match integrate.range {
+                            Some(range) => NumInterval::new(
+                                range
+                                    .from
+                                    .to_counts(counts_per_cm)
+                                    .context("Invalid integrate `range` start")?,
+                                range
+                                    .to
+                                    .to_counts(counts_per_cm)
+                                    .context("Invalid integrate `range` end")?,
+                            ),
+                            None => NumInterval::new(0.0, 750.0),
+                        },
+                        deadzone_norm: integrate.deadzone_norm,
+                        smoothing_alpha: integrate.smoothing_alpha,
+                    },
                 })
             }
             // TransformationStep::Curve { curve } => Ok(ResolvedTransformationStep::Curve {
@@ -630,20 +965,22 @@ impl ConfigManager {
             ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
         };
 
+        let mut expanded = base_control;
         if let Some(predef_name) = predefined_name {
             if let Some(predef) = self.predefines.midi_controls.get(predef_name) {
-                let mut expanded = base_control;
                 // Fill in missing fields from predefined
                 if expanded.midi_message.is_none() {
                     expanded.midi_message = Some(predef.midi_message.clone());
                 }
+                if expanded.encoding.is_none() {
+                    expanded.encoding = predef.encoding;
+                }
                 if expanded.range.is_none() {
                     expanded.range = Some(predef.range);
                 }
                 if expanded.description.is_none() {
                     expanded.description = Some(predef.description.clone());
                 }
-                return Ok(expanded);
             } else {
                 bail!(
                     "Unknown predefined MIDI control '{}'. Available: {:?}",
@@ -653,13 +990,136 @@ impl ConfigManager {
             }
         }
 
-        if base_control.midi_message.is_none() {
+        let Some(midi_message) = &expanded.midi_message else {
             bail!(
                 "MIDI control has neither 'midi_message' nor valid 'predefined_type': {:?}",
                 entry
             );
+        };
+        Self::check_midi_message_numbers(midi_message)?;
+        Self::check_sysex_message(midi_message)?;
+        if let Some(encoding) = expanded.encoding {
+            if !matches!(midi_message.msg_type, MidiMessageType::ControlChange) {
+                bail!(
+                    "MIDI control 'encoding: {:?}' only applies to control_change messages",
+                    encoding
+                );
+            }
+        }
+        if let Some(modulation) = expanded.velocity_modulation {
+            if !matches!(
+                midi_message.msg_type,
+                MidiMessageType::Note | MidiMessageType::NoteOn
+            ) {
+                bail!(
+                    "MIDI control 'velocity_modulation: {:?}' only applies to note and note_on messages",
+                    modulation
+                );
+            }
+        }
+        if let Some(group) = expanded.note_group {
+            if !matches!(midi_message.msg_type, MidiMessageType::Note) {
+                bail!(
+                    "MIDI control 'note_group: {:?}' only applies to note messages",
+                    group
+                );
+            }
+            if expanded.velocity_modulation.is_some() {
+                bail!("MIDI control can't have both 'note_group' and 'velocity_modulation'");
+            }
+        }
+        if expanded.range.is_none() {
+            let number = midi_message.number.as_ref();
+            if let Some(group) = expanded.note_group {
+                expanded.range = match group {
+                    MidiNoteGroup::Lowest | MidiNoteGroup::Highest | MidiNoteGroup::Last => number
+                        .and_then(MidiNumber::bounds)
+                        .filter(|(low, high)| low < high)
+                        .map(|(low, high)| NumInterval::new(low as i32, high as i32)),
+                    // Without a note count (any note) up to ten fingers.
+                    MidiNoteGroup::Count => Some(NumInterval::new(
+                        0,
+                        number.and_then(MidiNumber::count).unwrap_or(10) as i32,
+                    )),
+                    MidiNoteGroup::AnyHeld => None,
+                };
+            } else if expanded.encoding.is_some() {
+                // Deltas are centered on 0 like the relative mouse axes.
+                expanded.range = Some(NumInterval::new(-64, 64));
+            } else if matches!(midi_message.msg_type, MidiMessageType::ClockTempo) {
+                expanded.range = Some(NumInterval::new(20, 300));
+            } else if midi_message.msg_type.is_14bit() {
+                expanded.range = Some(NumInterval::new(0, crate::midi::MAX_14BIT_VALUE as i32));
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn check_sysex_message(midi_message: &crate::schemas::MidiMessage) -> Result<()> {
+        if midi_message.msg_type.is_system()
+            && (midi_message.number.is_some() || !matches!(midi_message.channel, MidiChannel::Any))
+        {
+            bail!(
+                "MIDI {:?} is a system message, it can't have 'number' or 'channel'",
+                midi_message.msg_type
+            );
+        }
+        if !matches!(midi_message.msg_type, MidiMessageType::Sysex) {
+            if midi_message.pattern.is_some() || midi_message.value_offset.is_some() {
+                bail!(
+                    "MIDI {:?} message can't have 'pattern' or 'value_offset', those are for sysex",
+                    midi_message.msg_type
+                );
+            }
+            return Ok(());
+        }
+        let Some(pattern) = &midi_message.pattern else {
+            bail!("MIDI sysex message requires a 'pattern', e.g. \"F0 7F ?? 06 ?? F7\"");
+        };
+        if let Some(offset) = midi_message.value_offset {
+            if offset == 0 || (!pattern.any_tail && offset >= pattern.bytes.len()) {
+                bail!(
+                    "MIDI sysex 'value_offset: {}' is outside of pattern '{}'",
+                    offset,
+                    pattern
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn check_midi_message_numbers(midi_message: &crate::schemas::MidiMessage) -> Result<()> {
+        let max_number = match midi_message.msg_type {
+            MidiMessageType::ControlChange14bit => 31,
+            MidiMessageType::Nrpn | MidiMessageType::Rpn => crate::midi::MAX_14BIT_VALUE,
+            _ => 127,
+        };
+        let numbers = match &midi_message.number {
+            Some(MidiNumber::Single(number)) => vec![*number],
+            Some(MidiNumber::Multiple(numbers)) => numbers.clone(),
+            Some(MidiNumber::Special(special)) => {
+                if special.eq_ignore_ascii_case("any") {
+                    vec![]
+                } else if let Some((_, high)) = MidiNumber::parse_range(special) {
+                    vec![high]
+                } else {
+                    bail!(
+                        "Invalid MIDI number '{}', expected a number, a list, 'any' or a range like \"48-59\"",
+                        special
+                    );
+                }
+            }
+            None => vec![],
+        };
+        if let Some(number) = numbers.iter().find(|number| **number > max_number) {
+            bail!(
+                "MIDI {:?} number {} is out of range 0-{}",
+                midi_message.msg_type,
+                number,
+                max_number
+            );
         }
-        Ok(base_control)
+        Ok(())
     }
 
     fn expand_mouse_control(
@@ -686,6 +1146,10 @@ impl ConfigManager {
                 if description.is_none() {
                     description = Some(predef.description.clone());
                 }
+            } else if let Some(named_type) = shorthand_control_type(predef_name)? {
+                if control_type.is_none() {
+                    control_type = Some(named_type);
+                }
             } else {
                 bail!(
                     "Unknown predefined mouse control '{}'. Available: {:?}",
@@ -698,6 +1162,9 @@ impl ConfigManager {
         let control_type = control_type.ok_or_else(|| {
             anyhow::anyhow!("Mouse control missing 'type' and no valid predefined_type")
         })?;
+        if range.is_none() && control_type.is_button() {
+            range = Some(NumInterval::new(0, 1));
+        }
         let range = range.ok_or_else(|| {
             anyhow::anyhow!("Mouse control missing 'range' and no valid predefined_type")
         })?;
@@ -709,136 +1176,1201 @@ impl ConfigManager {
         })
     }
 
-    fn expand_joystick_control(
+    fn expand_keyboard_control(
         &self,
-        entry: &ControlEntry<JoystickControl>,
-    ) -> Result<ResolvedJoystickControl> {
+        entry: &ControlEntry<KeyboardControl>,
+    ) -> Result<ResolvedKeyboardControl> {
         let (predefined_name, base_control) = match entry {
-            ControlEntry::Shorthand(name) => (Some(name.as_str()), JoystickControl::default()),
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), KeyboardControl::default()),
             ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
         };
 
         let mut control_type = base_control.r#type;
         let mut range = base_control.range;
-        let mut properties = base_control.properties;
-        let initial_value = base_control.initial_value;
+        let mut repeat = base_control.repeat;
+        let mut description = base_control.description;
 
         if let Some(predef_name) = predefined_name {
-            if let Some(predef) = self.predefines.joystick_controls.get(predef_name) {
+            if let Some(predef) = self.predefines.keyboard_controls.get(predef_name) {
                 if control_type.is_none() {
                     control_type = Some(predef.r#type);
                 }
                 if range.is_none() {
                     range = Some(predef.range);
                 }
-                if properties.is_none() {
-                    properties = predef.properties.clone();
+                if repeat.is_none() {
+                    repeat = Some(predef.repeat);
+                }
+                if description.is_none() {
+                    description = Some(predef.description.clone());
+                }
+            } else if let Some(named_type) = shorthand_control_type(predef_name)? {
+                if control_type.is_none() {
+                    control_type = Some(named_type);
                 }
             } else {
                 bail!(
-                    "Unknown predefined joystick control '{}'. Available: {:?}",
+                    "Unknown predefined keyboard control '{}'. Available: {:?}",
                     predef_name,
-                    self.predefines.joystick_controls.keys().collect::<Vec<_>>()
+                    self.predefines.keyboard_controls.keys().collect::<Vec<_>>()
                 );
             }
         }
 
         let control_type = control_type.ok_or_else(|| {
-            anyhow::anyhow!("Joystick control missing 'type' and no valid predefined_type")
+            anyhow::anyhow!("Keyboard control missing 'type' and no valid predefined_type")
         })?;
+        if !control_type.is_key() && !control_type.is_button() {
+            bail!(
+                "Keyboard control type {} is not a key or button",
+                control_type
+            );
+        }
+        if range.is_none() {
+            range = Some(NumInterval::new(0, 1));
+        }
         let range = range.ok_or_else(|| {
-            anyhow::anyhow!("Joystick control missing 'range' and no valid predefined_type")
+            anyhow::anyhow!("Keyboard control missing 'range' and no valid predefined_type")
         })?;
 
-        Ok(ResolvedJoystickControl {
+        Ok(ResolvedKeyboardControl {
             r#type: control_type,
             range,
-            properties,
-            initial_value,
-            idle_tick_enabled_flag: base_control.idle_tick_enabled_flag,
+            repeat: repeat.unwrap_or(false),
+            _description: description,
         })
     }
 
-    pub(crate) fn resolve_virtual_joystick(
+    fn expand_gamepad_control(
         &self,
-        joystick: &VirtualJoystick,
-    ) -> Result<ResolvedVirtualJoystick> {
-        let mut resolved_controls = HashMap::new();
-
-        for (control_name, control_entry) in &joystick.controls {
-            let resolved_control = self
-                .expand_joystick_control(control_entry)
-                .with_context(|| format!("Failed to expand joystick control '{}'", control_name))?;
-            resolved_controls.insert(control_name.clone(), resolved_control);
-        }
-
-        Ok(ResolvedVirtualJoystick {
-            enabled: joystick.enabled.unwrap_or(true),
-            persistent: joystick
-                .persistent
-                .unwrap_or(self.config.global.persistent_joysticks),
-            name: joystick.name.clone(),
-            properties: joystick.properties.clone(),
-            controls: resolved_controls,
-            force_feedback: joystick.force_feedback.clone(),
-        })
-    }
+        entry: &ControlEntry<GamepadControl>,
+    ) -> Result<ResolvedGamepadControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), GamepadControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
 
-    pub(crate) fn resolve_midi_device(&self, device: &MidiDevice) -> Result<ResolvedMidiDevice> {
-        let mut resolved_controls = HashMap::new();
+        let mut control_type = base_control.r#type;
+        let mut range = base_control.range;
+        let mut description = base_control.description;
 
-        for (control_name, control_entry) in &device.controls {
-            let resolved_control = self
-                .expand_midi_control(control_entry)
-                .with_context(|| format!("Failed to expand MIDI control '{}'", control_name))?;
-            resolved_controls.insert(control_name.clone(), resolved_control);
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.gamepad_controls.get(predef_name) {
+                if control_type.is_none() {
+                    control_type = Some(predef.r#type);
+                }
+                if range.is_none() {
+                    range = predef.range;
+                }
+                if description.is_none() {
+                    description = Some(predef.description.clone());
+                }
+            } else if let Some(named_type) = shorthand_control_type(predef_name)? {
+                if control_type.is_none() {
+                    control_type = Some(named_type);
+                }
+            } else {
+                bail!(
+                    "Unknown predefined gamepad control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines.gamepad_controls.keys().collect::<Vec<_>>()
+                );
+            }
         }
 
-        Ok(ResolvedMidiDevice {
-            enabled: device.enabled,
-            match_name_regex: device.match_name_regex.clone(),
-            controls: resolved_controls,
-        })
-    }
-
-    pub(crate) fn resolve_mouse_device(&self, device: &MouseDevice) -> Result<ResolvedMouseDevice> {
-        let mut resolved_controls = HashMap::new();
-
-        for (control_name, control_entry) in &device.controls {
-            let resolved_control = self
-                .expand_mouse_control(control_entry)
-                .with_context(|| format!("Failed to expand mouse control '{}'", control_name))?;
-            resolved_controls.insert(control_name.clone(), resolved_control);
+        let control_type = control_type.ok_or_else(|| {
+            anyhow::anyhow!("Gamepad control missing 'type' and no valid predefined_type")
+        })?;
+        if !control_type.is_absolute() && !control_type.is_button() && !control_type.is_key() {
+            bail!(
+                "Gamepad control type {} is not an absolute axis, button or key",
+                control_type
+            );
+        }
+        // Buttons and keys don't have AbsInfo, default them to the usual [0, 1].
+        if range.is_none() && !control_type.is_absolute() {
+            range = Some(NumInterval::new(0, 1));
         }
 
-        Ok(ResolvedMouseDevice {
-            enabled: device.enabled,
-            match_name_regex: device.match_name_regex.clone(),
-            controls: resolved_controls,
+        Ok(ResolvedGamepadControl {
+            r#type: control_type,
+            range,
+            _description: description,
         })
     }
 
-    fn resolve_source(&self, source: &MappingSource) -> Result<ResolvedMappingSource> {
-        if let Some(resolved_device) = self.resolved_midi_devices.get(&source.device) {
-            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
-                return Ok(ResolvedMappingSource {
-                    device_key: source.device.clone(),
-                    control_key: source.control.clone(),
-                    control: ControlReference::Midi(resolved_control.clone()),
-                });
-            }
-        }
+    fn expand_touch_control(
+        &self,
+        entry: &ControlEntry<TouchControl>,
+    ) -> Result<ResolvedTouchControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), TouchControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
 
-        if let Some(resolved_device) = self.resolved_mouse_devices.get(&source.device) {
-            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
-                return Ok(ResolvedMappingSource {
-                    device_key: source.device.clone(),
+        let mut control_type = base_control.r#type;
+        let mut finger = base_control.finger;
+        let mut fingers = base_control.fingers;
+        let mut range = base_control.range;
+        let mut description = base_control.description;
+
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.touch_controls.get(predef_name) {
+                if control_type.is_none() {
+                    control_type = Some(predef.r#type);
+                }
+                if finger.is_none() {
+                    finger = predef.finger;
+                }
+                if fingers.is_none() {
+                    fingers = predef.fingers;
+                }
+                if range.is_none() {
+                    range = predef.range;
+                }
+                if description.is_none() {
+                    description = Some(predef.description.clone());
+                }
+            } else {
+                bail!(
+                    "Unknown predefined touch control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines.touch_controls.keys().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        let control_type = control_type.ok_or_else(|| {
+            anyhow::anyhow!("Touch control missing 'type' and no valid predefined_type")
+        })?;
+        if finger == Some(0) {
+            bail!("Touch control 'finger' is 1-based, 0 is not a valid finger");
+        }
+        if let (Some(finger), Some(fingers)) = (finger, fingers) {
+            if finger > fingers {
+                bail!(
+                    "Touch control 'finger: {}' can never be present with 'fingers: {}'",
+                    finger,
+                    fingers
+                );
+            }
+        }
+        if range.is_none() && control_type == TouchControlType::Touching {
+            range = Some(NumInterval::new(0, 1));
+        }
+
+        Ok(ResolvedTouchControl {
+            r#type: control_type,
+            finger,
+            fingers,
+            range,
+            _description: description,
+        })
+    }
+
+    fn expand_osc_control(&self, entry: &ControlEntry<OscControl>) -> Result<ResolvedOscControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), OscControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
+
+        let mut address = base_control.address;
+        let mut argument = base_control.argument;
+        let mut range = base_control.range;
+        let mut description = base_control.description;
+
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.osc_controls.get(predef_name) {
+                if address.is_none() {
+                    address = predef.address.clone();
+                }
+                if argument.is_none() {
+                    argument = Some(predef.argument);
+                }
+                if range.is_none() {
+                    range = Some(predef.range);
+                }
+                if description.is_none() {
+                    description = Some(predef.description.clone());
+                }
+            } else {
+                bail!(
+                    "Unknown predefined OSC control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines.osc_controls.keys().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        let address = address.ok_or_else(|| {
+            anyhow::anyhow!("OSC control missing 'address' and no valid predefined_type")
+        })?;
+        if !address.starts_with('/') {
+            bail!(
+                "OSC control address pattern '{}' must start with '/'",
+                address
+            );
+        }
+        let range = range.unwrap_or(NumInterval::new(0.0, 1.0));
+        if range.from == range.to {
+            bail!("OSC control range {:?} is empty", range);
+        }
+
+        Ok(ResolvedOscControl {
+            address,
+            argument: argument.unwrap_or(0),
+            range,
+            _description: description,
+        })
+    }
+
+    fn expand_json_control(
+        &self,
+        control_name: &str,
+        entry: &ControlEntry<JsonControl>,
+    ) -> Result<ResolvedJsonControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), JsonControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
+
+        let mut range = base_control.range;
+        let mut description = base_control.description;
+
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.json_controls.get(predef_name) {
+                if range.is_none() {
+                    range = Some(predef.range);
+                }
+                if description.is_none() {
+                    description = Some(predef.description.clone());
+                }
+            } else {
+                bail!(
+                    "Unknown predefined JSON control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines.json_controls.keys().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        let range = range.unwrap_or(NumInterval::new(0.0, 1.0));
+        if range.from == range.to {
+            bail!("JSON control range {:?} is empty", range);
+        }
+
+        Ok(ResolvedJsonControl {
+            name: base_control
+                .name
+                .unwrap_or_else(|| control_name.to_string()),
+            range,
+            _description: description,
+        })
+    }
+
+    fn expand_joystick_control(
+        &self,
+        entry: &ControlEntry<JoystickControl>,
+    ) -> Result<ResolvedJoystickControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), JoystickControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
+
+        let mut control_type = base_control.r#type;
+        let mut range = base_control.range;
+        let mut properties = base_control.properties;
+        let initial_value = base_control.initial_value;
+
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.joystick_controls.get(predef_name) {
+                if control_type.is_none() {
+                    control_type = Some(predef.r#type);
+                }
+                if range.is_none() {
+                    range = Some(predef.range);
+                }
+                if properties.is_none() {
+                    properties = predef.properties.clone();
+                }
+            } else if let Some(named_type) = shorthand_control_type(predef_name)? {
+                if control_type.is_none() {
+                    control_type = Some(named_type);
+                }
+            } else {
+                bail!(
+                    "Unknown predefined joystick control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines.joystick_controls.keys().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        let control_type = control_type.ok_or_else(|| {
+            anyhow::anyhow!("Joystick control missing 'type' and no valid predefined_type")
+        })?;
+        let is_key_like = control_type.is_button() || control_type.is_key();
+        if is_key_like && range.is_none() {
+            range = Some(NumInterval::new(0, 1));
+        }
+        let range = range.ok_or_else(|| {
+            anyhow::anyhow!("Joystick control missing 'range' and no valid predefined_type")
+        })?;
+
+        let thresholds = match (base_control.press_threshold, base_control.release_threshold) {
+            (None, None) => None,
+            (None, Some(_)) => bail!("'release_threshold' needs a 'press_threshold'"),
+            (Some(press), release) => {
+                let release = release.unwrap_or(press);
+                if release > press {
+                    bail!(
+                        "'release_threshold' {} is above 'press_threshold' {}",
+                        release,
+                        press
+                    );
+                }
+                Some(KeyThresholds { press, release })
+            }
+        };
+        let repeat = match &base_control.repeat {
+            None => None,
+            Some(repeat) => {
+                let delay = Duration::try_from_secs_f32(repeat.delay).map_err(|_| {
+                    anyhow::anyhow!(
+                        "Key repeat 'delay' must be a non-negative number of seconds, got {}",
+                        repeat.delay
+                    )
+                })?;
+                if repeat.rate.is_nan() || repeat.rate <= 0.0 {
+                    bail!("Key repeat 'rate' must be positive, got {}", repeat.rate);
+                }
+                let interval = Duration::try_from_secs_f32(1.0 / repeat.rate)
+                    .map_err(|_| anyhow::anyhow!("Key repeat 'rate' {} is too low", repeat.rate))?;
+                Some(ResolvedKeyRepeat { delay, interval })
+            }
+        };
+        if !is_key_like && (thresholds.is_some() || base_control.repeat.is_some()) {
+            bail!(
+                "Only buttons and keys take 'press_threshold', 'release_threshold' \
+                and 'repeat', not {}",
+                control_type
+            );
+        }
+
+        Ok(ResolvedJoystickControl {
+            r#type: control_type,
+            range,
+            properties,
+            initial_value,
+            thresholds,
+            repeat,
+            idle_tick_enabled_flag: base_control.idle_tick_enabled_flag,
+        })
+    }
+
+    fn resolve_hat_switch(
+        &self,
+        hat: &HatSwitch,
+        controls: &HashMap<String, ResolvedJoystickControl>,
+        other_hats: &HashMap<String, ResolvedHatSwitch>,
+    ) -> Result<ResolvedHatSwitch> {
+        let (x, y) = match hat.hat {
+            0 => (ControlType::AbsHat0X, ControlType::AbsHat0Y),
+            1 => (ControlType::AbsHat1X, ControlType::AbsHat1Y),
+            2 => (ControlType::AbsHat2X, ControlType::AbsHat2Y),
+            3 => (ControlType::AbsHat3X, ControlType::AbsHat3Y),
+            n => bail!("Hat number {} is out of range 0 to 3", n),
+        };
+        for (control_name, control) in controls {
+            if control.r#type == x || control.r#type == y {
+                bail!(
+                    "Hat {} axes are taken by the control '{}' already",
+                    hat.hat,
+                    control_name
+                );
+            }
+        }
+        if let Some((other_name, _)) = other_hats.iter().find(|(_, other)| other.x == x) {
+            bail!(
+                "Hat {} is taken by the hat '{}' already",
+                hat.hat,
+                other_name
+            );
+        }
+        Ok(ResolvedHatSwitch {
+            x,
+            y,
+            socd: hat.socd,
+            diagonals: hat.diagonals,
+            idle_tick_enabled_flag: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub(crate) fn resolve_virtual_joystick(
+        &self,
+        joystick: &VirtualJoystick,
+    ) -> Result<ResolvedVirtualJoystick> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &joystick.controls {
+            let resolved_control = self
+                .expand_joystick_control(control_entry)
+                .with_context(|| format!("Failed to expand joystick control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        let mut resolved_hats: HashMap<String, ResolvedHatSwitch> = HashMap::new();
+        for (hat_name, hat) in &joystick.hats {
+            if resolved_controls.contains_key(hat_name) {
+                bail!("Hat switch '{}' has the name of a control", hat_name);
+            }
+            let resolved_hat = self
+                .resolve_hat_switch(hat, &resolved_controls, &resolved_hats)
+                .with_context(|| format!("Failed to resolve hat switch '{}'", hat_name))?;
+            resolved_hats.insert(hat_name.clone(), resolved_hat);
+        }
+
+        Ok(ResolvedVirtualJoystick {
+            enabled: joystick.enabled.unwrap_or(true),
+            persistent: joystick
+                .persistent
+                .unwrap_or(self.config.global.persistent_joysticks),
+            name: joystick.name.clone(),
+            properties: joystick.properties.clone(),
+            controls: resolved_controls,
+            hats: resolved_hats,
+            force_feedback: joystick.force_feedback.clone(),
+        })
+    }
+
+    fn resolve_midi_output_control(
+        &self,
+        control: &MidiOutputControl,
+    ) -> Result<ResolvedMidiOutputControl> {
+        if !(1..=16).contains(&control.channel) {
+            bail!("Channel {} is out of range 1 to 16", control.channel);
+        }
+        let number = match (control.msg_type, control.number) {
+            (MidiOutputMessageType::PitchWheel, None) => 0,
+            (MidiOutputMessageType::PitchWheel, Some(_)) => {
+                bail!("`number` is not used by pitch_wheel messages")
+            }
+            (msg_type, None) => bail!("{:?} messages need a `number`", msg_type),
+            (MidiOutputMessageType::ControlChange14bit, Some(number)) if number > 31 => {
+                bail!(
+                    "14 bit controller number {} is out of range 0 to 31, \
+                    its LSB is sent on controller number + 32",
+                    number
+                )
+            }
+            (_, Some(number)) if number > 127 => {
+                bail!("Number {} is out of range 0 to 127", number)
+            }
+            (_, Some(number)) => number,
+        };
+        let full_range = control.msg_type.value_range();
+        let range = control.range.unwrap_or(full_range);
+        if !full_range.contains_inclusive(range.from) || !full_range.contains_inclusive(range.to) {
+            bail!(
+                "Range {:?} exceeds the {:?} value range {:?}",
+                range,
+                control.msg_type,
+                full_range
+            );
+        }
+        Ok(ResolvedMidiOutputControl {
+            msg_type: control.msg_type,
+            channel: control.channel - 1,
+            number,
+            range,
+            idle_tick_enabled_flag: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    fn resolve_midi_output(&self, device: &MidiOutputDevice) -> Result<ResolvedMidiOutputDevice> {
+        match (&device.match_name_regex, &device.virtual_port) {
+            (Some(_), None) => {}
+            (None, Some(port_name)) if !port_name.trim().is_empty() => {}
+            (None, Some(_)) => bail!("`virtual_port` has an empty port name"),
+            _ => bail!("Exactly one of `match_name_regex` and `virtual_port` must be given"),
+        }
+
+        let mut resolved_controls = HashMap::new();
+        for (control_name, control) in &device.controls {
+            let resolved_control =
+                self.resolve_midi_output_control(control).with_context(|| {
+                    format!("Failed to resolve MIDI output control '{}'", control_name)
+                })?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedMidiOutputDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            virtual_port: device.virtual_port.clone(),
+            controls: resolved_controls,
+        })
+    }
+
+    fn resolve_midi_feedback(
+        &self,
+        device_key: &str,
+        device: &MidiDevice,
+        feedback: &MidiFeedback,
+    ) -> Result<ResolvedMidiFeedback> {
+        let (output_name_regex, port_match) = match &feedback.output_name_regex {
+            Some(regex) => (regex.clone(), ResolvedMidiPortMatch::default()),
+            None => (
+                device.match_name_regex.clone().context(
+                    "`output_name_regex` is needed for a device without `match_name_regex`",
+                )?,
+                ResolvedMidiPortMatch {
+                    client: device.match_client,
+                    port: device.match_port,
+                },
+            ),
+        };
+        if feedback.rate_limit.is_nan() || feedback.rate_limit <= 0.0 {
+            bail!(
+                "`rate_limit` must be a positive number of messages per second, got {}",
+                feedback.rate_limit
+            );
+        }
+        let min_interval =
+            Duration::try_from_secs_f32(1.0 / feedback.rate_limit).map_err(|_| {
+                anyhow::anyhow!(
+                    "`rate_limit` must be a positive number of messages per second, got {}",
+                    feedback.rate_limit
+                )
+            })?;
+        let touch_timeout = Duration::try_from_secs_f32(feedback.touch_timeout).map_err(|_| {
+            anyhow::anyhow!(
+                "`touch_timeout` must be a non-negative number of seconds, got {}",
+                feedback.touch_timeout
+            )
+        })?;
+
+        let resolved_device = self.resolved_midi_devices.get(device_key);
+        let mut resolved_controls = HashMap::new();
+        for (control_name, control) in &feedback.controls {
+            let resolved_control = self
+                .resolve_midi_feedback_control(device_key, control, resolved_device)
+                .with_context(|| {
+                    format!("Failed to resolve feedback control '{}'", control_name)
+                })?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedMidiFeedback {
+            output_name_regex,
+            port_match,
+            min_interval,
+            touch_timeout,
+            controls: resolved_controls,
+        })
+    }
+
+    fn resolve_midi_feedback_control(
+        &self,
+        device_key: &str,
+        control: &MidiFeedbackControl,
+        device: Option<&ResolvedMidiDevice>,
+    ) -> Result<ResolvedMidiFeedbackControl> {
+        let source = &control.source;
+        let source_range = if source.device == INTERNAL_DEVICE_KEY {
+            self.resolved_internal_controls
+                .get(&source.control)
+                .map(|control| control.range)
+        } else {
+            self.resolved_virtual_joysticks
+                .get(&source.device)
+                .and_then(|joystick| joystick.controls.get(&source.control))
+                .map(|control| control.range)
+        };
+        let Some(source_range) = source_range else {
+            bail!(
+                "Feedback source {}/{} is not a virtual joystick or internal control",
+                source.device,
+                source.control
+            );
+        };
+        let touch_controls = match &control.touch_controls {
+            Some(touch_controls) => {
+                for touch_control in touch_controls {
+                    if !device.is_some_and(|device| device.controls.contains_key(touch_control)) {
+                        bail!("Unknown touch control '{}'", touch_control);
+                    }
+                }
+                touch_controls.clone()
+            }
+            // The controls of the device moving the source, they would fight the feedback.
+            None => {
+                let mut touch_controls: Vec<String> = self
+                    .config
+                    .mappings
+                    .iter()
+                    .filter(|mapping| {
+                        mapping.enabled
+                            && mapping.source.device == device_key
+                            && mapping.destination.joystick == source.device
+                            && mapping.destination.control == source.control
+                    })
+                    .map(|mapping| mapping.source.control.clone())
+                    .collect();
+                touch_controls.sort();
+                touch_controls.dedup();
+                touch_controls
+            }
+        };
+        Ok(ResolvedMidiFeedbackControl {
+            source_device: source.device.clone(),
+            source_control: source.control.clone(),
+            source_range,
+            message: self.resolve_midi_output_control(&control.message)?,
+            touch_controls,
+        })
+    }
+
+    pub(crate) fn resolve_midi_device(&self, device: &MidiDevice) -> Result<ResolvedMidiDevice> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &device.controls {
+            let resolved_control = self
+                .expand_midi_control(control_entry)
+                .with_context(|| format!("Failed to expand MIDI control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedMidiDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            port_match: ResolvedMidiPortMatch {
+                client: device.match_client,
+                port: device.match_port,
+            },
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_mouse_device(&self, device: &MouseDevice) -> Result<ResolvedMouseDevice> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &device.controls {
+            let resolved_control = self
+                .expand_mouse_control(control_entry)
+                .with_context(|| format!("Failed to expand mouse control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedMouseDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            device_match: ResolvedEvdevDeviceMatch {
+                vendor_id: device.match_vendor_id,
+                product_id: device.match_product_id,
+                phys_regex: device.match_phys_regex.clone(),
+                uniq_regex: device.match_uniq_regex.clone(),
+                by_id_regex: device.match_by_id_regex.clone(),
+            },
+            instances: device.instances,
+            grab: device.grab,
+            counts_per_cm: match device.counts_per_cm {
+                Some(counts_per_cm) if !counts_per_cm.is_finite() || counts_per_cm <= 0.0 => {
+                    bail!(
+                        "counts_per_cm must be a positive number, got {}",
+                        counts_per_cm
+                    )
+                }
+                counts_per_cm => counts_per_cm,
+            },
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_keyboard_device(
+        &self,
+        device: &KeyboardDevice,
+    ) -> Result<ResolvedKeyboardDevice> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &device.controls {
+            let resolved_control = self
+                .expand_keyboard_control(control_entry)
+                .with_context(|| format!("Failed to expand keyboard control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedKeyboardDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            device_match: ResolvedEvdevDeviceMatch {
+                vendor_id: device.match_vendor_id,
+                product_id: device.match_product_id,
+                phys_regex: device.match_phys_regex.clone(),
+                uniq_regex: device.match_uniq_regex.clone(),
+                by_id_regex: device.match_by_id_regex.clone(),
+            },
+            grab: device.grab,
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_gamepad_device(
+        &self,
+        device: &GamepadDevice,
+    ) -> Result<ResolvedGamepadDevice> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &device.controls {
+            let resolved_control = self
+                .expand_gamepad_control(control_entry)
+                .with_context(|| format!("Failed to expand gamepad control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedGamepadDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            device_match: ResolvedEvdevDeviceMatch {
+                vendor_id: device.match_vendor_id,
+                product_id: device.match_product_id,
+                phys_regex: device.match_phys_regex.clone(),
+                uniq_regex: device.match_uniq_regex.clone(),
+                by_id_regex: device.match_by_id_regex.clone(),
+            },
+            grab: device.grab,
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_touch_device(&self, device: &TouchDevice) -> Result<ResolvedTouchDevice> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &device.controls {
+            let resolved_control = self
+                .expand_touch_control(control_entry)
+                .with_context(|| format!("Failed to expand touch control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedTouchDevice {
+            enabled: device.enabled,
+            match_name_regex: device.match_name_regex.clone(),
+            device_match: ResolvedEvdevDeviceMatch {
+                vendor_id: device.match_vendor_id,
+                product_id: device.match_product_id,
+                phys_regex: device.match_phys_regex.clone(),
+                uniq_regex: device.match_uniq_regex.clone(),
+                by_id_regex: device.match_by_id_regex.clone(),
+            },
+            grab: device.grab,
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_osc_source(&self, source: &OscSource) -> Result<ResolvedOscSource> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &source.controls {
+            let resolved_control = self
+                .expand_osc_control(control_entry)
+                .with_context(|| format!("Failed to expand OSC control '{}'", control_name))?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedOscSource {
+            enabled: source.enabled,
+            bind: source.bind,
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_json_source(&self, source: &JsonSource) -> Result<ResolvedJsonSource> {
+        let endpoint = match (&source.udp, &source.unix) {
+            (Some(bind), None) => JsonEndpoint::Udp(*bind),
+            (None, Some(path)) => JsonEndpoint::Unix(path.clone()),
+            _ => bail!("JSON source needs exactly one of 'udp' and 'unix'"),
+        };
+        let stats_interval = Duration::try_from_secs_f32(source.stats_interval)
+            .map_err(|_| anyhow::anyhow!("Invalid stats_interval {}", source.stats_interval))?;
+
+        let mut resolved_controls: HashMap<String, ResolvedJsonControl> = HashMap::new();
+        for (control_name, control_entry) in &source.controls {
+            let resolved_control = self
+                .expand_json_control(control_name, control_entry)
+                .with_context(|| format!("Failed to expand JSON control '{}'", control_name))?;
+            if let Some((other_name, _)) = resolved_controls
+                .iter()
+                .find(|(_, other)| other.name == resolved_control.name)
+            {
+                bail!(
+                    "JSON controls '{}' and '{}' both take messages for '{}'",
+                    other_name,
+                    control_name,
+                    resolved_control.name
+                );
+            }
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedJsonSource {
+            enabled: source.enabled,
+            endpoint,
+            stats_interval: (!stats_interval.is_zero()).then_some(stats_interval),
+            controls: resolved_controls,
+        })
+    }
+
+    pub(crate) fn resolve_generator_source(
+        &self,
+        source: &GeneratorSource,
+    ) -> Result<ResolvedGeneratorSource> {
+        let mut resolved_controls = HashMap::new();
+
+        for (control_name, control_entry) in &source.controls {
+            let resolved_control =
+                self.expand_generator_control(control_entry)
+                    .with_context(|| {
+                        format!("Failed to expand generator control '{}'", control_name)
+                    })?;
+            resolved_controls.insert(control_name.clone(), resolved_control);
+        }
+
+        Ok(ResolvedGeneratorSource {
+            enabled: source.enabled,
+            controls: resolved_controls,
+        })
+    }
+
+    fn expand_generator_control(
+        &self,
+        entry: &ControlEntry<GeneratorControl>,
+    ) -> Result<ResolvedGeneratorControl> {
+        let (predefined_name, base_control) = match entry {
+            ControlEntry::Shorthand(name) => (Some(name.as_str()), GeneratorControl::default()),
+            ControlEntry::Full(ctrl) => (ctrl.merge_from.as_deref(), ctrl.clone()),
+        };
+
+        let mut expanded = base_control;
+        if let Some(predef_name) = predefined_name {
+            if let Some(predef) = self.predefines.generator_controls.get(predef_name) {
+                if expanded.waveform.is_none() {
+                    expanded.waveform = Some(predef.waveform);
+                }
+                if expanded.frequency.is_none() {
+                    expanded.frequency = predef.frequency.map(GeneratorParameter::Value);
+                }
+                if expanded.amplitude.is_none() {
+                    expanded.amplitude = predef.amplitude.map(GeneratorParameter::Value);
+                }
+                if expanded.offset.is_none() {
+                    expanded.offset = predef.offset.map(GeneratorParameter::Value);
+                }
+                if expanded.steps.is_none() {
+                    expanded.steps = predef.steps.clone();
+                }
+                if expanded.range.is_none() {
+                    expanded.range = Some(predef.range);
+                }
+                if expanded.description.is_none() {
+                    expanded.description = Some(predef.description.clone());
+                }
+            } else {
+                bail!(
+                    "Unknown predefined generator control '{}'. Available: {:?}",
+                    predef_name,
+                    self.predefines
+                        .generator_controls
+                        .keys()
+                        .collect::<Vec<_>>()
+                );
+            }
+        }
+
+        let waveform = expanded.waveform.ok_or_else(|| {
+            anyhow::anyhow!("Generator control missing 'waveform' and no valid predefined_type")
+        })?;
+        let steps = expanded.steps.unwrap_or_default();
+        match (waveform, steps.is_empty()) {
+            (GeneratorWaveform::StepSequence, true) => {
+                bail!("Generator step_sequence needs at least one value in 'steps'")
+            }
+            (GeneratorWaveform::StepSequence, false) | (_, true) => {}
+            (_, false) => bail!("Generator 'steps' only apply to step_sequence"),
+        }
+        if let Some(GeneratorParameter::Value(frequency)) = expanded.frequency {
+            if !(frequency >= 0.0 && frequency.is_finite()) {
+                bail!("Generator frequency {} must be 0 or positive", frequency);
+            }
+        }
+        let range = expanded.range.unwrap_or(NumInterval::new(-1.0, 1.0));
+        if range.from == range.to {
+            bail!("Generator control range {:?} is empty", range);
+        }
+
+        Ok(ResolvedGeneratorControl {
+            waveform,
+            frequency: self.resolve_generator_parameter(expanded.frequency.as_ref(), 1.0)?,
+            amplitude: self.resolve_generator_parameter(expanded.amplitude.as_ref(), 1.0)?,
+            offset: self.resolve_generator_parameter(expanded.offset.as_ref(), 0.0)?,
+            steps,
+            seed: expanded.seed,
+            range,
+            _description: expanded.description,
+        })
+    }
+
+    fn resolve_generator_parameter(
+        &self,
+        parameter: Option<&GeneratorParameter>,
+        default: f32,
+    ) -> Result<ResolvedGeneratorParameter> {
+        match parameter {
+            None => Ok(ResolvedGeneratorParameter::Value(default)),
+            Some(GeneratorParameter::Value(v)) => Ok(ResolvedGeneratorParameter::Value(*v)),
+            Some(GeneratorParameter::Reference {
+                device,
+                control,
+                range,
+            }) => {
+                let Some(control_range) = self.lookup_control_range(device, control) else {
+                    bail!(
+                        "Generator parameter reference to {}/{} could not be resolved (device or control not found)",
+                        device,
+                        control
+                    );
+                };
+                let control_range = control_range.cast().with_context(|| {
+                    format!(
+                        "Generator parameter reference to {}/{} has a range {:?} not representable as float",
+                        device, control, control_range
+                    )
+                })?;
+                Ok(ResolvedGeneratorParameter::Reference {
+                    device: device.clone(),
+                    control: control.clone(),
+                    control_range,
+                    range: range.unwrap_or(NumInterval::new(0.0, 1.0)),
+                })
+            }
+        }
+    }
+
+    fn resolve_internal_control(
+        &self,
+        control: &InternalControl,
+    ) -> Result<ResolvedInternalControl> {
+        let range = control.range.unwrap_or(NumInterval::new(-32768, 32767));
+        if range.from >= range.to {
+            bail!("Internal control range {:?} is empty", range);
+        }
+        if !range.contains_inclusive(control.initial_value) {
+            bail!(
+                "Internal control initial value {} is out of its range {:?}",
+                control.initial_value,
+                range
+            );
+        }
+        Ok(ResolvedInternalControl {
+            range,
+            initial_value: control.initial_value,
+            idle_tick_enabled_flag: Default::default(),
+            _description: control.description.clone(),
+        })
+    }
+
+    /// `internal` is taken by the internal controls once they're used, other sections can't have it.
+    fn check_internal_device_key_is_free(&self) -> Result<()> {
+        let config = &self.config;
+        let sections = [
+            (
+                "midi_devices",
+                config
+                    .midi_devices
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "mouse_devices",
+                config
+                    .mouse_devices
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "keyboard_devices",
+                config
+                    .keyboard_devices
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "gamepad_devices",
+                config
+                    .gamepad_devices
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "touch_devices",
+                config
+                    .touch_devices
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "osc_sources",
+                config
+                    .osc_sources
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "json_sources",
+                config
+                    .json_sources
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "generators",
+                config
+                    .generators
+                    .as_ref()
+                    .is_some_and(|m| m.contains_key(INTERNAL_DEVICE_KEY)),
+            ),
+            (
+                "virtual_joysticks",
+                config.virtual_joysticks.contains_key(INTERNAL_DEVICE_KEY),
+            ),
+        ];
+        for (section, taken) in sections {
+            if taken {
+                bail!(
+                    "'{}' in {} is reserved for internal_controls, please rename it",
+                    INTERNAL_DEVICE_KEY,
+                    section
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_source(&self, source: &MappingSource) -> Result<ResolvedMappingSource> {
+        let (device_key, instance) = split_device_instance(&source.device);
+        if instance.is_some()
+            && !self
+                .resolved_mouse_devices
+                .get(device_key)
+                .is_some_and(|device| device.instances)
+        {
+            bail!(
+                "Source device '{}' selects an instance, but '{}' is not a mouse device \
+                with `instances: true`",
+                source.device,
+                device_key
+            );
+        }
+
+        if device_key == INTERNAL_DEVICE_KEY {
+            if let Some(resolved_control) = self.resolved_internal_controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: None,
+                    control_key: source.control.clone(),
+                    control: ControlReference::Internal(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_device) = self.resolved_midi_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Midi(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_device) = self.resolved_mouse_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
                     control_key: source.control.clone(),
                     control: ControlReference::Mouse(resolved_control.clone()),
                 });
             }
         }
 
+        if let Some(resolved_device) = self.resolved_keyboard_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Keyboard(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_device) = self.resolved_gamepad_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Gamepad(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_device) = self.resolved_touch_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Touch(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_source) = self.resolved_osc_sources.get(device_key) {
+            if let Some(resolved_control) = resolved_source.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Osc(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_source) = self.resolved_json_sources.get(device_key) {
+            if let Some(resolved_control) = resolved_source.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Json(resolved_control.clone()),
+                });
+            }
+        }
+
+        if let Some(resolved_source) = self.resolved_generators.get(device_key) {
+            if let Some(resolved_control) = resolved_source.controls.get(&source.control) {
+                return Ok(ResolvedMappingSource {
+                    device_key: device_key.to_string(),
+                    instance: instance.clone(),
+                    control_key: source.control.clone(),
+                    control: ControlReference::Generator(resolved_control.clone()),
+                });
+            }
+        }
+
         bail!(
             "Failed to resolve source device '{}' or control '{}'",
             source.device,
@@ -847,21 +2379,64 @@ impl ConfigManager {
     }
 
     fn resolve_destination(&self, dest: &MappingDestination) -> Result<ResolvedMappingDestination> {
+        if dest.joystick == INTERNAL_DEVICE_KEY {
+            if let Some(resolved_control) = self.resolved_internal_controls.get(&dest.control) {
+                return Ok(ResolvedMappingDestination {
+                    device_key: dest.joystick.clone(),
+                    control_key: dest.control.clone(),
+                    control: DestinationReference::Internal(resolved_control.clone()),
+                });
+            }
+        }
+
         if let Some(resolved_joystick) = self.resolved_virtual_joysticks.get(&dest.joystick) {
+            if let Some(hat) = resolved_joystick.hats.get(&dest.control) {
+                let Some(direction) = dest.direction else {
+                    bail!(
+                        "Destination hat '{}' of joystick '{}' needs a `direction`: \
+                        up, down, left or right",
+                        dest.control,
+                        dest.joystick
+                    );
+                };
+                return Ok(ResolvedMappingDestination {
+                    device_key: dest.joystick.clone(),
+                    control_key: dest.control.clone(),
+                    control: DestinationReference::Hat {
+                        hat: hat.clone(),
+                        direction,
+                    },
+                });
+            }
+            if dest.direction.is_some() {
+                bail!(
+                    "Destination '{}' of joystick '{}' takes no `direction`, it's not a hat",
+                    dest.control,
+                    dest.joystick
+                );
+            }
             if let Some(resolved_control) = resolved_joystick.controls.get(&dest.control) {
-                let joystick = self
-                    .config
-                    .virtual_joysticks
-                    .get(&dest.joystick)
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Joystick '{}' not found in config", dest.joystick)
-                    })?;
+                return Ok(ResolvedMappingDestination {
+                    device_key: dest.joystick.clone(),
+                    control_key: dest.control.clone(),
+                    control: DestinationReference::Joystick(resolved_control.clone()),
+                });
+            }
+        }
 
+        if let Some(resolved_output) = self.resolved_midi_outputs.get(&dest.joystick) {
+            if dest.direction.is_some() {
+                bail!(
+                    "Destination '{}' of MIDI output '{}' takes no `direction`",
+                    dest.control,
+                    dest.joystick
+                );
+            }
+            if let Some(resolved_control) = resolved_output.controls.get(&dest.control) {
                 return Ok(ResolvedMappingDestination {
                     device_key: dest.joystick.clone(),
-                    joystick: joystick.clone(),
                     control_key: dest.control.clone(),
-                    control: resolved_control.clone(),
+                    control: DestinationReference::MidiOutput(resolved_control.clone()),
                 });
             }
         }
@@ -896,8 +2471,27 @@ impl ConfigManager {
     pub(crate) fn validate(&self) -> Result<Vec<String>> {
         let mut errors = Vec::new();
 
+        if Duration::try_from_secs_f32(self.config.global.hotplug_poll_interval).is_err() {
+            errors.push(format!(
+                "global.hotplug_poll_interval must be a non-negative number of seconds, got {}",
+                self.config.global.hotplug_poll_interval
+            ));
+        }
+
+        let virtual_midi_ports = &self.config.global.virtual_midi_ports;
+        for (i, port_name) in virtual_midi_ports.iter().enumerate() {
+            if port_name.trim().is_empty() {
+                errors.push("global.virtual_midi_ports has an empty port name".to_string());
+            } else if virtual_midi_ports[..i].contains(port_name) {
+                errors.push(format!(
+                    "global.virtual_midi_ports has duplicate port name '{}'",
+                    port_name
+                ));
+            }
+        }
+
         for (i, mapping) in self.config.mappings.iter().enumerate() {
-            let src_dev = &mapping.source.device;
+            let (src_dev, _) = split_device_instance(&mapping.source.device);
             let src_ctrl = &mapping.source.control;
             let dst_joy = &mapping.destination.joystick;
             let dst_ctrl = &mapping.destination.control;
@@ -915,11 +2509,68 @@ impl ConfigManager {
                 .map(|m| m.contains_key(src_dev))
                 .unwrap_or(false);
 
-            if !found_in_midi && !found_in_mouse {
+            let found_in_keyboard = self
+                .config
+                .keyboard_devices
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_gamepad = self
+                .config
+                .gamepad_devices
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_touch = self
+                .config
+                .touch_devices
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_osc = self
+                .config
+                .osc_sources
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_json = self
+                .config
+                .json_sources
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_generators = self
+                .config
+                .generators
+                .as_ref()
+                .is_some_and(|m| m.contains_key(src_dev));
+
+            let found_in_internal =
+                src_dev == INTERNAL_DEVICE_KEY && self.config.internal_controls.is_some();
+
+            if !found_in_midi
+                && !found_in_mouse
+                && !found_in_keyboard
+                && !found_in_gamepad
+                && !found_in_touch
+                && !found_in_osc
+                && !found_in_json
+                && !found_in_generators
+                && !found_in_internal
+            {
                 errors.push(format!(
                     "Mapping[{}] references unknown device '{}'",
                     i, src_dev
                 ));
+            } else if found_in_internal {
+                if let Some(internal_controls) = &self.config.internal_controls {
+                    if !internal_controls.contains_key(src_ctrl) {
+                        errors.push(format!(
+                            "Mapping[{}] references unknown control '{}' in internal_controls",
+                            i, src_ctrl
+                        ));
+                    }
+                }
             } else if found_in_midi {
                 if let Some(midi_devices) = &self.config.midi_devices {
                     if let Some(device) = midi_devices.get(src_dev) {
@@ -933,13 +2584,36 @@ impl ConfigManager {
                 }
             }
 
-            if !self.config.virtual_joysticks.contains_key(dst_joy) {
+            if dst_joy == INTERNAL_DEVICE_KEY && self.config.internal_controls.is_some() {
+                if let Some(internal_controls) = &self.config.internal_controls {
+                    if !internal_controls.contains_key(dst_ctrl) {
+                        errors.push(format!(
+                            "Mapping[{}] references unknown control '{}' in internal_controls",
+                            i, dst_ctrl
+                        ));
+                    }
+                }
+            } else if let Some(output) = self
+                .config
+                .midi_outputs
+                .as_ref()
+                .and_then(|m| m.get(dst_joy))
+            {
+                if !output.controls.contains_key(dst_ctrl) {
+                    errors.push(format!(
+                        "Mapping[{}] references unknown control '{}' in midi_outputs['{}']",
+                        i, dst_ctrl, dst_joy
+                    ));
+                }
+            } else if !self.config.virtual_joysticks.contains_key(dst_joy) {
                 errors.push(format!(
                     "Mapping[{}] references unknown virtual joystick '{}'",
                     i, dst_joy
                 ));
             } else if let Some(joystick) = self.config.virtual_joysticks.get(dst_joy) {
-                if !joystick.controls.contains_key(dst_ctrl) {
+                if !joystick.controls.contains_key(dst_ctrl)
+                    && !joystick.hats.contains_key(dst_ctrl)
+                {
                     errors.push(format!(
                         "Mapping[{}] references unknown control '{}' in virtual_joysticks['{}']",
                         i, dst_ctrl, dst_joy
@@ -972,6 +2646,18 @@ impl ConfigManager {
         &self.resolved_virtual_joysticks
     }
 
+    pub(crate) fn get_resolved_midi_outputs(
+        &self,
+    ) -> &BTreeMap<String, crate::schemas::ResolvedMidiOutputDevice> {
+        &self.resolved_midi_outputs
+    }
+
+    pub(crate) fn get_resolved_midi_feedback(
+        &self,
+    ) -> &BTreeMap<String, crate::schemas::ResolvedMidiFeedback> {
+        &self.resolved_midi_feedback
+    }
+
     pub(crate) fn get_resolved_midi_device(
         &self,
         key: &str,
@@ -986,11 +2672,69 @@ impl ConfigManager {
         self.resolved_mouse_devices.get(key)
     }
 
+    pub(crate) fn get_resolved_keyboard_device(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedKeyboardDevice> {
+        self.resolved_keyboard_devices.get(key)
+    }
+
+    pub(crate) fn get_resolved_gamepad_device(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedGamepadDevice> {
+        self.resolved_gamepad_devices.get(key)
+    }
+
+    pub(crate) fn get_resolved_touch_device(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedTouchDevice> {
+        self.resolved_touch_devices.get(key)
+    }
+
+    pub(crate) fn get_resolved_osc_source(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedOscSource> {
+        self.resolved_osc_sources.get(key)
+    }
+
+    pub(crate) fn get_resolved_json_source(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedJsonSource> {
+        self.resolved_json_sources.get(key)
+    }
+
+    pub(crate) fn get_resolved_generator_source(
+        &self,
+        key: &str,
+    ) -> Option<&crate::schemas::ResolvedGeneratorSource> {
+        self.resolved_generators.get(key)
+    }
+
+    pub(crate) fn get_resolved_internal_controls(
+        &self,
+    ) -> &BTreeMap<String, crate::schemas::ResolvedInternalControl> {
+        &self.resolved_internal_controls
+    }
+
+    pub(crate) fn get_internal_control_order(&self) -> &[String] {
+        &self.internal_control_order
+    }
+
     fn lookup_control_range(
         &self,
         device_key: &str,
         control_key: &str,
     ) -> Option<NumInterval<i32>> {
+        if device_key == INTERNAL_DEVICE_KEY {
+            if let Some(resolved_control) = self.resolved_internal_controls.get(control_key) {
+                return Some(resolved_control.range);
+            }
+        }
+
         if let Some(resolved_device) = self.resolved_midi_devices.get(device_key) {
             if let Some(resolved_control) = resolved_device.controls.get(control_key) {
                 return resolved_control.range;
@@ -1003,6 +2747,26 @@ impl ConfigManager {
             }
         }
 
+        if let Some(resolved_device) = self.resolved_keyboard_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(control_key) {
+                return Some(resolved_control.range);
+            }
+        }
+
+        // NB: gamepad axes without an explicit range are only known once the device is open,
+        // NB: so they can't be referenced here.
+        if let Some(resolved_device) = self.resolved_gamepad_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(control_key) {
+                return resolved_control.range;
+            }
+        }
+
+        if let Some(resolved_device) = self.resolved_touch_devices.get(device_key) {
+            if let Some(resolved_control) = resolved_device.controls.get(control_key) {
+                return resolved_control.range;
+            }
+        }
+
         if let Some(resolved_joystick) = self.resolved_virtual_joysticks.get(device_key) {
             if let Some(resolved_control) = resolved_joystick.controls.get(control_key) {
                 return Some(resolved_control.range);
@@ -1012,3 +2776,205 @@ impl ConfigManager {
         None
     }
 }
+
+/// Devices and controls a mapping reads: its source and the ones referenced by its steps.
+fn mapping_input_controls(mapping: &ResolvedMapping) -> Vec<(&str, &str)> {
+    let mut inputs = vec![(
+        mapping.source.device_key.as_str(),
+        mapping.source.control_key.as_str(),
+    )];
+    if let ControlReference::Generator(generator) = &mapping.source.control {
+        for parameter in [
+            &generator.frequency,
+            &generator.amplitude,
+            &generator.offset,
+        ] {
+            if let ResolvedGeneratorParameter::Reference {
+                device, control, ..
+            } = parameter
+            {
+                inputs.push((device, control));
+            }
+        }
+    }
+    for step in &mapping.transformation {
+        let factor = match step {
+            ResolvedTransformationStep::Steering { steering, .. } => &steering.hold_factor,
+            ResolvedTransformationStep::PedalSmoother { pedal_smoother, .. } => {
+                &pedal_smoother.fall_gentling_factor
+            }
+            _ => continue,
+        };
+        if let Some(ResolvedHoldFactor::Reference {
+            device, control, ..
+        }) = factor
+        {
+            inputs.push((device, control));
+        }
+    }
+    inputs
+}
+
+/// Orders keys so that each one comes after the keys it depends on,
+/// dependencies which aren't keys themselves are ignored.
+fn dependency_order<'a>(
+    dependencies: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+) -> Result<Vec<&'a str>> {
+    fn visit<'a>(
+        key: &'a str,
+        dependencies: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+        done: &mut BTreeSet<&'a str>,
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        if done.contains(key) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|k| *k == key) {
+            // NB: the path runs from dependents to their dependencies, the values flow backwards.
+            let mut cycle = path[start..].to_vec();
+            cycle.push(key);
+            cycle.reverse();
+            bail!(
+                "Controls depend on each other in a cycle: {}",
+                cycle.join(" -> ")
+            );
+        }
+        path.push(key);
+        for dependency in dependencies.get(key).into_iter().flatten() {
+            if dependencies.contains_key(dependency) {
+                visit(dependency, dependencies, done, path, order)?;
+            }
+        }
+        path.pop();
+        done.insert(key);
+        order.push(key);
+        Ok(())
+    }
+
+    let mut done = BTreeSet::new();
+    let mut order = Vec::new();
+    for key in dependencies.keys() {
+        visit(key, dependencies, &mut done, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_device_instance() {
+        assert_eq!(split_device_instance("mouse"), ("mouse", None));
+        assert_eq!(
+            split_device_instance("left_hand_mouse[1]"),
+            ("left_hand_mouse", Some(DeviceInstanceSelector::Index(1)))
+        );
+        assert_eq!(
+            split_device_instance("mouse[usb-0000:00:14.0-2/input0]"),
+            (
+                "mouse",
+                Some(DeviceInstanceSelector::Id(
+                    "usb-0000:00:14.0-2/input0".to_string()
+                ))
+            )
+        );
+        assert_eq!(split_device_instance("odd]name"), ("odd]name", None));
+    }
+
+    #[test]
+    fn test_instance_selected() {
+        let phys = "usb-0000:00:14.0-2/input0";
+        assert!(instance_selected(None, None));
+        assert!(instance_selected(None, Some((0, phys))));
+        assert!(!instance_selected(None, Some((1, phys))));
+
+        let second = DeviceInstanceSelector::Index(1);
+        assert!(instance_selected(Some(&second), Some((1, phys))));
+        assert!(!instance_selected(Some(&second), Some((0, phys))));
+        assert!(!instance_selected(Some(&second), None));
+
+        let by_phys = DeviceInstanceSelector::Id(phys.to_string());
+        assert!(instance_selected(Some(&by_phys), Some((1, phys))));
+        assert!(!instance_selected(
+            Some(&by_phys),
+            Some((0, "/dev/input/event5"))
+        ));
+    }
+
+    #[test]
+    fn test_relative_distance() {
+        let parse = |yaml: &str| serde_yaml::from_str::<RelativeDistance>(yaml);
+        assert_eq!(parse("600").unwrap().to_counts(None).unwrap(), 600.0);
+        assert_eq!(
+            parse("\"12cm\"").unwrap().to_counts(Some(50.0)).unwrap(),
+            600.0
+        );
+        assert_eq!(
+            parse("\"5 mm\"").unwrap().to_counts(Some(40.0)).unwrap(),
+            20.0
+        );
+        assert_eq!(
+            parse("\"2in\"").unwrap().to_counts(Some(10.0)).unwrap(),
+            50.8
+        );
+        assert!(parse("\"12cm\"").unwrap().to_counts(None).is_err());
+        assert!(parse("\"12\"").is_err());
+        assert!(parse("\"twelve cm\"").is_err());
+
+        let range: RelativeRange = serde_yaml::from_str("[-300, \"3cm\"]").unwrap();
+        assert_eq!(range.from, RelativeDistance::Counts(-300.0));
+        assert_eq!(range.to.to_counts(Some(100.0)).unwrap(), 300.0);
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let dependencies: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::from([
+            ("mix", BTreeSet::from(["a", "b"])),
+            ("b", BTreeSet::from(["a", "external"])),
+            ("a", BTreeSet::new()),
+            ("alone", BTreeSet::new()),
+        ]);
+        assert_eq!(
+            dependency_order(&dependencies).unwrap(),
+            vec!["a", "alone", "b", "mix"]
+        );
+
+        let cyclic: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::from([
+            ("a", BTreeSet::from(["c"])),
+            ("b", BTreeSet::from(["a"])),
+            ("c", BTreeSet::from(["b"])),
+        ]);
+        let error = dependency_order(&cyclic).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Controls depend on each other in a cycle: a -> b -> c -> a"
+        );
+
+        let self_loop: BTreeMap<&str, BTreeSet<&str>> =
+            BTreeMap::from([("a", BTreeSet::from(["a"]))]);
+        assert!(dependency_order(&self_loop).is_err());
+    }
+
+    #[test]
+    fn test_key_thresholds() {
+        // Without thresholds any non-zero value presses, negative ones included.
+        assert!(KeyThresholds::is_pressed(None, false, -0.1));
+        assert!(!KeyThresholds::is_pressed(None, true, 0.0));
+
+        let thresholds = KeyThresholds {
+            press: 0.6,
+            release: 0.4,
+        };
+        let mut pressed = false;
+        let states: Vec<bool> = [0.5, 0.6, 0.5, 0.4, 0.39, 0.5]
+            .into_iter()
+            .map(|value| {
+                pressed = KeyThresholds::is_pressed(Some(&thresholds), pressed, value);
+                pressed
+            })
+            .collect();
+        assert_eq!(states, vec![false, true, true, true, false, false]);
+    }
+}
@@ -11,6 +11,9 @@ macro_rules! define_control_types {
         button {
             $( $btn_variant:ident => $btn_code:ident $(, $btn_doc:literal)? ),* $(,)?
         }
+        key {
+            $( $key_variant:ident => $key_code:ident $(as $key_name:literal)? $(, $key_doc:literal)? ),* $(,)?
+        }
         midi {
             $( $midi_variant:ident $(, $midi_doc:literal)? ),* $(,)?
         }
@@ -18,7 +21,8 @@ macro_rules! define_control_types {
         /// Internal event codes enumeration, flat and abstracting from platform-related
         /// implementation, conversion from and to configuration string representation
         /// with strum.
-        #[derive(Debug, PartialEq, EnumString, Display, Clone, Default, Copy, Hash, Eq)]
+        /// Codes without a name are kept raw and written like `ABS:0x29`, `REL:0xa` or `KEY:0x2e8`.
+        #[derive(Debug, PartialEq, Display, Clone, Default, Copy, Hash, Eq)]
         #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
         pub(crate) enum ControlType {
             $(
@@ -33,12 +37,113 @@ macro_rules! define_control_types {
                 $(#[doc = $btn_doc])?
                 $btn_variant,
             )*
+            $(
+                $(#[doc = $key_doc])?
+                $(#[strum(to_string = $key_name)])?
+                $key_variant,
+            )*
             $(
                 $(#[doc = $midi_doc])?
                 $midi_variant,
             )*
             #[default]
             Unhandled,
+            #[strum(to_string = "ABS:{0:#x}")]
+            RawAbsolute(u16),
+            #[strum(to_string = "REL:{0:#x}")]
+            RawRelative(u16),
+            #[strum(to_string = "KEY:{0:#x}")]
+            RawKey(u16),
+        }
+
+        impl ControlType {
+            /// All the variants having a name.
+            const NAMED: &[ControlType] = &[
+                $( ControlType::$abs_variant, )*
+                $( ControlType::$rel_variant, )*
+                $( ControlType::$btn_variant, )*
+                $( ControlType::$key_variant, )*
+                $( ControlType::$midi_variant, )*
+                ControlType::Unhandled,
+            ];
+
+            /// The control type of an evdev event code: a named one if there is, a raw one otherwise.
+            pub(crate) fn from_evdev_code(event_type: evdev::EventType, code: u16) -> ControlType {
+                type OsAbsAxisCode = evdev::AbsoluteAxisCode;
+                type OsRelCode = evdev::RelativeAxisCode;
+                type OsKeyCode = evdev::KeyCode;
+
+                match event_type {
+                    evdev::EventType::ABSOLUTE => match code {
+                        $(
+                            c if c == OsAbsAxisCode::$abs_code.0 => ControlType::$abs_variant,
+                        )*
+                        c => ControlType::RawAbsolute(c),
+                    },
+                    evdev::EventType::RELATIVE => match code {
+                        $(
+                            c if c == OsRelCode::$rel_code.0 => ControlType::$rel_variant,
+                        )*
+                        c => ControlType::RawRelative(c),
+                    },
+                    evdev::EventType::KEY => match code {
+                        $(
+                            c if c == OsKeyCode::$btn_code.0 => ControlType::$btn_variant,
+                        )*
+                        $(
+                            c if c == OsKeyCode::$key_code.0 => ControlType::$key_variant,
+                        )*
+                        c => ControlType::RawKey(c),
+                    },
+                    _ => ControlType::Unhandled,
+                }
+            }
+        }
+
+        impl std::str::FromStr for ControlType {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> anyhow::Result<Self> {
+                static BY_NAME: std::sync::LazyLock<std::collections::HashMap<String, ControlType>> =
+                    std::sync::LazyLock::new(|| {
+                        ControlType::NAMED
+                            .iter()
+                            .map(|control_type| (control_type.to_string(), *control_type))
+                            .collect()
+                    });
+
+                if let Some(named) = BY_NAME.get(s) {
+                    return Ok(*named);
+                }
+                let Some((prefix, code)) = s.split_once(':') else {
+                    anyhow::bail!("Unknown control type '{}'", s);
+                };
+                let (event_type, max_code) = match prefix {
+                    "ABS" => (evdev::EventType::ABSOLUTE, 0x3f),
+                    "REL" => (evdev::EventType::RELATIVE, 0x0f),
+                    "KEY" => (evdev::EventType::KEY, 0x2ff),
+                    _ => anyhow::bail!(
+                        "Unknown raw code type '{}' in '{}', expected ABS, REL or KEY",
+                        prefix,
+                        s
+                    ),
+                };
+                let parsed = match code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => code.parse::<u16>(),
+                };
+                let code = match parsed {
+                    Ok(code) if code <= max_code => code,
+                    _ => anyhow::bail!(
+                        "Invalid raw code '{}' in '{}', expected a number up to {:#x}",
+                        code,
+                        s,
+                        max_code
+                    ),
+                };
+                // NB: a raw code having a name is the named control, so both forms match the same events.
+                Ok(Self::from_evdev_code(event_type, code))
+            }
         }
 
         impl ControlType {
@@ -51,7 +156,7 @@ macro_rules! define_control_types {
             pub(crate) fn is_absolute(&self) -> bool {
                 matches!(
                     self,
-                    $( ControlType::$abs_variant )|*
+                    $( ControlType::$abs_variant )|* | ControlType::RawAbsolute(_)
                 )
             }
 
@@ -59,16 +164,26 @@ macro_rules! define_control_types {
             pub(crate) fn is_relative(&self) -> bool {
                 matches!(
                     self,
-                    $( ControlType::$rel_variant )|*
+                    $( ControlType::$rel_variant )|* | ControlType::RawRelative(_)
                 )
             }
 
             #[allow(dead_code)]
             pub(crate) fn is_button(&self) -> bool {
-                matches!(
-                    self,
-                    $( ControlType::$btn_variant )|*
-                )
+                match self {
+                    $( ControlType::$btn_variant )|* => true,
+                    ControlType::RawKey(code) => $crate::control_types_macro::is_button_code(*code),
+                    _ => false,
+                }
+            }
+
+            #[allow(dead_code)]
+            pub(crate) fn is_key(&self) -> bool {
+                match self {
+                    $( ControlType::$key_variant )|* => true,
+                    ControlType::RawKey(code) => !$crate::control_types_macro::is_button_code(*code),
+                    _ => false,
+                }
             }
 
             /// Returns an iterator over all absolute control types
@@ -88,6 +203,12 @@ macro_rules! define_control_types {
             pub(crate) fn iter_button() -> ButtonControlTypeIter {
                 ButtonControlTypeIter { index: 0 }
             }
+
+            /// Returns an iterator over all keyboard key control types
+            #[allow(dead_code)]
+            pub(crate) fn iter_key() -> KeyControlTypeIter {
+                KeyControlTypeIter { index: 0 }
+            }
         }
 
         // Absolute controls iterator
@@ -186,55 +307,53 @@ macro_rules! define_control_types {
 
         impl ExactSizeIterator for ButtonControlTypeIter {}
 
+        // Keyboard key controls iterator
+        #[derive(Debug, Clone)]
+        pub(crate) struct KeyControlTypeIter {
+            index: usize,
+        }
+
+        impl Iterator for KeyControlTypeIter {
+            type Item = ControlType;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let mut _current_index = 0;
+                $(
+                    if self.index == _current_index {
+                        self.index += 1;
+                        return Some(ControlType::$key_variant);
+                    }
+                    _current_index += 1;
+                )*
+                None
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let remaining = {
+                    let total = [ $( stringify!($key_variant) ),* ].len();
+                    total.saturating_sub(self.index)
+                };
+                (remaining, Some(remaining))
+            }
+        }
+
+        impl ExactSizeIterator for KeyControlTypeIter {}
+
         impl From<evdev::InputEvent> for ControlType {
             fn from(event: evdev::InputEvent) -> Self {
-                let code = event.code();
-                type OsAbsAxisCode = evdev::AbsoluteAxisCode;
-                type OsRelCode = evdev::RelativeAxisCode;
-                type OsKeyCode = evdev::KeyCode;
-
-                match event.event_type() {
-                    evdev::EventType::ABSOLUTE => match code {
-                        $(
-                            c if c == OsAbsAxisCode::$abs_code.0 => ControlType::$abs_variant,
-                        )*
-                        c => {
-                            log::warn!(
-                                "Unimplemented handling for control {:?} \
-                                while getting control type from {event:?}",
-                                c
-                            );
-                            ControlType::Unhandled
-                        }
-                    },
-                    evdev::EventType::RELATIVE => match code {
-                        $(
-                            c if c == OsRelCode::$rel_code.0 => ControlType::$rel_variant,
-                        )*
-                        c => {
-                            log::warn!(
-                                "Unimplemented handling for control {:?} \
-                                while getting control type from {event:?}",
-                                c
-                            );
-                            ControlType::Unhandled
-                        }
-                    },
-                    evdev::EventType::KEY => match code {
-                        $(
-                            c if c == OsKeyCode::$btn_code.0 => ControlType::$btn_variant,
-                        )*
-                        c => {
-                            log::warn!(
-                                "Unimplemented handling for control {:?} \
-                                while getting control type from {event:?}",
-                                c
-                            );
-                            ControlType::Unhandled
-                        }
-                    },
-                    _ => ControlType::Unhandled,
+                let control_type = ControlType::from_evdev_code(event.event_type(), event.code());
+                if matches!(
+                    control_type,
+                    ControlType::RawAbsolute(_) | ControlType::RawRelative(_) | ControlType::RawKey(_)
+                ) {
+                    log::debug!(
+                        "No named control for code {:#x}, handling it as {} while getting \
+                        control type from {event:?}",
+                        event.code(),
+                        control_type
+                    );
                 }
+                control_type
             }
         }
 
@@ -254,6 +373,12 @@ macro_rules! define_control_types {
                     $(
                         ControlType::$btn_variant => OsKeyCode::$btn_code.0,
                     )*
+                    $(
+                        ControlType::$key_variant => OsKeyCode::$key_code.0,
+                    )*
+                    ControlType::RawAbsolute(code)
+                    | ControlType::RawRelative(code)
+                    | ControlType::RawKey(code) => code,
                     c => {
                         log::warn!(
                             "Unimplemented handling for control type {:?} while converting to evdev code.",
@@ -275,8 +400,14 @@ macro_rules! define_control_types {
             fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ControlType, D::Error> {
                 let s = String::deserialize(deserializer)?;
                 s.parse::<ControlType>()
-                    .map_err(|_| de::Error::custom(format!("Invalid code string: {}", s)))
+                    .map_err(|e| de::Error::custom(format!("Invalid code string: {}", e)))
             }
         }
     };
 }
+
+/// Whether an evdev KEY code is in one of the button blocks rather than a keyboard key.
+pub(crate) fn is_button_code(code: u16) -> bool {
+    // BTN_MISC..=BTN_GEAR_UP, BTN_DPAD_UP..=BTN_DPAD_RIGHT, BTN_TRIGGER_HAPPY1..=BTN_TRIGGER_HAPPY40.
+    matches!(code, 0x100..=0x151 | 0x220..=0x223 | 0x2c0..=0x2e7)
+}
@@ -6,18 +6,63 @@ use colored::Colorize;
 use log::{error, info, warn};
 
 use crate::config::ConfigManager;
+use crate::evdev_source::EvdevDeviceInfo;
+use crate::gamepad::GamepadManager;
 use crate::joystick::VirtualJoystickManager;
+use crate::json::JsonManager;
+use crate::keyboard::KeyboardManager;
 use crate::mapping::MappingEngine;
 use crate::midi::{MidiLearnMode, MidiManager};
+use crate::midi_feedback::MidiFeedbackManager;
+use crate::midi_output::MidiOutputManager;
 use crate::mouse::MouseManager;
+use crate::osc::OscManager;
+use crate::schemas::JsonEndpoint;
+use crate::touch::TouchManager;
 
 #[derive(Subcommand, Clone)]
 pub enum AuxDriverTask {
     EnumMidi,
-    MonitorMidi { name_regex: Option<String> },
+    /// List the MIDI output ports `midi_outputs` can connect to.
+    EnumMidiOutputs,
+    MonitorMidi {
+        name_regex: Option<String>,
+    },
     MidiLearn,
     EnumMice,
-    MonitorMouse { name_regex: Option<String> },
+    MonitorMouse {
+        name_regex: Option<String>,
+    },
+    /// Measure counts per cm of a `mouse_devices` entry by moving it a known distance,
+    /// and print the `counts_per_cm` line to add to the entry.
+    CalibrateMouse {
+        /// Key of the entry in `mouse_devices`.
+        device_key: String,
+        /// Length of each stroke, in cm.
+        #[arg(long, default_value_t = 10.0)]
+        distance_cm: f32,
+        /// Number of strokes to average.
+        #[arg(long, default_value_t = 3)]
+        strokes: usize,
+    },
+    EnumKeyboards,
+    MonitorKeyboard {
+        name_regex: Option<String>,
+    },
+    EnumGamepads,
+    MonitorGamepad {
+        name_regex: Option<String>,
+    },
+    EnumTouch,
+    MonitorTouch {
+        name_regex: Option<String>,
+    },
+    MonitorOsc {
+        bind: std::net::SocketAddr,
+    },
+    MonitorJson {
+        endpoint: String,
+    },
     ValidateConfig,
 }
 
@@ -62,7 +107,26 @@ pub async fn run_aux_task(
                 .iter()
                 .enumerate()
             {
-                info!("> {}. {}", i + 1, device.name);
+                match (device.client, device.port) {
+                    (Some(client), Some(port)) => info!(
+                        "> {}. {} (client: {}, port: {})",
+                        i + 1,
+                        device.name,
+                        client,
+                        port
+                    ),
+                    _ => info!("> {}. {}", i + 1, device.name),
+                }
+            }
+        }
+        AuxDriverTask::EnumMidiOutputs => {
+            info!("Available MIDI output ports:");
+            for (i, name) in MidiOutputManager::new(debug)?
+                .enumerate_ports()?
+                .iter()
+                .enumerate()
+            {
+                info!("> {}. {}", i + 1, name);
             }
         }
         AuxDriverTask::MonitorMidi { name_regex: device } => {
@@ -78,7 +142,7 @@ pub async fn run_aux_task(
         AuxDriverTask::EnumMice => {
             info!("Available mouse devices:");
             for device in MouseManager::new(debug)?.enumerate_devices()? {
-                info!("> {} @ {}", device.name, device.path.display());
+                log_evdev_device(&device);
             }
         }
         AuxDriverTask::MonitorMouse { name_regex: device } => {
@@ -88,6 +152,83 @@ pub async fn run_aux_task(
                 )?)
                 .await?;
         }
+        AuxDriverTask::CalibrateMouse {
+            device_key,
+            distance_cm,
+            strokes,
+        } => {
+            let mut config_manager =
+                ConfigManager::new(cfg_file_path, predef_cfg_file_path, debug)?;
+            config_manager.load()?;
+            let Some(device) = config_manager.get_resolved_mouse_device(device_key) else {
+                bail!("No mouse_devices['{}'] in the config", device_key);
+            };
+            let any_name = regex::Regex::new(".*")?;
+            let counts_per_cm = MouseManager::new(debug)?
+                .calibrate(
+                    device_key,
+                    device.match_name_regex.as_ref().unwrap_or(&any_name),
+                    &device.device_match,
+                    *distance_cm,
+                    *strokes,
+                )
+                .await?;
+            println!("Add to mouse_devices['{}'] in your config:", device_key);
+            println!("    counts_per_cm: {}", counts_per_cm);
+        }
+        AuxDriverTask::EnumKeyboards => {
+            info!("Available keyboard devices:");
+            for device in KeyboardManager::new(debug)?.enumerate_devices()? {
+                log_evdev_device(&device);
+            }
+        }
+        AuxDriverTask::MonitorKeyboard { name_regex: device } => {
+            KeyboardManager::new(debug)?
+                .monitor(&regex::Regex::new(
+                    &device.clone().unwrap_or(".*".to_string()),
+                )?)
+                .await?;
+        }
+        AuxDriverTask::EnumGamepads => {
+            info!("Available gamepad/joystick devices:");
+            for device in GamepadManager::new(debug)?.enumerate_devices()? {
+                log_evdev_device(&device);
+                for (control_type, range) in &device.axes {
+                    info!("    {} [{}, {}]", control_type, range.from, range.to);
+                }
+            }
+        }
+        AuxDriverTask::MonitorGamepad { name_regex: device } => {
+            GamepadManager::new(debug)?
+                .monitor(&regex::Regex::new(
+                    &device.clone().unwrap_or(".*".to_string()),
+                )?)
+                .await?;
+        }
+        AuxDriverTask::EnumTouch => {
+            info!("Available multitouch devices:");
+            for device in TouchManager::new(debug)?.enumerate_devices()? {
+                log_evdev_device(&device);
+                for (control_type, range) in &device.axes {
+                    info!("    {} [{}, {}]", control_type, range.from, range.to);
+                }
+            }
+        }
+        AuxDriverTask::MonitorTouch { name_regex: device } => {
+            TouchManager::new(debug)?
+                .monitor(&regex::Regex::new(
+                    &device.clone().unwrap_or(".*".to_string()),
+                )?)
+                .await?;
+        }
+        AuxDriverTask::MonitorOsc { bind } => {
+            OscManager::new(debug)?.monitor(*bind).await?;
+        }
+        AuxDriverTask::MonitorJson { endpoint } => {
+            JsonManager::new(debug)?
+                .monitor(&endpoint.parse::<JsonEndpoint>()?)
+                .await?;
+        }
         AuxDriverTask::ValidateConfig => {
             let mut config_manager =
                 ConfigManager::new(cfg_file_path, predef_cfg_file_path, debug)?;
@@ -107,6 +248,21 @@ pub async fn run_aux_task(
     Ok(())
 }
 
+/// Logs the attributes the `match_*` options of evdev device entries are matched against.
+fn log_evdev_device(device: &EvdevDeviceInfo) {
+    info!("> {} @ {}", device.name, device.path.display());
+    info!(
+        "    vendor_id: {:#06x}, product_id: {:#06x}, phys: {}, uniq: {}",
+        device.vendor_id,
+        device.product_id,
+        device.phys.as_deref().unwrap_or("-"),
+        device.uniq.as_deref().unwrap_or("-")
+    );
+    for link in &device.by_id {
+        info!("    by-id: {}", link);
+    }
+}
+
 fn watch_config_file(cfg_file_path: &std::path::Path) -> Result<tokio::sync::mpsc::Receiver<()>> {
     use notify_debouncer_full::{new_debouncer, DebounceEventResult};
 
@@ -196,7 +352,14 @@ pub async fn run_mapping_engine(
             &config_manager,
             MidiManager::new(debug)?,
             MouseManager::new(debug)?,
+            KeyboardManager::new(debug)?,
+            GamepadManager::new(debug)?,
+            TouchManager::new(debug)?,
+            OscManager::new(debug)?,
+            JsonManager::new(debug)?,
             &joystick_manager,
+            MidiOutputManager::new(debug)?,
+            MidiFeedbackManager::new(debug)?,
             debug,
             debug_idle_tick,
             enable_steering_indicator_window,
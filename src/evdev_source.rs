@@ -0,0 +1,552 @@
+use anyhow::{bail, Context, Result};
+use evdev::Device;
+use log::{error, info};
+use std::fmt::{Debug, Display};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task;
+use tokio_util::sync::CancellationToken;
+
+use crate::common::{ControlType, NumInterval};
+use crate::grab::{DeviceGrab, GrabRole};
+use crate::hotplug::{OpenedDevices, INPUT_DEVICES_DIR};
+use crate::joystick::VIRTUAL_DEVICE_PHYS;
+use crate::schemas::ResolvedEvdevDeviceMatch;
+
+#[derive(Debug, Clone)]
+pub(crate) struct EvdevDeviceInfo {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) vendor_id: u16,
+    pub(crate) product_id: u16,
+    pub(crate) phys: Option<String>,
+    pub(crate) uniq: Option<String>,
+    /// Names of the /dev/input/by-id links pointing to the device node.
+    pub(crate) by_id: Vec<String>,
+    /// Absolute axes known to `ControlType` with their ranges as reported by the device AbsInfo.
+    pub(crate) axes: Vec<(ControlType, NumInterval<i32>)>,
+}
+
+impl EvdevDeviceInfo {
+    fn read(path: &Path, device: &Device) -> Self {
+        let input_id = device.input_id();
+        Self {
+            name: device.name().unwrap_or("Unknown").to_string(),
+            path: path.to_path_buf(),
+            vendor_id: input_id.vendor(),
+            product_id: input_id.product(),
+            phys: device
+                .physical_path()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string()),
+            uniq: device
+                .unique_name()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string()),
+            by_id: read_by_id_links(path),
+            axes: read_abs_axes(device),
+        }
+    }
+
+    /// Identifies the physical device across replugging for `instances: true` entries.
+    pub(crate) fn instance_id(&self) -> String {
+        self.phys
+            .clone()
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
+/// Names of the /dev/input/by-id links resolving to the given device node.
+pub(crate) fn read_by_id_links(path: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/dev/input/by-id") else {
+        return Vec::new();
+    };
+    let mut links: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| std::fs::canonicalize(entry.path()).is_ok_and(|target| target == path))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    links.sort();
+    links
+}
+
+/// Virtual devices created by MMVJ itself, recognized by their phys.
+fn is_virtual_device(device: &Device) -> bool {
+    device
+        .physical_path()
+        .is_some_and(|phys| phys.as_bytes() == VIRTUAL_DEVICE_PHYS.to_bytes())
+}
+
+/// Reads ranges of all absolute axes known to `ControlType` from the device AbsInfo.
+pub(crate) fn read_abs_axes(device: &Device) -> Vec<(ControlType, NumInterval<i32>)> {
+    let Ok(abs_infos) = device.get_absinfo() else {
+        return Vec::new();
+    };
+    abs_infos
+        .filter_map(|(code, abs_info)| {
+            ControlType::iter_absolute()
+                .find(|control_type| u16::from(*control_type) == code.0)
+                .map(|control_type| {
+                    (
+                        control_type,
+                        NumInterval::new(abs_info.minimum(), abs_info.maximum()),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Checks the optional attribute matchers of a config entry, unset ones match anything.
+fn device_attributes_match(
+    device_match: &ResolvedEvdevDeviceMatch,
+    device: &EvdevDeviceInfo,
+) -> bool {
+    let regex_matches = |regex: &Option<regex::Regex>, value: Option<&str>| {
+        regex
+            .as_ref()
+            .is_none_or(|regex| value.is_some_and(|value| regex.is_match(value)))
+    };
+    device_match
+        .vendor_id
+        .is_none_or(|vendor_id| vendor_id == device.vendor_id)
+        && device_match
+            .product_id
+            .is_none_or(|product_id| product_id == device.product_id)
+        && regex_matches(&device_match.phys_regex, device.phys.as_deref())
+        && regex_matches(&device_match.uniq_regex, device.uniq.as_deref())
+        && device_match
+            .by_id_regex
+            .as_ref()
+            .is_none_or(|regex| device.by_id.iter().any(|link| regex.is_match(link)))
+}
+
+/// A key, button or axis event of a mouse, keyboard or gamepad.
+#[derive(Debug, Clone)]
+pub(crate) struct EvdevEvent {
+    pub(crate) device_key: String,
+    pub(crate) control_type: ControlType,
+    pub(crate) value: i32,
+}
+
+impl EvdevEvent {
+    /// None for synchronization events and events not associated with any control.
+    pub(crate) fn from_evdev(
+        device_key: &str,
+        event: evdev::InputEvent,
+        debug: bool,
+    ) -> Option<Self> {
+        let control_type = ControlType::from(event);
+        if control_type.is_unhandled() {
+            if debug && event.event_type() != evdev::EventType::SYNCHRONIZATION {
+                log::debug!("Event not associated with any control: {:?}", event);
+            }
+            None
+        } else {
+            Some(Self {
+                device_key: device_key.into(),
+                control_type,
+                value: event.value(),
+            })
+        }
+    }
+}
+
+impl Display for EvdevEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} = {}",
+            self.device_key, self.control_type, self.value
+        )
+    }
+}
+
+/// Non-blocking handle of an opened device node.
+pub(crate) trait EvdevStream: Send + Sized + 'static {
+    fn open(path: &Path) -> std::io::Result<Self>;
+
+    /// Reads the pending events, fails with `WouldBlock` if there are none.
+    fn fetch_events(&mut self) -> std::io::Result<Vec<evdev::InputEvent>>;
+
+    fn grab(&mut self) -> std::io::Result<()>;
+
+    fn ungrab(&mut self) -> std::io::Result<()>;
+
+    fn physical_path(&self) -> Option<&str>;
+}
+
+/// Synced device: after a SYN_DROPPED, evdev restores the lost key and axis states with
+/// compensating events and the SYN_DROPPED itself is not reported.
+impl EvdevStream for evdev::EventStream {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        Device::open(path)?.into_event_stream()
+    }
+
+    fn fetch_events(&mut self) -> std::io::Result<Vec<evdev::InputEvent>> {
+        Ok(self.device_mut().fetch_events()?.collect())
+    }
+
+    fn grab(&mut self) -> std::io::Result<()> {
+        self.device_mut().grab()
+    }
+
+    fn ungrab(&mut self) -> std::io::Result<()> {
+        self.device_mut().ungrab()
+    }
+
+    fn physical_path(&self) -> Option<&str> {
+        self.device().physical_path()
+    }
+}
+
+/// Raw device: SYN_DROPPED is reported and the source resyncs its state itself.
+impl EvdevStream for evdev::raw_stream::EventStream {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        evdev::raw_stream::RawDevice::open(path)?.into_event_stream()
+    }
+
+    fn fetch_events(&mut self) -> std::io::Result<Vec<evdev::InputEvent>> {
+        Ok(self.device_mut().fetch_events()?.collect())
+    }
+
+    fn grab(&mut self) -> std::io::Result<()> {
+        self.device_mut().grab()
+    }
+
+    fn ungrab(&mut self) -> std::io::Result<()> {
+        self.device_mut().ungrab()
+    }
+
+    fn physical_path(&self) -> Option<&str> {
+        self.device().physical_path()
+    }
+}
+
+/// The device kind specific part of an evdev source: which device nodes it takes and how
+/// their events become source events. Every opened device gets a clone as its own state.
+pub(crate) trait EvdevSource: Clone + Send + 'static {
+    type Event: Display + Send + 'static;
+
+    type Stream: EvdevStream;
+
+    /// Device kind in log messages.
+    const DEVICE_KIND: &'static str;
+
+    fn supports(&self, device: &Device) -> bool;
+
+    /// Prepares the state of a device being opened.
+    fn init(&mut self, _device_info: &EvdevDeviceInfo) {}
+
+    fn handle_event(
+        &mut self,
+        stream: &Self::Stream,
+        device_key: &str,
+        event: evdev::InputEvent,
+        debug: bool,
+    ) -> Option<Self::Event>;
+}
+
+struct EvdevDevice<S: EvdevSource> {
+    device_name: String,
+    device: S::Stream,
+    source: S,
+    sender: mpsc::UnboundedSender<S::Event>,
+    grab: Option<DeviceGrab>,
+    debug: bool,
+}
+
+impl<S: EvdevSource> EvdevDevice<S> {
+    fn new(
+        device_info: &EvdevDeviceInfo,
+        device_name: String,
+        mut source: S,
+        sender: mpsc::UnboundedSender<S::Event>,
+        grab_role: Option<GrabRole>,
+        debug: bool,
+    ) -> Result<Self> {
+        let path = &device_info.path;
+        let mut device =
+            S::Stream::open(path).context(format!("Failed to open device: {}", path.display()))?;
+        let grab = grab_role
+            .map(|role| DeviceGrab::from_role(role, &mut device, &device_name))
+            .transpose()?;
+        source.init(device_info);
+        Ok(Self {
+            device_name,
+            device,
+            source,
+            sender,
+            grab,
+            debug,
+        })
+    }
+
+    fn run(&mut self, engine_stop_token: CancellationToken) {
+        let mut sleep_millis = 0;
+        loop {
+            if engine_stop_token.is_cancelled() {
+                info!(
+                    "Stopping run thread for {} {:?} {}",
+                    S::DEVICE_KIND,
+                    self.device.physical_path(),
+                    self.device_name
+                );
+                if let Some(grab) = &mut self.grab {
+                    grab.release(&mut self.device, &self.device_name);
+                }
+                return;
+            }
+
+            if let Some(grab) = &mut self.grab {
+                grab.update(&mut self.device, &self.device_name);
+            }
+
+            // Using non-blocking mode, so manually handling WouldBlock case.
+            let result = match self.device.fetch_events() {
+                Ok(events) => {
+                    let mut source_events = Vec::new();
+                    for event in events {
+                        if let Some(grab) = &self.grab {
+                            grab.observe(ControlType::from(event), event.value());
+                        }
+                        if let Some(source_event) = self.source.handle_event(
+                            &self.device,
+                            &self.device_name,
+                            event,
+                            self.debug,
+                        ) {
+                            source_events.push(source_event);
+                        }
+                    }
+                    Some(Ok(source_events))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => None,
+                Err(e) => Some(Err(e)),
+            };
+
+            match result {
+                None => {
+                    if sleep_millis < 5 {
+                        sleep_millis += 1;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(sleep_millis));
+                }
+                Some(Ok(source_events)) => {
+                    sleep_millis = 0;
+                    for source_event in source_events {
+                        let _ = self.sender.send(source_event);
+                    }
+                }
+                Some(Err(e)) => {
+                    if self.debug {
+                        error!("Error reading {} events: {}", S::DEVICE_KIND, e);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Opens the /dev/input devices of one source kind, each read by its own blocking task.
+pub(crate) struct EvdevSourceManager<S: EvdevSource> {
+    source: S,
+    debug: bool,
+    devices: OpenedDevices,
+    sender: mpsc::UnboundedSender<S::Event>,
+    receiver: Arc<AsyncMutex<mpsc::UnboundedReceiver<S::Event>>>,
+    engine_stop_token: CancellationToken,
+}
+
+impl<S: EvdevSource + Default> EvdevSourceManager<S> {
+    pub(crate) fn new(debug: bool) -> Result<Self> {
+        Self::with_source(S::default(), debug)
+    }
+}
+
+impl<S: EvdevSource> EvdevSourceManager<S> {
+    pub(crate) fn with_source(source: S, debug: bool) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Ok(Self {
+            source,
+            debug,
+            devices: OpenedDevices::default(),
+            sender: tx,
+            receiver: Arc::new(AsyncMutex::new(rx)),
+            engine_stop_token: CancellationToken::new(),
+        })
+    }
+
+    pub(crate) fn enumerate_devices(&self) -> Result<Vec<EvdevDeviceInfo>> {
+        let mut devices = Vec::new();
+
+        for entry in std::fs::read_dir(INPUT_DEVICES_DIR)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if let Some(name) = path.file_name() {
+                if name.to_string_lossy().starts_with("event") {
+                    if let Ok(device) = Device::open(&path) {
+                        if !is_virtual_device(&device) && self.source.supports(&device) {
+                            devices.push(EvdevDeviceInfo::read(&path, &device));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(devices)
+    }
+
+    pub(crate) fn match_device(
+        &self,
+        pattern: &regex::Regex,
+        device_match: &ResolvedEvdevDeviceMatch,
+        devices: &[EvdevDeviceInfo],
+    ) -> Vec<EvdevDeviceInfo> {
+        devices
+            .iter()
+            .filter(|device| {
+                pattern.is_match(&device.name) && device_attributes_match(device_match, device)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn open_device(
+        &mut self,
+        device_info: &EvdevDeviceInfo,
+        key_name: &str,
+        grab_role: Option<GrabRole>,
+    ) -> Result<()> {
+        let sender = self.sender.clone();
+        let source = self.source.clone();
+        let info = device_info.clone();
+        let device_name = key_name.to_string();
+        let debug = self.debug;
+        let stop_token = self.engine_stop_token.child_token();
+        let device_stop_token = stop_token.clone();
+
+        let handle = task::spawn_blocking(move || {
+            match EvdevDevice::new(&info, device_name, source, sender, grab_role, debug) {
+                Ok(mut device) => device.run(stop_token),
+                Err(e) => error!("{e:#}"),
+            }
+        });
+
+        self.devices
+            .insert(key_name, &device_info.path, handle, device_stop_token);
+
+        if self.debug {
+            info!(
+                "Opened {} device: {} as {}",
+                S::DEVICE_KIND,
+                device_info.name,
+                key_name
+            );
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_device_open(&self, device_info: &EvdevDeviceInfo, key_name: &str) -> bool {
+        self.devices.is_open(key_name, &device_info.path)
+    }
+
+    pub(crate) fn has_open_devices(&self, key_name: &str) -> bool {
+        self.devices.has_key(key_name)
+    }
+
+    /// Forgets opened devices missing from `available` or no longer running,
+    /// returns their config keys and paths.
+    pub(crate) fn close_missing_devices(
+        &mut self,
+        available: &[EvdevDeviceInfo],
+    ) -> Vec<(String, PathBuf)> {
+        self.devices
+            .close_missing(available.iter().map(|device| device.path.as_path()))
+    }
+
+    pub(crate) async fn get_event(&self) -> Option<S::Event> {
+        self.receiver.lock().await.recv().await
+    }
+
+    pub(crate) async fn monitor(&mut self, name_regex: &regex::Regex) -> Result<()> {
+        let devices = self.enumerate_devices()?;
+        let matched = self.match_device(name_regex, &ResolvedEvdevDeviceMatch::default(), &devices);
+
+        if matched.is_empty() {
+            bail!("No devices found matching '{}'", name_regex);
+        }
+
+        println!("Monitoring {} devices:", S::DEVICE_KIND);
+        for device in &matched {
+            println!("  - {} @ {}", device.name, device.path.display());
+            self.open_device(device, &device.name, None)?;
+        }
+
+        println!("Press Ctrl+C to stop monitoring...");
+
+        while let Some(event) = self.get_event().await {
+            log::info!("{}", event);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn stop(&mut self) -> Result<()> {
+        self.engine_stop_token.cancel();
+
+        self.devices.stop_all();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_attributes_match() {
+        let device = EvdevDeviceInfo {
+            name: "Logitech G502".to_string(),
+            path: PathBuf::from("/dev/input/event5"),
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            phys: Some("usb-0000:00:14.0-2/input0".to_string()),
+            uniq: None,
+            by_id: vec!["usb-Logitech_G502-event-mouse".to_string()],
+            axes: Vec::new(),
+        };
+
+        assert!(device_attributes_match(
+            &ResolvedEvdevDeviceMatch::default(),
+            &device
+        ));
+
+        let by_ids = ResolvedEvdevDeviceMatch {
+            vendor_id: Some(0x046d),
+            product_id: Some(0xc08b),
+            phys_regex: Some(regex::Regex::new("-2/input0$").unwrap()),
+            by_id_regex: Some(regex::Regex::new("G502").unwrap()),
+            ..Default::default()
+        };
+        assert!(device_attributes_match(&by_ids, &device));
+
+        let other_product = ResolvedEvdevDeviceMatch {
+            product_id: Some(0xc077),
+            ..by_ids.clone()
+        };
+        assert!(!device_attributes_match(&other_product, &device));
+
+        // A device not reporting uniq doesn't match a uniq matcher.
+        let by_uniq = ResolvedEvdevDeviceMatch {
+            uniq_regex: Some(regex::Regex::new(".*").unwrap()),
+            ..Default::default()
+        };
+        assert!(!device_attributes_match(&by_uniq, &device));
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{bail, Result};
+use evdev::{Device, KeyCode, PropType};
+
+use crate::evdev_source::{EvdevDeviceInfo, EvdevEvent, EvdevSource, EvdevSourceManager};
+use crate::schemas::ResolvedGamepadDevice;
+
+/// Joysticks, gamepads, wheels and pedals: absolute axes, buttons and keys.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GamepadSource;
+
+impl EvdevSource for GamepadSource {
+    type Event = EvdevEvent;
+
+    type Stream = evdev::EventStream;
+
+    const DEVICE_KIND: &'static str = "gamepad";
+
+    fn supports(&self, device: &Device) -> bool {
+        // Absolute axes, but neither a touch surface nor a motion sensor.
+        let is_touch = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(KeyCode::BTN_TOUCH));
+        let is_accelerometer = device.properties().contains(PropType::ACCELEROMETER);
+        device.supported_absolute_axes().is_some() && !is_touch && !is_accelerometer
+    }
+
+    fn handle_event(
+        &mut self,
+        _stream: &evdev::EventStream,
+        device_key: &str,
+        event: evdev::InputEvent,
+        debug: bool,
+    ) -> Option<EvdevEvent> {
+        if !matches!(
+            event.event_type(),
+            evdev::EventType::ABSOLUTE | evdev::EventType::KEY
+        ) {
+            return None;
+        }
+        EvdevEvent::from_evdev(device_key, event, debug)
+    }
+}
+
+pub(crate) type GamepadManager = EvdevSourceManager<GamepadSource>;
+
+/// Every axis control without a configured range must be reported by the device AbsInfo,
+/// otherwise its values could not be scaled.
+pub(crate) fn check_axis_ranges(
+    resolved_device: &ResolvedGamepadDevice,
+    device_info: &EvdevDeviceInfo,
+) -> Result<()> {
+    for (control_key, control) in &resolved_device.controls {
+        if control.range.is_none()
+            && !device_info
+                .axes
+                .iter()
+                .any(|(control_type, _)| *control_type == control.r#type)
+        {
+            bail!(
+                "control '{}' has no configured range and the device doesn't report {}, \
+                set the range in the config",
+                control_key,
+                control.r#type
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ControlType, NumInterval};
+    use crate::schemas::{ResolvedEvdevDeviceMatch, ResolvedGamepadControl};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_check_axis_ranges() {
+        let control = |r#type, range| ResolvedGamepadControl {
+            r#type,
+            range,
+            _description: None,
+        };
+        let device_info = EvdevDeviceInfo {
+            name: "Pedals".to_string(),
+            path: PathBuf::from("/dev/input/event7"),
+            vendor_id: 0,
+            product_id: 0,
+            phys: None,
+            uniq: None,
+            by_id: Vec::new(),
+            axes: vec![(ControlType::AbsX, NumInterval::new(0, 1023))],
+        };
+        let mut resolved_device = ResolvedGamepadDevice {
+            enabled: true,
+            match_name_regex: None,
+            device_match: ResolvedEvdevDeviceMatch::default(),
+            grab: false,
+            controls: HashMap::from([
+                ("gas".to_string(), control(ControlType::AbsX, None)),
+                (
+                    "clutch".to_string(),
+                    control(ControlType::AbsZ, Some(NumInterval::new(0, 255))),
+                ),
+            ]),
+        };
+        assert!(check_axis_ranges(&resolved_device, &device_info).is_ok());
+
+        resolved_device
+            .controls
+            .insert("brake".to_string(), control(ControlType::AbsY, None));
+        assert!(check_axis_ranges(&resolved_device, &device_info).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::schemas::GeneratorWaveform;
+
+/// Runtime state of a generator control, advanced on every idle tick.
+#[derive(Debug, Clone)]
+pub(crate) struct Generator {
+    /// Position in the current cycle, 0..1.
+    phase: f32,
+    walk: f32,
+    rng_state: u64,
+}
+
+impl Generator {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            phase: 0.0,
+            walk: 0.0,
+            // NB: xorshift never leaves the all zero state.
+            rng_state: seed | 1,
+        }
+    }
+
+    /// Advances by `dt` seconds at `frequency` cycles per second, returns the waveform value in -1..1.
+    /// NB: the phase is accumulated, so a modulated frequency doesn't make the waveform jump.
+    pub(crate) fn advance(
+        &mut self,
+        waveform: GeneratorWaveform,
+        steps: &[f32],
+        frequency: f32,
+        dt: f32,
+    ) -> f32 {
+        let cycles = frequency.max(0.0) * dt.max(0.0);
+        self.phase = (self.phase + cycles).rem_euclid(1.0);
+        match waveform {
+            GeneratorWaveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            GeneratorWaveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            GeneratorWaveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            GeneratorWaveform::Ramp => 2.0 * self.phase - 1.0,
+            GeneratorWaveform::Constant => 0.0,
+            GeneratorWaveform::RandomWalk => {
+                // Steps scale with the square root of time, like a Brownian motion.
+                self.walk += self.next_random() * 2.0 * cycles.sqrt();
+                // Reflect off the bounds instead of sticking to them.
+                if self.walk > 1.0 {
+                    self.walk = 2.0 - self.walk;
+                } else if self.walk < -1.0 {
+                    self.walk = -2.0 - self.walk;
+                }
+                self.walk = self.walk.clamp(-1.0, 1.0);
+                self.walk
+            }
+            GeneratorWaveform::StepSequence => {
+                let index = (self.phase * steps.len() as f32) as usize;
+                steps
+                    .get(index)
+                    .or(steps.last())
+                    .copied()
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// Seed of a generator without a configured `seed`, stable across runs (FNV-1a of the keys).
+    pub(crate) fn seed_from_key(source_key: &str, control_key: &str) -> u64 {
+        [source_key.as_bytes(), b"/", control_key.as_bytes()]
+            .concat()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    /// Uniform in -1..1 (xorshift64).
+    fn next_random(&mut self) -> f32 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        (x >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic_waveforms() {
+        let sample = |waveform, dt| Generator::new(1).advance(waveform, &[], 1.0, dt);
+        assert!((sample(GeneratorWaveform::Sine, 0.25) - 1.0).abs() < 1e-6);
+        assert_eq!(sample(GeneratorWaveform::Triangle, 0.0), -1.0);
+        assert_eq!(sample(GeneratorWaveform::Triangle, 0.5), 1.0);
+        assert_eq!(sample(GeneratorWaveform::Square, 0.25), 1.0);
+        assert_eq!(sample(GeneratorWaveform::Square, 0.75), -1.0);
+        assert_eq!(sample(GeneratorWaveform::Ramp, 0.75), 0.5);
+        assert_eq!(sample(GeneratorWaveform::Constant, 0.3), 0.0);
+
+        // The phase accumulates over ticks and wraps around.
+        let mut generator = Generator::new(1);
+        for _ in 0..5 {
+            generator.advance(GeneratorWaveform::Ramp, &[], 2.0, 0.1);
+        }
+        let ramp = generator.advance(GeneratorWaveform::Ramp, &[], 2.0, 0.125);
+        assert!((ramp - -0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_step_sequence_and_random_walk() {
+        let steps = [0.0, 0.5, 1.0, -1.0];
+        let mut generator = Generator::new(1);
+        let values: Vec<f32> = (0..4)
+            .map(|_| generator.advance(GeneratorWaveform::StepSequence, &steps, 1.0, 0.25))
+            .collect();
+        assert_eq!(values, vec![0.5, 1.0, -1.0, 0.0]);
+
+        let mut generator = Generator::new(42);
+        let walk: Vec<f32> = (0..1000)
+            .map(|_| generator.advance(GeneratorWaveform::RandomWalk, &[], 5.0, 0.01))
+            .collect();
+        assert!(walk.iter().all(|value| (-1.0..=1.0).contains(value)));
+        assert!(walk.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_seed_from_key() {
+        let seed = Generator::seed_from_key("lfo", "Wobble");
+        assert_eq!(seed, Generator::seed_from_key("lfo", "Wobble"));
+        assert_ne!(seed, Generator::seed_from_key("lfo", "Wobble 2"));
+        assert_ne!(seed, Generator::seed_from_key("lf", "oWobble"));
+
+        // The same seed repeats the same random walk.
+        let walk = |seed| {
+            let mut generator = Generator::new(seed);
+            (0..100)
+                .map(|_| generator.advance(GeneratorWaveform::RandomWalk, &[], 5.0, 0.01))
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(walk(seed), walk(seed));
+        assert_ne!(walk(seed), walk(seed + 2));
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use evdev::{AttributeSetRef, Device, KeyCode};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::common::ControlType;
+use crate::evdev_source::{EvdevSource, EvdevSourceManager, EvdevStream};
+
+/// Device key of the devices opened only to watch for the emergency combo.
+pub(crate) const COMBO_WATCH_DEVICE_KEY: &str = "<-- emergency combo watch -->";
+
+/// Shared between all grabbed input devices of a running engine: tracks keys/buttons held
+/// on any opened device and releases every grab once the emergency combo is held.
+#[derive(Debug)]
+pub(crate) struct GrabController {
+    emergency_combo: Vec<ControlType>,
+    held: Mutex<HashSet<ControlType>>,
+    released: AtomicBool,
+}
+
+impl GrabController {
+    pub(crate) fn new(emergency_combo: Vec<ControlType>) -> Self {
+        Self {
+            emergency_combo,
+            held: Mutex::new(HashSet::new()),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn is_released(&self) -> bool {
+        self.released.load(Ordering::Relaxed)
+    }
+
+    /// Feeds a key/button event (value 0 is release, anything else is press/repeat).
+    pub(crate) fn observe(&self, control_type: ControlType, value: i32) {
+        if self.emergency_combo.is_empty()
+            || self.is_released()
+            || !(control_type.is_key() || control_type.is_button())
+        {
+            return;
+        }
+        let mut held = self.held.lock().unwrap();
+        if value == 0 {
+            held.remove(&control_type);
+        } else {
+            held.insert(control_type);
+        }
+        if self.emergency_combo.iter().all(|c| held.contains(c)) {
+            self.released.store(true, Ordering::Relaxed);
+            warn!(
+                "Emergency combo {:?} pressed, releasing all grabbed input devices \
+                until the engine is restarted.",
+                self.emergency_combo
+            );
+        }
+    }
+}
+
+/// Part of an opened input device in the exclusive grab.
+#[derive(Debug, Clone)]
+pub(crate) enum GrabRole {
+    /// Taken exclusively, its keys and buttons count for the emergency combo.
+    Grab(Arc<GrabController>),
+    /// Not taken, only its keys and buttons count for the emergency combo.
+    Watch(Arc<GrabController>),
+}
+
+/// Exclusive grab of one evdev device, released on engine stop or on the emergency combo.
+/// NB: the kernel drops the grab itself when the device fd is closed (incl. a crash).
+pub(crate) struct DeviceGrab {
+    controller: Arc<GrabController>,
+    grabbed: bool,
+}
+
+impl DeviceGrab {
+    pub(crate) fn acquire(
+        controller: Arc<GrabController>,
+        device: &mut impl EvdevStream,
+        device_name: &str,
+    ) -> Result<Self> {
+        let mut grab = Self {
+            controller,
+            grabbed: false,
+        };
+        if !grab.controller.is_released() {
+            device
+                .grab()
+                .with_context(|| format!("Can't grab the device {device_name}"))?;
+            grab.grabbed = true;
+            info!("Grabbed input device {} exclusively.", device_name);
+        }
+        Ok(grab)
+    }
+
+    pub(crate) fn from_role(
+        role: GrabRole,
+        device: &mut impl EvdevStream,
+        device_name: &str,
+    ) -> Result<Self> {
+        match role {
+            GrabRole::Grab(controller) => Self::acquire(controller, device, device_name),
+            GrabRole::Watch(controller) => Ok(Self::watch(controller)),
+        }
+    }
+
+    /// Only feeds the device events to the emergency combo, the device is not grabbed.
+    pub(crate) fn watch(controller: Arc<GrabController>) -> Self {
+        Self {
+            controller,
+            grabbed: false,
+        }
+    }
+
+    pub(crate) fn observe(&self, control_type: ControlType, value: i32) {
+        self.controller.observe(control_type, value);
+    }
+
+    /// Releases the grab if the emergency combo was pressed on any device.
+    pub(crate) fn update(&mut self, device: &mut impl EvdevStream, device_name: &str) {
+        if self.grabbed && self.controller.is_released() {
+            self.release(device, device_name);
+        }
+    }
+
+    pub(crate) fn release(&mut self, device: &mut impl EvdevStream, device_name: &str) {
+        if !self.grabbed {
+            return;
+        }
+        match device.ungrab() {
+            Ok(()) => info!("Released grabbed input device {}.", device_name),
+            Err(e) => warn!("Failed to release grabbed device {}: {}", device_name, e),
+        }
+        self.grabbed = false;
+    }
+}
+
+/// Devices able to press any emergency combo key or button. While any source device is
+/// grabbed they are opened without grabbing, so the combo works even if no grabbed device
+/// has the combo keys (e.g. only a mouse is grabbed).
+#[derive(Debug, Clone)]
+pub(crate) struct ComboWatchSource {
+    combo_keys: Vec<KeyCode>,
+}
+
+impl ComboWatchSource {
+    pub(crate) fn new(emergency_combo: &[ControlType]) -> Self {
+        Self {
+            combo_keys: emergency_combo
+                .iter()
+                .filter(|control_type| control_type.is_key() || control_type.is_button())
+                .map(|control_type| KeyCode::new(u16::from(*control_type)))
+                .collect(),
+        }
+    }
+
+    fn reports_combo_keys(&self, keys: &AttributeSetRef<KeyCode>) -> bool {
+        self.combo_keys.iter().any(|key| keys.contains(*key))
+    }
+}
+
+impl EvdevSource for ComboWatchSource {
+    type Event = Infallible;
+
+    type Stream = evdev::EventStream;
+
+    const DEVICE_KIND: &'static str = "emergency combo";
+
+    fn supports(&self, device: &Device) -> bool {
+        device
+            .supported_keys()
+            .is_some_and(|keys| self.reports_combo_keys(keys))
+    }
+
+    fn handle_event(
+        &mut self,
+        _stream: &evdev::EventStream,
+        _device_key: &str,
+        _event: evdev::InputEvent,
+        _debug: bool,
+    ) -> Option<Infallible> {
+        None
+    }
+}
+
+pub(crate) type ComboWatchManager = EvdevSourceManager<ComboWatchSource>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emergency_combo_requires_all_held() {
+        let controller = GrabController::new(vec![
+            ControlType::KeyLeftctrl,
+            ControlType::KeyLeftalt,
+            ControlType::KeyEsc,
+        ]);
+
+        controller.observe(ControlType::KeyLeftctrl, 1);
+        controller.observe(ControlType::KeyEsc, 1);
+        assert!(!controller.is_released());
+
+        // Released key no longer counts.
+        controller.observe(ControlType::KeyEsc, 0);
+        controller.observe(ControlType::KeyLeftalt, 1);
+        assert!(!controller.is_released());
+
+        // Autorepeat (2) counts as held.
+        controller.observe(ControlType::KeyEsc, 2);
+        assert!(controller.is_released());
+    }
+
+    #[test]
+    fn test_empty_combo_never_releases() {
+        let controller = GrabController::new(vec![]);
+        controller.observe(ControlType::BtnLeft, 1);
+        assert!(!controller.is_released());
+    }
+
+    #[test]
+    fn test_mouse_only_grab_released_by_watched_keyboard() {
+        let combo = vec![
+            ControlType::KeyLeftctrl,
+            ControlType::KeyLeftalt,
+            ControlType::KeyEsc,
+        ];
+        let controller = Arc::new(GrabController::new(combo.clone()));
+
+        // Only a keyboard with the combo keys is watched, not the grabbed mouse.
+        let watch = ComboWatchSource::new(&combo);
+        let keyboard_keys: evdev::AttributeSet<KeyCode> = [
+            KeyCode::KEY_ESC,
+            KeyCode::KEY_A,
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_LEFTALT,
+        ]
+        .into_iter()
+        .collect();
+        let mouse_keys: evdev::AttributeSet<KeyCode> = [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT]
+            .into_iter()
+            .collect();
+        assert!(watch.reports_combo_keys(&keyboard_keys));
+        assert!(!watch.reports_combo_keys(&mouse_keys));
+
+        // The watched keyboard is not grabbed but its keys release the mouse grab.
+        let keyboard = DeviceGrab::watch(controller.clone());
+        assert!(!keyboard.grabbed);
+        keyboard.observe(ControlType::KeyLeftctrl, 1);
+        keyboard.observe(ControlType::KeyLeftalt, 1);
+        assert!(!controller.is_released());
+        keyboard.observe(ControlType::KeyEsc, 1);
+        assert!(controller.is_released());
+    }
+}
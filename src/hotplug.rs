@@ -0,0 +1,176 @@
+use anyhow::Result;
+use log::{error, warn};
+use notify_debouncer_full::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio::time::{interval_at, Duration, Instant, Interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+pub(crate) const INPUT_DEVICES_DIR: &str = "/dev/input";
+
+/// Device types to rescan after a `DeviceWatcher` notification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RescanScope {
+    pub(crate) input_devices: bool,
+    pub(crate) midi_ports: bool,
+}
+
+/// Watches `/dev/input` nodes via inotify and polls ALSA sequencer ports periodically
+/// (midir has no port change notifications). Falls back to polling `/dev/input` as well
+/// if it can't be watched.
+pub(crate) struct DeviceWatcher {
+    input_changes: Option<mpsc::Receiver<()>>,
+    _debouncer: Option<Debouncer<RecommendedWatcher, RecommendedCache>>,
+    ticker: Option<Interval>,
+}
+
+impl DeviceWatcher {
+    /// A zero `poll_interval` disables hot-plugging altogether.
+    pub(crate) fn new(poll_interval: Duration) -> Self {
+        if poll_interval.is_zero() {
+            return Self {
+                input_changes: None,
+                _debouncer: None,
+                ticker: None,
+            };
+        }
+
+        let mut ticker = interval_at(Instant::now() + poll_interval, poll_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let (input_changes, debouncer) = match Self::watch_input_devices_dir() {
+            Ok((rx, debouncer)) => (Some(rx), Some(debouncer)),
+            Err(e) => {
+                warn!(
+                    "Can't watch {} for device changes, polling it instead: {:#}",
+                    INPUT_DEVICES_DIR, e
+                );
+                (None, None)
+            }
+        };
+
+        Self {
+            input_changes,
+            _debouncer: debouncer,
+            ticker: Some(ticker),
+        }
+    }
+
+    fn watch_input_devices_dir() -> Result<(
+        mpsc::Receiver<()>,
+        Debouncer<RecommendedWatcher, RecommendedCache>,
+    )> {
+        let (tx, rx) = mpsc::channel(1);
+        // NB: udev sets node permissions shortly after creation, the debounce timeout
+        // NB: lets it settle and the resulting metadata change triggers another rescan.
+        let mut debouncer = new_debouncer(
+            Duration::from_millis(500),
+            None,
+            move |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    if !events.is_empty() {
+                        // A pending notification already covers this one.
+                        let _ = tx.try_send(());
+                    }
+                }
+                Err(e) => error!("Input devices watch error: {:?}", e),
+            },
+        )?;
+        debouncer.watch(INPUT_DEVICES_DIR, RecursiveMode::NonRecursive)?;
+        Ok((rx, debouncer))
+    }
+
+    /// Waits for a possible device change, never returns if hot-plugging is disabled.
+    pub(crate) async fn changed(&mut self) -> RescanScope {
+        let Some(ticker) = self.ticker.as_mut() else {
+            return std::future::pending().await;
+        };
+        match self.input_changes.as_mut() {
+            Some(input_changes) => tokio::select! {
+                Some(()) = input_changes.recv() => RescanScope {
+                    input_devices: true,
+                    midi_ports: false,
+                },
+                _ = ticker.tick() => RescanScope {
+                    input_devices: false,
+                    midi_ports: true,
+                },
+            },
+            None => {
+                ticker.tick().await;
+                RescanScope {
+                    input_devices: true,
+                    midi_ports: true,
+                }
+            }
+        }
+    }
+}
+
+/// Run tasks of opened evdev devices by config device key and device node path.
+#[derive(Default)]
+pub(crate) struct OpenedDevices {
+    devices: HashMap<(String, PathBuf), (task::JoinHandle<()>, CancellationToken)>,
+}
+
+impl OpenedDevices {
+    pub(crate) fn insert(
+        &mut self,
+        key_name: &str,
+        path: &Path,
+        handle: task::JoinHandle<()>,
+        stop_token: CancellationToken,
+    ) {
+        self.devices.insert(
+            (key_name.to_string(), path.to_path_buf()),
+            (handle, stop_token),
+        );
+    }
+
+    /// True if the device node is opened for the key and its run task is still alive.
+    pub(crate) fn is_open(&self, key_name: &str, path: &Path) -> bool {
+        self.devices
+            .get(&(key_name.to_string(), path.to_path_buf()))
+            .is_some_and(|(handle, _)| !handle.is_finished())
+    }
+
+    pub(crate) fn has_key(&self, key_name: &str) -> bool {
+        self.devices.keys().any(|(key, _)| key == key_name)
+    }
+
+    /// Forgets devices whose node is gone or whose run task has ended (unplugged device
+    /// read error, failed open), so they can be reopened. Returns their keys and paths.
+    pub(crate) fn close_missing<'a>(
+        &mut self,
+        present_paths: impl IntoIterator<Item = &'a Path>,
+    ) -> Vec<(String, PathBuf)> {
+        let present_paths: Vec<&Path> = present_paths.into_iter().collect();
+        let closed: Vec<(String, PathBuf)> = self
+            .devices
+            .iter()
+            .filter(|((_, path), (handle, _))| {
+                handle.is_finished() || !present_paths.contains(&path.as_path())
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &closed {
+            if let Some((handle, stop_token)) = self.devices.remove(id) {
+                stop_token.cancel();
+                handle.abort();
+            }
+        }
+        closed
+    }
+
+    /// NB: the owning manager cancels the parent stop token first.
+    pub(crate) fn stop_all(&mut self) {
+        for (_, (handle, _)) in self.devices.drain() {
+            // NB: for a blocking parallel task (our case) it's a noop if it is running.
+            //     but can prevent the task from running if it's not already started.
+            handle.abort();
+        }
+    }
+}
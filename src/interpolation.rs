@@ -92,7 +92,7 @@ impl ValueFilter {
         } else {
             1.0
         };
-        return prev_val + alpha * (current_input - prev_val);
+        prev_val + alpha * (current_input - prev_val)
     }
 
     pub(crate) fn _moving_average_weighted(
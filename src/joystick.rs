@@ -2,20 +2,26 @@ use anyhow::{bail, Context, Result};
 use evdev::uinput::{VirtualDevice, VirtualEventStream};
 use evdev::{
     AbsInfo, AbsoluteAxisCode, AttributeSet, EvdevEnum, FFEffectCode, InputEvent, KeyCode,
-    UinputAbsSetup,
+    RelativeAxisCode, UinputAbsSetup,
 };
 use num_traits::Zero;
 
 use crate::schemas::ResolvedVirtualJoystick as JoystickConfig;
+use crate::schemas::{HatDirection, KeyThresholds, ResolvedKeyRepeat, SocdPolicy};
 use atomic_float::AtomicF32;
 use log::{debug, info, warn};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
+/// Physical path of the virtual devices, lets the evdev sources skip them instead of
+/// feeding MMVJ's own output back in as a gamepad, keyboard or mouse.
+pub(crate) const VIRTUAL_DEVICE_PHYS: &std::ffi::CStr = c"mmvj/virtual";
+
 type FfIndexT = usize;
 const VJ_FF_MAX_EFFECTS: crate::joystick::FfIndexT = 1; // TODO: VjFfIndexT::MAX - 1;
 
@@ -39,12 +45,84 @@ struct FfWorkingState {
 }
 
 type FfPlayedSummT = AtomicF32;
+
+/// Position of a hat switch composed from the held directions.
+struct HatState {
+    x: crate::common::ControlType,
+    y: crate::common::ControlType,
+    socd: SocdPolicy,
+    diagonals: bool,
+    /// Held directions in press order, the latest last.
+    held: Vec<HatDirection>,
+    position: (i32, i32),
+}
+
+impl HatState {
+    fn new(hat: &crate::schemas::ResolvedHatSwitch) -> Self {
+        Self {
+            x: hat.x,
+            y: hat.y,
+            socd: hat.socd,
+            diagonals: hat.diagonals,
+            held: Vec::new(),
+            position: (0, 0),
+        }
+    }
+
+    fn set_direction(&mut self, direction: HatDirection, pressed: bool) {
+        self.held.retain(|held| *held != direction);
+        if pressed {
+            self.held.push(direction);
+        }
+    }
+
+    /// The (x, y) position the held directions give, y is -1 for up as with evdev hats.
+    fn compose(&self) -> (i32, i32) {
+        let order = |direction| self.held.iter().position(|held| *held == direction);
+        // The axis value and the press order of the direction that gives it.
+        let axis = |negative, positive| match (order(negative), order(positive)) {
+            (None, None) => (0, None),
+            (Some(n), None) => (-1, Some(n)),
+            (None, Some(p)) => (1, Some(p)),
+            (Some(n), Some(p)) => match self.socd {
+                SocdPolicy::LastWins if n > p => (-1, Some(n)),
+                SocdPolicy::LastWins => (1, Some(p)),
+                SocdPolicy::FirstWins if n < p => (-1, Some(n)),
+                SocdPolicy::FirstWins => (1, Some(p)),
+                SocdPolicy::Neutral => (0, None),
+            },
+        };
+        let (x, x_order) = axis(HatDirection::Left, HatDirection::Right);
+        let (y, y_order) = axis(HatDirection::Up, HatDirection::Down);
+        if let (false, Some(x_order), Some(y_order)) = (self.diagonals, x_order, y_order) {
+            // NB: of a diagonal a 4-way hat keeps the direction the SOCD policy would.
+            let x_wins = match self.socd {
+                SocdPolicy::FirstWins => x_order < y_order,
+                _ => x_order > y_order,
+            };
+            return if x_wins { (x, 0) } else { (0, y) };
+        }
+        (x, y)
+    }
+}
+
+/// Press behaviour of a button or key control.
+struct KeyOutput {
+    thresholds: Option<KeyThresholds>,
+    repeat: Option<ResolvedKeyRepeat>,
+    next_repeat: Option<Instant>,
+}
+
 pub(crate) struct VirtualJoystick {
     config_key: String,
     name: String,
     platform_device_api: Arc<Mutex<evdev::uinput::VirtualEventStream>>,
     control_states: HashMap<String, i32>,
     control_info: HashMap<String, crate::common::ControlType>,
+    key_outputs: HashMap<String, KeyOutput>,
+    /// Fractions of counts not yet emitted on relative axes.
+    relative_remainders: HashMap<String, f32>,
+    hats: HashMap<String, HatState>,
     ff_enabled: bool,
     ff_played_summ_norm: Arc<crate::joystick::FfPlayedSummT>,
     ff_input_join_handle: Option<JoinHandle<()>>,
@@ -69,19 +147,34 @@ impl VirtualJoystick {
                 config.properties.vendor_id,
                 config.properties.product_id,
                 config.properties.version,
-            ));
+            ))
+            .with_phys(VIRTUAL_DEVICE_PHYS)
+            .context("Failed to set virtual device phys")?;
 
         let mut keys = AttributeSet::<KeyCode>::new();
         let mut abs_axes = AttributeSet::<AbsoluteAxisCode>::new();
+        let mut rel_axes = AttributeSet::<RelativeAxisCode>::new();
         let mut control_states = HashMap::new();
         let mut control_info = HashMap::new();
+        let mut key_outputs = HashMap::new();
+        let mut relative_remainders = HashMap::new();
 
         for (control_name, control_config) in &config.controls {
-            if control_config.r#type.is_button() {
+            // NB: keyboard keys are EV_KEY codes just like buttons. A device with keys only
+            // NB: is seen as a keyboard by the system.
+            if control_config.r#type.is_button() || control_config.r#type.is_key() {
                 let evdev_key_code_int = control_config.r#type.into();
                 keys.insert(KeyCode::new(evdev_key_code_int));
                 control_states.insert(control_name.clone(), control_config.initial_value);
                 control_info.insert(control_name.clone(), control_config.r#type);
+                key_outputs.insert(
+                    control_name.clone(),
+                    KeyOutput {
+                        thresholds: control_config.thresholds,
+                        repeat: control_config.repeat,
+                        next_repeat: None,
+                    },
+                );
             } else if control_config.r#type.is_absolute() {
                 let abs_info = AbsInfo::new(
                     control_config.initial_value,
@@ -113,10 +206,34 @@ impl VirtualJoystick {
                 abs_axes.insert(evdev_axis_code);
                 control_states.insert(control_name.clone(), control_config.initial_value);
                 control_info.insert(control_name.clone(), control_config.r#type);
+            } else if control_config.r#type.is_relative() {
+                rel_axes.insert(RelativeAxisCode(control_config.r#type.into()));
+                control_states.insert(control_name.clone(), 0);
+                control_info.insert(control_name.clone(), control_config.r#type);
+                relative_remainders.insert(control_name.clone(), 0.0);
+            }
+        }
+
+        let mut hats = HashMap::new();
+        for (hat_name, hat_config) in &config.hats {
+            for axis in [hat_config.x, hat_config.y] {
+                let abs_setup = UinputAbsSetup::new(
+                    AbsoluteAxisCode(axis.into()),
+                    AbsInfo::new(0, -1, 1, 0, 0, 0),
+                );
+                evdev_builder = evdev_builder
+                    .with_absolute_axis(&abs_setup)
+                    .context("Failed to setup joystick with hat axis")?;
             }
+            hats.insert(hat_name.clone(), HatState::new(hat_config));
         }
 
         evdev_builder = evdev_builder.with_keys(&keys)?;
+        if rel_axes.iter().next().is_some() {
+            evdev_builder = evdev_builder
+                .with_relative_axes(&rel_axes)
+                .context("Failed to setup virtual device with relative axes")?;
+        }
 
         if config.is_ff_enabled() {
             info!("Enabling Force Feedback for '{joystick_config_key}'");
@@ -143,6 +260,9 @@ impl VirtualJoystick {
             )),
             control_states,
             control_info,
+            key_outputs,
+            relative_remainders,
+            hats,
             ff_enabled: config.is_ff_enabled(),
             ff_played_summ_norm: Arc::new(0.0.into()),
             ff_input_join_handle: None,
@@ -384,10 +504,26 @@ impl VirtualJoystick {
     ) -> Result<()> {
         if let Some(current_value) = self.control_states.get_mut(control_name) {
             if let Some(control_type) = self.control_info.get(control_name) {
-                if control_type.is_button() {
+                if control_type.is_button() || control_type.is_key() {
                     // We may map arbitrary floating point values to button and we count any
-                    // non-zero one as "button on", that is value 1.
-                    *current_value = (!value.is_zero()).into();
+                    // non-zero one (or one past the thresholds) as "button on", that is value 1.
+                    let was_pressed = !current_value.is_zero();
+                    let key_output = self
+                        .key_outputs
+                        .get_mut(control_name)
+                        .expect("Every button and key has its output state");
+                    let pressed = KeyThresholds::is_pressed(
+                        key_output.thresholds.as_ref(),
+                        was_pressed,
+                        value,
+                    );
+                    if pressed != was_pressed {
+                        key_output.next_repeat = key_output
+                            .repeat
+                            .filter(|_| pressed)
+                            .map(|repeat| Instant::now() + repeat.delay);
+                    }
+                    *current_value = pressed.into();
                     let event = InputEvent::new(
                         evdev::EventType::KEY.0,
                         (*control_type).into(),
@@ -412,6 +548,26 @@ impl VirtualJoystick {
                         .device_mut()
                         .emit(&[event])
                         .context("Failed to emit axis event")?;
+                } else if control_type.is_relative() {
+                    // NB: the value is a movement, not a position. The state is the last one.
+                    let remainder = self
+                        .relative_remainders
+                        .get_mut(control_name)
+                        .expect("Every relative axis has its remainder");
+                    *current_value = take_whole_counts(remainder, value);
+                    if !current_value.is_zero() {
+                        let event = InputEvent::new(
+                            evdev::EventType::RELATIVE.0,
+                            (*control_type).into(),
+                            *current_value,
+                        );
+                        self.platform_device_api
+                            .lock()
+                            .unwrap()
+                            .device_mut()
+                            .emit(&[event])
+                            .context("Failed to emit relative axis event")?;
+                    }
                 }
             }
 
@@ -433,6 +589,82 @@ impl VirtualJoystick {
         self.control_states.get(control_name).copied().unwrap_or(0)
     }
 
+    pub(crate) fn set_hat_direction(
+        &mut self,
+        hat_name: &str,
+        direction: HatDirection,
+        pressed: bool,
+        silent: bool,
+    ) -> Result<()> {
+        let Some(hat) = self.hats.get_mut(hat_name) else {
+            bail!("Hat '{}' not found in joystick '{}'", hat_name, self.name);
+        };
+        hat.set_direction(direction, pressed);
+        let (x, y) = hat.compose();
+        let mut events = Vec::new();
+        if x != hat.position.0 {
+            events.push(InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                hat.x.into(),
+                x,
+            ));
+        }
+        if y != hat.position.1 {
+            events.push(InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                hat.y.into(),
+                y,
+            ));
+        }
+        hat.position = (x, y);
+        if !events.is_empty() {
+            self.platform_device_api
+                .lock()
+                .unwrap()
+                .device_mut()
+                .emit(&events)
+                .context("Failed to emit hat events")?;
+        }
+
+        if self.debug && !silent {
+            debug!(
+                "[{}][{}] {:?} = {} -> ({}, {})",
+                self.name, hat_name, direction, pressed, x, y
+            );
+        }
+        Ok(())
+    }
+
+    /// Emits the autorepeat events due by `now` for the held keys with `repeat` set.
+    fn process_key_repeats(&mut self, now: Instant) -> Result<()> {
+        let mut events = Vec::new();
+        for (control_name, key_output) in &mut self.key_outputs {
+            let (Some(repeat), Some(next_repeat)) = (key_output.repeat, key_output.next_repeat)
+            else {
+                continue;
+            };
+            if next_repeat > now {
+                continue;
+            }
+            // NB: a late tick doesn't emit a burst to catch up.
+            key_output.next_repeat = Some((next_repeat + repeat.interval).max(now));
+            events.push(InputEvent::new(
+                evdev::EventType::KEY.0,
+                self.control_info[control_name].into(),
+                2,
+            ));
+        }
+        if !events.is_empty() {
+            self.platform_device_api
+                .lock()
+                .unwrap()
+                .device_mut()
+                .emit(&events)
+                .context("Failed to emit key repeat events")?;
+        }
+        Ok(())
+    }
+
     // Get the current constant force feedback level.
     pub(crate) fn get_ff_played_summ_norm(&self) -> f32 {
         if !self.ff_enabled {
@@ -442,6 +674,15 @@ impl VirtualJoystick {
     }
 }
 
+/// Adds `value` to the carried `remainder` and takes out the whole counts, so that fractional
+/// movements add up over events instead of being lost.
+fn take_whole_counts(remainder: &mut f32, value: f32) -> i32 {
+    let total = *remainder + value;
+    let counts = total.trunc();
+    *remainder = total - counts;
+    counts as i32
+}
+
 #[derive(Clone)]
 pub(crate) struct VirtualJoystickManager {
     debug: bool,
@@ -521,6 +762,25 @@ impl VirtualJoystickManager {
         }
     }
 
+    pub(crate) fn set_hat_direction(
+        &self,
+        joystick_name: &str,
+        hat_name: &str,
+        direction: HatDirection,
+        pressed: bool,
+        silent: bool,
+    ) -> Result<()> {
+        let mut joysticks = self.joysticks.lock().unwrap();
+        if let Some(joystick) = joysticks.get_mut(joystick_name) {
+            joystick.set_hat_direction(hat_name, direction, pressed, silent)
+        } else {
+            if self.debug {
+                warn!("Joystick '{}' not found", joystick_name);
+            }
+            Ok(())
+        }
+    }
+
     pub(crate) fn get_control_state(&self, joystick_name: &str, control_name: &str) -> i32 {
         let joysticks = self.joysticks.lock().unwrap();
         if let Some(joystick) = joysticks.get(joystick_name) {
@@ -530,6 +790,15 @@ impl VirtualJoystickManager {
         }
     }
 
+    pub(crate) fn process_key_repeats(&self) -> Result<()> {
+        let now = Instant::now();
+        let mut joysticks = self.joysticks.lock().unwrap();
+        for joystick in joysticks.values_mut() {
+            joystick.process_key_repeats(now)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn get_ff_constant_force_norm(&self, joystick_name: &str) -> f32 {
         let joysticks = self.joysticks.lock().unwrap();
         if let Some(joystick) = joysticks.get(joystick_name) {
@@ -563,3 +832,56 @@ impl VirtualJoystickManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hat_composition() {
+        use HatDirection::*;
+        let hat = |socd, diagonals| HatState {
+            x: crate::common::ControlType::AbsHat0X,
+            y: crate::common::ControlType::AbsHat0Y,
+            socd,
+            diagonals,
+            held: Vec::new(),
+            position: (0, 0),
+        };
+        let press = |hat: &mut HatState, directions: &[HatDirection]| {
+            for direction in directions {
+                hat.set_direction(*direction, true);
+            }
+            hat.compose()
+        };
+
+        let mut last_wins = hat(SocdPolicy::LastWins, true);
+        assert_eq!(press(&mut last_wins, &[Up, Right]), (1, -1));
+        assert_eq!(press(&mut last_wins, &[Left]), (-1, -1));
+        last_wins.set_direction(Left, false);
+        assert_eq!(last_wins.compose(), (1, -1));
+
+        let mut first_wins = hat(SocdPolicy::FirstWins, true);
+        assert_eq!(press(&mut first_wins, &[Down, Up]), (0, 1));
+
+        let mut neutral = hat(SocdPolicy::Neutral, true);
+        assert_eq!(press(&mut neutral, &[Left, Right, Up]), (0, -1));
+
+        // 4-way: of a diagonal the latest direction counts.
+        let mut four_way = hat(SocdPolicy::LastWins, false);
+        assert_eq!(press(&mut four_way, &[Up, Left]), (-1, 0));
+        four_way.set_direction(Left, false);
+        assert_eq!(four_way.compose(), (0, -1));
+    }
+
+    #[test]
+    fn test_take_whole_counts() {
+        let mut remainder = 0.0;
+        let counts: Vec<i32> = [0.4, 0.4, 0.4, 2.5, -0.6, -1.9]
+            .into_iter()
+            .map(|value| take_whole_counts(&mut remainder, value))
+            .collect();
+        assert_eq!(counts, vec![0, 0, 1, 2, 0, -1]);
+        assert!((remainder - -0.8).abs() < 1e-5);
+    }
+}
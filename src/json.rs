@@ -0,0 +1,464 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{UdpSocket, UnixListener};
+use tokio::sync::mpsc;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task;
+use tokio::time::{interval_at, Duration, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::schemas::{JsonEndpoint, ResolvedJsonSource};
+
+const MAX_JSON_DATAGRAM_SIZE: usize = 65536;
+/// Pause after a failed receive or accept, so a persistent socket error doesn't spin the task.
+const RECEIVE_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub(crate) struct JsonEvent {
+    pub(crate) source_key: String,
+    pub(crate) control: String,
+    pub(crate) value: f32,
+}
+
+/// One pushed value, e.g. `{"control":"throttle","value":0.42}`. Other fields are ignored.
+#[derive(Debug, Deserialize)]
+struct JsonValueMessage {
+    control: String,
+    value: JsonNumber,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonNumber {
+    Number(f64),
+    Bool(bool),
+}
+
+/// Parses a JSON value message, booleans map to 1/0.
+pub(crate) fn parse_json_message(text: &str) -> Result<(String, f32)> {
+    let message: JsonValueMessage = serde_json::from_str(text)
+        .context("Expected {\"control\": <name>, \"value\": <number>}")?;
+    let value = match message.value {
+        JsonNumber::Number(v) => v as f32,
+        JsonNumber::Bool(v) => {
+            if v {
+                1.0
+            } else {
+                0.0
+            }
+        }
+    };
+    if !value.is_finite() {
+        bail!("Value of '{}' is not a finite number", message.control);
+    }
+    Ok((message.control, value))
+}
+
+/// Message counters of a source since the last report.
+#[derive(Debug, Default)]
+pub(crate) struct JsonSourceStats {
+    malformed: u64,
+    unknown: u64,
+    per_control: BTreeMap<String, u64>,
+}
+
+impl JsonSourceStats {
+    fn is_empty(&self) -> bool {
+        self.malformed == 0 && self.unknown == 0 && self.per_control.is_empty()
+    }
+
+    /// Rates over `elapsed`, the counters are reset.
+    pub(crate) fn take_report(&mut self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f32().max(f32::EPSILON);
+        let total: u64 = self.per_control.values().sum();
+        let per_control: Vec<String> = self
+            .per_control
+            .iter()
+            .map(|(control, count)| format!("{} {:.1}/s", control, *count as f32 / secs))
+            .collect();
+        let report = format!(
+            "{:.1} msg/s [{}], {} malformed, {} unknown control",
+            total as f32 / secs,
+            per_control.join(", "),
+            self.malformed,
+            self.unknown
+        );
+        *self = Self::default();
+        report
+    }
+}
+
+/// Turns received text into events, shared by the receive tasks of a source.
+struct JsonIngest {
+    source_key: String,
+    /// Control names the source declares, `None` passes on everything (monitor mode).
+    control_names: Option<HashSet<String>>,
+    sender: mpsc::UnboundedSender<JsonEvent>,
+    stats: JsonSourceStats,
+    debug: bool,
+}
+
+impl JsonIngest {
+    /// A datagram or a stream chunk may carry several newline separated messages.
+    fn ingest(&mut self, text: &str, peer: &str) {
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match parse_json_message(line) {
+                Ok((control, value)) => {
+                    if self
+                        .control_names
+                        .as_ref()
+                        .is_some_and(|names| !names.contains(&control))
+                    {
+                        self.stats.unknown += 1;
+                        if self.debug {
+                            debug!(
+                                "JSON source {} has no control '{}' (from {})",
+                                self.source_key, control, peer
+                            );
+                        }
+                        continue;
+                    }
+                    *self.stats.per_control.entry(control.clone()).or_default() += 1;
+                    let _ = self.sender.send(JsonEvent {
+                        source_key: self.source_key.clone(),
+                        control,
+                        value,
+                    });
+                }
+                Err(e) => {
+                    self.stats.malformed += 1;
+                    if self.debug {
+                        warn!("Malformed JSON message from {}: {:#}", peer, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct JsonManager {
+    debug: bool,
+    sources: HashMap<String, Vec<task::JoinHandle<()>>>,
+    unix_socket_paths: Vec<PathBuf>,
+    sender: mpsc::UnboundedSender<JsonEvent>,
+    receiver: Arc<AsyncMutex<mpsc::UnboundedReceiver<JsonEvent>>>,
+    engine_stop_token: CancellationToken,
+}
+
+impl JsonManager {
+    pub(crate) fn new(debug: bool) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Ok(Self {
+            debug,
+            sources: HashMap::new(),
+            unix_socket_paths: Vec::new(),
+            sender: tx,
+            receiver: Arc::new(AsyncMutex::new(rx)),
+            engine_stop_token: CancellationToken::new(),
+        })
+    }
+
+    pub(crate) fn open_source(
+        &mut self,
+        key_name: &str,
+        source: &ResolvedJsonSource,
+    ) -> Result<()> {
+        let control_names = source
+            .controls
+            .values()
+            .map(|control| control.name.clone())
+            .collect();
+        self.open_endpoint(
+            key_name,
+            &source.endpoint,
+            Some(control_names),
+            source.stats_interval,
+        )
+    }
+
+    /// Binds the socket right away, so a busy port or path is reported to the caller.
+    fn open_endpoint(
+        &mut self,
+        key_name: &str,
+        endpoint: &JsonEndpoint,
+        control_names: Option<HashSet<String>>,
+        stats_interval: Option<Duration>,
+    ) -> Result<()> {
+        let ingest = Arc::new(Mutex::new(JsonIngest {
+            source_key: key_name.to_string(),
+            control_names,
+            sender: self.sender.clone(),
+            stats: JsonSourceStats::default(),
+            debug: self.debug,
+        }));
+        let stop_token = self.engine_stop_token.child_token();
+
+        let mut handles = vec![match endpoint {
+            JsonEndpoint::Udp(bind) => {
+                let socket = std::net::UdpSocket::bind(bind).with_context(|| {
+                    format!("Failed to bind JSON source '{}' to {}", key_name, bind)
+                })?;
+                socket.set_nonblocking(true)?;
+                let socket = UdpSocket::from_std(socket)?;
+                task::spawn(Self::receive_datagrams(
+                    socket,
+                    ingest.clone(),
+                    stop_token.clone(),
+                ))
+            }
+            JsonEndpoint::Unix(path) => {
+                Self::remove_stale_unix_socket(path)?;
+                let listener = UnixListener::bind(path).with_context(|| {
+                    format!(
+                        "Failed to bind JSON source '{}' to {}",
+                        key_name,
+                        path.display()
+                    )
+                })?;
+                self.unix_socket_paths.push(path.clone());
+                task::spawn(Self::accept_connections(
+                    listener,
+                    ingest.clone(),
+                    stop_token.clone(),
+                ))
+            }
+        }];
+
+        if let Some(stats_interval) = stats_interval {
+            handles.push(task::spawn(Self::report_stats(
+                ingest,
+                stats_interval,
+                stop_token,
+            )));
+        }
+
+        self.sources.insert(key_name.to_string(), handles);
+
+        info!("Listening for JSON values on {} as {}", endpoint, key_name);
+
+        Ok(())
+    }
+
+    /// A socket file left by a previous run would make the bind fail.
+    fn remove_stale_unix_socket(path: &Path) -> Result<()> {
+        let Ok(metadata) = std::fs::symlink_metadata(path) else {
+            return Ok(());
+        };
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))
+    }
+
+    async fn receive_datagrams(
+        socket: UdpSocket,
+        ingest: Arc<Mutex<JsonIngest>>,
+        stop_token: CancellationToken,
+    ) {
+        let mut buf = vec![0u8; MAX_JSON_DATAGRAM_SIZE];
+        let mut receive_failing = false;
+        loop {
+            let result = tokio::select! {
+                _ = stop_token.cancelled() => return,
+                result = socket.recv_from(&mut buf) => result,
+            };
+            let (len, peer) = match result {
+                Ok(received) => {
+                    if receive_failing {
+                        info!("Receiving JSON datagrams again");
+                        receive_failing = false;
+                    }
+                    received
+                }
+                Err(e) => {
+                    if !receive_failing {
+                        error!(
+                            "Error receiving JSON datagrams: {}, retrying every {:?}",
+                            e, RECEIVE_ERROR_BACKOFF
+                        );
+                        receive_failing = true;
+                    }
+                    tokio::select! {
+                        _ = stop_token.cancelled() => return,
+                        _ = tokio::time::sleep(RECEIVE_ERROR_BACKOFF) => continue,
+                    }
+                }
+            };
+            let text = String::from_utf8_lossy(&buf[..len]);
+            ingest.lock().unwrap().ingest(&text, &peer.to_string());
+        }
+    }
+
+    async fn accept_connections(
+        listener: UnixListener,
+        ingest: Arc<Mutex<JsonIngest>>,
+        stop_token: CancellationToken,
+    ) {
+        let mut connection_id = 0usize;
+        let mut accept_failing = false;
+        loop {
+            let result = tokio::select! {
+                _ = stop_token.cancelled() => return,
+                result = listener.accept() => result,
+            };
+            let stream = match result {
+                Ok((stream, _)) => {
+                    if accept_failing {
+                        info!("Accepting JSON socket connections again");
+                        accept_failing = false;
+                    }
+                    stream
+                }
+                Err(e) => {
+                    if !accept_failing {
+                        error!(
+                            "Error accepting JSON socket connection: {}, retrying every {:?}",
+                            e, RECEIVE_ERROR_BACKOFF
+                        );
+                        accept_failing = true;
+                    }
+                    tokio::select! {
+                        _ = stop_token.cancelled() => return,
+                        _ = tokio::time::sleep(RECEIVE_ERROR_BACKOFF) => continue,
+                    }
+                }
+            };
+            connection_id += 1;
+            let peer = format!("connection #{}", connection_id);
+            let ingest = ingest.clone();
+            let stop_token = stop_token.clone();
+            task::spawn(async move {
+                let mut lines = BufReader::new(stream).lines();
+                loop {
+                    let line = tokio::select! {
+                        _ = stop_token.cancelled() => return,
+                        line = lines.next_line() => line,
+                    };
+                    match line {
+                        Ok(Some(line)) => ingest.lock().unwrap().ingest(&line, &peer),
+                        Ok(None) => return,
+                        Err(e) => {
+                            warn!("JSON socket {} closed: {}", peer, e);
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    async fn report_stats(
+        ingest: Arc<Mutex<JsonIngest>>,
+        stats_interval: Duration,
+        stop_token: CancellationToken,
+    ) {
+        let mut ticker = interval_at(Instant::now() + stats_interval, stats_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_report = Instant::now();
+        loop {
+            tokio::select! {
+                _ = stop_token.cancelled() => return,
+                _ = ticker.tick() => {}
+            }
+            let mut ingest = ingest.lock().unwrap();
+            if ingest.stats.is_empty() {
+                last_report = Instant::now();
+                continue;
+            }
+            let report = ingest.stats.take_report(last_report.elapsed());
+            last_report = Instant::now();
+            info!("JSON source {}: {}", ingest.source_key, report);
+        }
+    }
+
+    pub(crate) async fn get_event(&self) -> Option<JsonEvent> {
+        self.receiver.lock().await.recv().await
+    }
+
+    pub(crate) async fn monitor(&mut self, endpoint: &JsonEndpoint) -> Result<()> {
+        self.open_endpoint(
+            &endpoint.to_string(),
+            endpoint,
+            None,
+            Some(Duration::from_secs(5)),
+        )?;
+
+        println!("Press Ctrl+C to stop monitoring...");
+
+        while let Some(event) = self.get_event().await {
+            log::info!("[{}] {} = {}", event.source_key, event.control, event.value);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn stop(&mut self) -> Result<()> {
+        self.engine_stop_token.cancel();
+
+        for (_, handles) in self.sources.drain() {
+            for handle in handles {
+                handle.abort();
+            }
+        }
+        for path in self.unix_socket_paths.drain(..) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove socket {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_message() {
+        assert_eq!(
+            parse_json_message(r#"{"control":"throttle","value":0.42}"#).unwrap(),
+            ("throttle".to_string(), 0.42)
+        );
+        assert_eq!(
+            parse_json_message(r#"{"control":"gear_up","value":true,"ts":1}"#).unwrap(),
+            ("gear_up".to_string(), 1.0)
+        );
+        assert!(parse_json_message(r#"{"control":"throttle"}"#).is_err());
+        assert!(parse_json_message(r#"{"control":"throttle","value":"high"}"#).is_err());
+        assert!(parse_json_message("throttle=0.42").is_err());
+    }
+
+    #[test]
+    fn test_ingest_counts_and_filters() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut ingest = JsonIngest {
+            source_key: "scripts".to_string(),
+            control_names: Some(HashSet::from(["throttle".to_string()])),
+            sender,
+            stats: JsonSourceStats::default(),
+            debug: false,
+        };
+        ingest.ingest(
+            "{\"control\":\"throttle\",\"value\":1}\n\n{\"control\":\"brake\",\"value\":1}\n{",
+            "test",
+        );
+        ingest.ingest(r#"{"control":"throttle","value":0.5}"#, "test");
+
+        assert_eq!(receiver.try_recv().unwrap().value, 1.0);
+        assert_eq!(receiver.try_recv().unwrap().value, 0.5);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            ingest.stats.take_report(Duration::from_secs(2)),
+            "1.0 msg/s [throttle 1.0/s], 1 malformed, 1 unknown control"
+        );
+        assert!(ingest.stats.is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+use evdev::{Device, KeyCode};
+
+use crate::evdev_source::{EvdevEvent, EvdevSource, EvdevSourceManager};
+
+/// Value reported by evdev for a held key autorepeat event (0 is release, 1 is press).
+pub(crate) const KEY_VALUE_REPEAT: i32 = 2;
+
+/// Keyboards, stand-alone numpads and macro keypads, only their key events are read.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeyboardSource;
+
+impl EvdevSource for KeyboardSource {
+    type Event = EvdevEvent;
+
+    type Stream = evdev::EventStream;
+
+    const DEVICE_KIND: &'static str = "keyboard";
+
+    fn supports(&self, device: &Device) -> bool {
+        is_keyboard(device)
+    }
+
+    fn handle_event(
+        &mut self,
+        _stream: &evdev::EventStream,
+        device_key: &str,
+        event: evdev::InputEvent,
+        debug: bool,
+    ) -> Option<EvdevEvent> {
+        if event.event_type() != evdev::EventType::KEY {
+            return None;
+        }
+        EvdevEvent::from_evdev(device_key, event, debug)
+    }
+}
+
+/// Anything reporting at least one of the main block keys (ESC .. keypad dot)
+/// is considered a keyboard, this also covers stand-alone numpads and macro keypads.
+pub(crate) fn is_keyboard(device: &Device) -> bool {
+    device.supported_keys().is_some_and(|keys| {
+        (KeyCode::KEY_ESC.code()..=KeyCode::KEY_KPDOT.code())
+            .any(|code| keys.contains(KeyCode::new(code)))
+    })
+}
+
+pub(crate) type KeyboardManager = EvdevSourceManager<KeyboardSource>;
@@ -4,10 +4,21 @@ pub mod control_types_macro;
 pub mod common;
 pub mod config;
 pub mod driver;
+pub mod evdev_source;
+pub mod gamepad;
+pub mod generator;
+pub mod grab;
+pub mod hotplug;
 pub mod interpolation;
 pub mod joystick;
+pub mod json;
+pub mod keyboard;
 pub mod mapping;
 pub mod midi;
+pub mod midi_feedback;
+pub mod midi_output;
 pub mod mouse;
+pub mod osc;
 pub mod overlay;
 pub mod schemas;
+pub mod touch;
@@ -1,37 +1,58 @@
 use anyhow::{bail, Context, Result};
 use atomic_float::AtomicF32;
 use log::{debug, info, warn};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::Instant;
-use std::usize;
 use tokio::select;
 use tokio::time::{interval, Duration, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
 
-use crate::common::NumInterval;
-use crate::config::{ConfigManager, ControlReference, ResolvedMapping};
+use crate::common::{ControlType, NumInterval};
+use crate::config::{
+    instance_selected, ConfigManager, ControlReference, DestinationReference, ResolvedMapping,
+    INTERNAL_DEVICE_KEY,
+};
+use crate::evdev_source::EvdevEvent;
+use crate::gamepad::{check_axis_ranges, GamepadManager};
+use crate::generator::Generator;
+use crate::grab::{
+    ComboWatchManager, ComboWatchSource, GrabController, GrabRole, COMBO_WATCH_DEVICE_KEY,
+};
+use crate::hotplug::{DeviceWatcher, RescanScope};
 use crate::interpolation::{InterpolationCurve, ValueFilter};
 use crate::joystick::VirtualJoystickManager;
-use crate::midi::{MidiManager, MidiMessage};
-use crate::mouse::{MouseEvent, MouseManager};
+use crate::json::{JsonEvent, JsonManager};
+use crate::keyboard::{KeyboardManager, KEY_VALUE_REPEAT};
+use crate::midi::{MidiManager, MidiMessage, MidiMessageType};
+use crate::midi_feedback::MidiFeedbackManager;
+use crate::midi_output::MidiOutputManager;
+use crate::mouse::MouseManager;
+use crate::osc::{osc_address_matches, OscEvent, OscManager};
 use crate::schemas::{
-    IntegrateTransform, ResolvedHoldFactor, ResolvedPedalSmootherTransform,
-    ResolvedSteeringTransform, ResolvedTransformationStep, StepRuntimeStateId,
+    ResolvedGamepadDevice, ResolvedGeneratorControl, ResolvedGeneratorParameter,
+    ResolvedHoldFactor, ResolvedIntegrateTransform, ResolvedJsonSource, ResolvedKeyboardDevice,
+    ResolvedMidiDevice, ResolvedMouseDevice, ResolvedOscSource, ResolvedPedalSmootherTransform,
+    ResolvedSteeringTransform, ResolvedTouchDevice, ResolvedTransformationStep, StepRuntimeStateId,
 };
+use crate::touch::{touch_control_device_range, touch_control_value, TouchFrame, TouchManager};
+
+/// State of a transformation step for one device instance, the instance number is 0
+/// for devices without `instances: true`.
+type StepStateKey = (StepRuntimeStateId, usize);
 
 struct TransformStepState {
-    time1: HashMap<StepRuntimeStateId, Instant>,
-    time2: HashMap<StepRuntimeStateId, Instant>,
+    time1: HashMap<StepStateKey, Instant>,
+    time2: HashMap<StepStateKey, Instant>,
     #[allow(unused)]
-    usize_1: HashMap<StepRuntimeStateId, usize>,
-    f32_1: HashMap<StepRuntimeStateId, f32>,
-    f32_2: HashMap<StepRuntimeStateId, f32>,
+    usize_1: HashMap<StepStateKey, usize>,
+    f32_1: HashMap<StepStateKey, f32>,
+    f32_2: HashMap<StepStateKey, f32>,
     #[allow(unused)]
-    vec_deq_1: HashMap<StepRuntimeStateId, VecDeque<f32>>,
+    vec_deq_1: HashMap<StepStateKey, VecDeque<f32>>,
     #[allow(unused)]
-    vec_deq_timestamped: HashMap<StepRuntimeStateId, VecDeque<(Instant, f32)>>,
+    vec_deq_timestamped: HashMap<StepStateKey, VecDeque<(Instant, f32)>>,
 }
 
 impl TransformStepState {
@@ -52,7 +73,16 @@ pub(crate) struct MappingEngine<'cfg> {
     config_manager: &'cfg ConfigManager,
     midi_manager: MidiManager,
     mouse_manager: MouseManager,
+    keyboard_manager: KeyboardManager,
+    gamepad_manager: GamepadManager,
+    touch_manager: TouchManager,
+    /// Devices watched for the emergency combo while any source device is grabbed.
+    combo_watch_manager: ComboWatchManager,
+    osc_manager: OscManager,
+    json_manager: JsonManager,
     joystick_manager: &'cfg VirtualJoystickManager,
+    midi_output_manager: MidiOutputManager,
+    midi_feedback_manager: MidiFeedbackManager,
     debug: bool,
     debug_idle_tick: bool,
     update_rate: u32,
@@ -60,8 +90,31 @@ pub(crate) struct MappingEngine<'cfg> {
     running: bool,
     moving_average_step_data: RefCell<TransformStepState>,
     transform_step_data: RefCell<TransformStepState>,
+    /// Device time of the input event being mapped, if the device reports one.
+    input_event_time: Cell<Option<Instant>>,
+    /// Routed generator source keys, evaluated on the idle tick.
+    generator_source_keys: Vec<String>,
+    /// Generator states with their last evaluation time, by source and control key.
+    generators: RefCell<HashMap<(String, String), (Generator, Instant)>>,
+    /// Values of the internal controls, they live only as long as the engine.
+    internal_control_states: RefCell<HashMap<String, i32>>,
+    /// Internal controls written since their mappings last ran.
+    pending_internal_controls: RefCell<HashSet<String>>,
     router: HashMap<String, Vec<&'cfg ResolvedMapping>>,
-    idle_tick_mappings: Vec<&'cfg ResolvedMapping>,
+    /// Enabled mappings by their enabled source device key, routed once a matching device opens.
+    source_mappings: HashMap<String, Vec<&'cfg ResolvedMapping>>,
+    /// Enabled source device keys of enabled mappings, rematched on device hot-plug.
+    active_src_device_keys: BTreeSet<String>,
+    /// Ids of the physical devices seen per source device key with `instances: true`,
+    /// the position is the instance number.
+    device_instance_ids: HashMap<String, Vec<String>>,
+    /// Instance numbers of the runtime device keys of device instances.
+    runtime_device_instances: HashMap<String, usize>,
+    /// Absolute axis ranges read from opened gamepads and touch devices, per source device key.
+    source_axis_ranges: HashMap<String, HashMap<ControlType, NumInterval<i32>>>,
+    /// Mappings run on the idle tick, once per device instance with a runtime device key of it.
+    idle_tick_mappings: Vec<(String, &'cfg ResolvedMapping)>,
+    grab_controller: Arc<GrabController>,
     enable_steering_indicator_window: bool,
     steering_indicator_pos: Arc<AtomicF32>,
     steering_indicator_hold: Arc<AtomicF32>,
@@ -70,20 +123,40 @@ pub(crate) struct MappingEngine<'cfg> {
 }
 
 impl<'cfg> MappingEngine<'cfg> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         config_manager: &'cfg ConfigManager,
         midi_manager: MidiManager,
         mouse_manager: MouseManager,
+        keyboard_manager: KeyboardManager,
+        gamepad_manager: GamepadManager,
+        touch_manager: TouchManager,
+        osc_manager: OscManager,
+        json_manager: JsonManager,
         joystick_manager: &'cfg VirtualJoystickManager,
+        midi_output_manager: MidiOutputManager,
+        midi_feedback_manager: MidiFeedbackManager,
         debug: bool,
         debug_idle_tick: bool,
         enable_steering_indicator_window: bool,
     ) -> Result<Self> {
+        let emergency_release_combo = &config_manager.get_config().global.emergency_release_combo;
         Ok(Self {
             config_manager,
             midi_manager,
             mouse_manager,
+            keyboard_manager,
+            gamepad_manager,
+            touch_manager,
+            combo_watch_manager: ComboWatchManager::with_source(
+                ComboWatchSource::new(emergency_release_combo),
+                debug,
+            )?,
+            osc_manager,
+            json_manager,
             joystick_manager,
+            midi_output_manager,
+            midi_feedback_manager,
             debug,
             debug_idle_tick,
             update_rate: config_manager.get_config().global.idle_tick_update_rate,
@@ -91,8 +164,25 @@ impl<'cfg> MappingEngine<'cfg> {
             running: false,
             moving_average_step_data: TransformStepState::new().into(),
             transform_step_data: TransformStepState::new().into(),
+            input_event_time: Cell::new(None),
+            generator_source_keys: Vec::new(),
+            generators: RefCell::new(HashMap::new()),
+            internal_control_states: RefCell::new(
+                config_manager
+                    .get_resolved_internal_controls()
+                    .iter()
+                    .map(|(key, control)| (key.clone(), control.initial_value))
+                    .collect(),
+            ),
+            pending_internal_controls: RefCell::new(HashSet::new()),
             router: HashMap::new(),
+            source_mappings: HashMap::new(),
+            active_src_device_keys: BTreeSet::new(),
+            device_instance_ids: HashMap::new(),
+            runtime_device_instances: HashMap::new(),
+            source_axis_ranges: HashMap::new(),
             idle_tick_mappings: Vec::new(),
+            grab_controller: Arc::new(GrabController::new(emergency_release_combo.clone())),
             enable_steering_indicator_window,
             steering_indicator_pos: Arc::new(0.0.into()),
             steering_indicator_hold: Arc::new(0.0.into()),
@@ -136,6 +226,30 @@ impl<'cfg> MappingEngine<'cfg> {
 
         let mut opened_virtual_joysticks: BTreeSet<String> = BTreeSet::new();
         for vjoy_key in required_dst_device_keys {
+            if vjoy_key == INTERNAL_DEVICE_KEY {
+                opened_virtual_joysticks.insert(vjoy_key);
+                continue;
+            }
+            if let Some(output) = self
+                .config_manager
+                .get_resolved_midi_outputs()
+                .get(&vjoy_key)
+            {
+                if !output.enabled {
+                    warn!(
+                        "MIDI output '{}' is not enabled, ignoring it and all the associated mappings.",
+                        vjoy_key
+                    );
+                } else if let Err(error) = self.midi_output_manager.open(&vjoy_key, output) {
+                    warn!(
+                        "Failed to open MIDI output '{}', ignoring all the associated mappings: {:#}",
+                        vjoy_key, error
+                    );
+                } else {
+                    opened_virtual_joysticks.insert(vjoy_key);
+                }
+                continue;
+            }
             let resolved_device = self
                 .config_manager
                 .get_resolved_virtual_joystick(&vjoy_key)
@@ -161,116 +275,654 @@ impl<'cfg> MappingEngine<'cfg> {
             }
         }
 
-        let mut runtime_device_name_to_config_device_key: HashMap<String, String> = HashMap::new();
-
-        let available_midi = self.midi_manager.enumerate_devices();
+        let config_manager = self.config_manager;
         for src_device_key in &required_src_device_keys {
-            if let Some(resolved_device) =
-                self.config_manager.get_resolved_midi_device(src_device_key)
-            {
-                if !resolved_device.enabled {
+            if src_device_key == INTERNAL_DEVICE_KEY {
+                self.active_src_device_keys.insert(src_device_key.clone());
+                continue;
+            }
+            if let Some(source) = config_manager.get_resolved_osc_source(src_device_key) {
+                if source.enabled {
+                    self.active_src_device_keys.insert(src_device_key.clone());
+                } else {
                     warn!(
-                        "MIDI devices with name regex pattern {:?} are not enabled, ignoring config entry '{}' \
-                     and all the associated mappings.",
-                        resolved_device.match_name_regex, src_device_key
+                        "OSC source '{}' on {} is not enabled, ignoring it \
+                        and all the associated mappings.",
+                        src_device_key, source.bind
+                    );
+                }
+                continue;
+            }
+            if let Some(source) = config_manager.get_resolved_json_source(src_device_key) {
+                if source.enabled {
+                    self.active_src_device_keys.insert(src_device_key.clone());
+                } else {
+                    warn!(
+                        "JSON source '{}' on {} is not enabled, ignoring it \
+                        and all the associated mappings.",
+                        src_device_key, source.endpoint
                     );
-                    continue;
                 }
+                continue;
+            }
+            if let Some(source) = config_manager.get_resolved_generator_source(src_device_key) {
+                if source.enabled {
+                    self.active_src_device_keys.insert(src_device_key.clone());
+                } else {
+                    warn!(
+                        "Generator '{}' is not enabled, ignoring it and all the associated mappings.",
+                        src_device_key
+                    );
+                }
+                continue;
+            }
+            let status = if let Some(device) =
+                config_manager.get_resolved_midi_device(src_device_key)
+            {
+                Some(("MIDI", device.enabled, &device.match_name_regex))
+            } else if let Some(device) = config_manager.get_resolved_mouse_device(src_device_key) {
+                Some(("Mouse", device.enabled, &device.match_name_regex))
+            } else if let Some(device) = config_manager.get_resolved_keyboard_device(src_device_key)
+            {
+                Some(("Keyboard", device.enabled, &device.match_name_regex))
+            } else if let Some(device) = config_manager.get_resolved_gamepad_device(src_device_key)
+            {
+                Some(("Gamepad", device.enabled, &device.match_name_regex))
+            } else {
+                config_manager
+                    .get_resolved_touch_device(src_device_key)
+                    .map(|device| ("Touch", device.enabled, &device.match_name_regex))
+            };
+            let Some((device_kind, enabled, match_name_regex)) = status else {
+                continue;
+            };
+            if !enabled {
+                warn!(
+                    "{} devices with name regex pattern {:?} are not enabled, ignoring config entry '{}' \
+                     and all the associated mappings.",
+                    device_kind, match_name_regex, src_device_key
+                );
+                continue;
+            }
+            self.active_src_device_keys.insert(src_device_key.clone());
+        }
 
-                if let Some(pattern) = &resolved_device.match_name_regex {
-                    let matched = self.midi_manager.match_device(pattern, &available_midi);
-
-                    for device_name in matched {
-                        match self.midi_manager.open_device(&device_name) {
-                            Ok(_) => {
-                                if self.debug {
-                                    debug!(
-                                        "Opened Source MIDI: {} (for key: {})",
-                                        device_name, src_device_key
-                                    );
-                                }
-                                runtime_device_name_to_config_device_key
-                                    .insert(device_name, src_device_key.clone());
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "Failed to open matched MIDI device '{}': {}",
-                                    device_name, e
-                                );
-                            }
-                        }
+        for mapping in all_mappings {
+            if mapping.enabled
+                && opened_virtual_joysticks.contains(&mapping.destination.device_key)
+                && self
+                    .active_src_device_keys
+                    .contains(&mapping.source.device_key)
+            {
+                self.source_mappings
+                    .entry(mapping.source.device_key.clone())
+                    .or_default()
+                    .push(mapping);
+            }
+        }
+
+        self.midi_manager
+            .set_virtual_ports(&self.config_manager.get_config().global.virtual_midi_ports);
+        self.sync_midi_devices();
+        self.sync_midi_feedback_ports();
+        self.sync_mouse_devices()?;
+        self.sync_keyboard_devices()?;
+        self.sync_gamepad_devices()?;
+        self.sync_touch_devices()?;
+        self.sync_combo_watch_devices()?;
+        self.open_osc_sources();
+        self.open_json_sources();
+        self.open_generator_sources();
+        if self.active_src_device_keys.contains(INTERNAL_DEVICE_KEY) {
+            self.route_source_device(INTERNAL_DEVICE_KEY, INTERNAL_DEVICE_KEY, None);
+        }
+
+        info!("Router built. Active Source Devices: {}", self.router.len());
+        Ok(())
+    }
+
+    /// Routes events of a runtime source device to the mappings of its config device key,
+    /// for a device instance (number and id) only to the mappings selecting it, see `instance_selected`.
+    fn route_source_device(
+        &mut self,
+        runtime_device_name: &str,
+        src_device_key: &str,
+        instance: Option<(usize, &str)>,
+    ) {
+        let Some(mappings) = self.source_mappings.get(src_device_key) else {
+            return;
+        };
+        if let Some((index, _)) = instance {
+            self.runtime_device_instances
+                .insert(runtime_device_name.to_string(), index);
+        }
+        let selected: Vec<&'cfg ResolvedMapping> = mappings
+            .iter()
+            .copied()
+            .filter(|mapping| instance_selected(mapping.source.instance.as_ref(), instance))
+            .collect();
+        let routed = self
+            .router
+            .entry(runtime_device_name.to_string())
+            .or_default();
+        for mapping in &selected {
+            if !routed.iter().any(|m| std::ptr::eq(*m, *mapping)) {
+                routed.push(mapping);
+            }
+        }
+        let instance_index = self.device_instance_of(runtime_device_name);
+        for mapping in selected {
+            if self.requires_idle_tick(mapping)
+                && !self.idle_tick_mappings.iter().any(|(device_name, m)| {
+                    std::ptr::eq(*m, mapping)
+                        && self.device_instance_of(device_name) == instance_index
+                })
+            {
+                self.idle_tick_mappings
+                    .push((runtime_device_name.to_string(), mapping));
+            }
+        }
+    }
+
+    /// Instance number of a runtime device key, 0 for devices without instances.
+    fn device_instance_of(&self, runtime_device_name: &str) -> usize {
+        self.runtime_device_instances
+            .get(runtime_device_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Stable number of a physical device among the instances of a source device key,
+    /// a device plugged back in (same id) gets its previous number.
+    fn device_instance_index(&mut self, src_device_key: &str, instance_id: &str) -> usize {
+        let ids = self
+            .device_instance_ids
+            .entry(src_device_key.to_string())
+            .or_default();
+        ids.iter()
+            .position(|id| id == instance_id)
+            .unwrap_or_else(|| {
+                ids.push(instance_id.to_string());
+                ids.len() - 1
+            })
+    }
+
+    /// Reopens devices after a `DeviceWatcher` notification. Virtual joysticks and
+    /// transformation step state are kept, only the router is updated.
+    fn rescan_devices(&mut self, scope: RescanScope) {
+        if scope.midi_ports {
+            self.sync_midi_devices();
+            self.sync_midi_feedback_ports();
+            self.midi_output_manager
+                .reopen_closed_ports(self.config_manager.get_resolved_midi_outputs());
+        }
+        if scope.input_devices {
+            let results = [
+                self.sync_mouse_devices(),
+                self.sync_keyboard_devices(),
+                self.sync_gamepad_devices(),
+                self.sync_touch_devices(),
+                self.sync_combo_watch_devices(),
+            ];
+            for result in results {
+                if let Err(e) = result {
+                    warn!("Input devices rescan failed: {:#}", e);
+                }
+            }
+        }
+    }
+
+    /// Closes disappeared MIDI ports and opens the new ones matching active source devices.
+    fn sync_midi_devices(&mut self) {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedMidiDevice)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_midi_device(key)
+                    .map(|device| (key.clone(), device))
+            })
+            .collect();
+        if sources.is_empty() {
+            return;
+        }
+
+        let available_midi = self.midi_manager.enumerate_devices();
+        for device_name in self.midi_manager.close_missing_devices(&available_midi) {
+            info!("MIDI device disconnected: {}", device_name);
+            self.router.remove(&device_name);
+        }
+
+        for (src_device_key, resolved_device) in sources {
+            let Some(pattern) = &resolved_device.match_name_regex else {
+                continue;
+            };
+            for device_name in self.midi_manager.match_device(
+                pattern,
+                &resolved_device.port_match,
+                &available_midi,
+            ) {
+                if !self.midi_manager.is_device_open(&device_name) {
+                    if let Err(e) = self.midi_manager.open_device(&device_name) {
+                        warn!(
+                            "Failed to open matched MIDI device '{}': {}",
+                            device_name, e
+                        );
+                        continue;
+                    }
+                    if self.debug {
+                        debug!(
+                            "Opened Source MIDI: {} (for key: {})",
+                            device_name, src_device_key
+                        );
                     }
                 }
+                self.route_source_device(&device_name, &src_device_key, None);
             }
         }
+    }
+
+    fn sync_midi_feedback_ports(&self) {
+        let feedback = self.config_manager.get_resolved_midi_feedback();
+        if feedback.is_empty() {
+            return;
+        }
+        if let Err(e) = self.midi_feedback_manager.sync_ports(feedback) {
+            warn!("MIDI feedback ports rescan failed: {:#}", e);
+        }
+    }
+
+    /// Closes unplugged mice and opens the new ones matching active source devices.
+    fn sync_mouse_devices(&mut self) -> Result<()> {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedMouseDevice)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_mouse_device(key)
+                    .map(|device| (key.clone(), device))
+            })
+            .collect();
+        if sources.is_empty() {
+            return Ok(());
+        }
 
         let available_mice = self.mouse_manager.enumerate_devices()?;
-        for src_device_key in &required_src_device_keys {
-            if let Some(resolved_device) = self
-                .config_manager
-                .get_resolved_mouse_device(src_device_key)
-            {
-                if !resolved_device.enabled {
-                    warn!(
-                        "Mouse devices with name regex pattern {:?} are not enabled, ignoring config entry '{}' \
-                     and all the associated mappings.",
-                        resolved_device.match_name_regex, src_device_key
-                    );
+        for (runtime_device_key, path) in self.mouse_manager.close_missing_devices(&available_mice)
+        {
+            info!(
+                "Closed mouse {} (as {})",
+                path.display(),
+                runtime_device_key
+            );
+            if !self.mouse_manager.has_open_devices(&runtime_device_key) {
+                self.router.remove(&runtime_device_key);
+            }
+        }
+
+        for (src_device_key, resolved_device) in sources {
+            let Some(pattern) = &resolved_device.match_name_regex else {
+                continue;
+            };
+            let mut matched = self.mouse_manager.match_device(
+                pattern,
+                &resolved_device.device_match,
+                &available_mice,
+            );
+            // Instances present on engine start are numbered in the order of their ids.
+            matched.sort_by_key(|device_info| device_info.instance_id());
+            for device_info in matched {
+                let instance = resolved_device.instances.then(|| {
+                    let instance_id = device_info.instance_id();
+                    let index = self.device_instance_index(&src_device_key, &instance_id);
+                    (index, instance_id)
+                });
+                let runtime_device_key = match &instance {
+                    Some((index, _)) => format!("{}[{}]", src_device_key, index),
+                    None => src_device_key.clone(),
+                };
+                if self
+                    .mouse_manager
+                    .is_device_open(&device_info, &runtime_device_key)
+                {
                     continue;
                 }
+                match self.mouse_manager.open_device(
+                    &device_info,
+                    &runtime_device_key,
+                    self.grab_controller_for(resolved_device.grab),
+                ) {
+                    Ok(_) => {
+                        if self.debug || self.running {
+                            info!(
+                                "Opened Source Mouse: {} (as {})",
+                                device_info.name, runtime_device_key
+                            );
+                        }
+                        self.route_source_device(
+                            &runtime_device_key,
+                            &src_device_key,
+                            instance.as_ref().map(|(index, id)| (*index, id.as_str())),
+                        );
+                    }
+                    Err(e) => {
+                        warn!("Failed to open mouse '{}': {}", device_info.name, e)
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes unplugged keyboards and opens the new ones matching active source devices.
+    fn sync_keyboard_devices(&mut self) -> Result<()> {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedKeyboardDevice)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_keyboard_device(key)
+                    .map(|device| (key.clone(), device))
+            })
+            .collect();
+        if sources.is_empty() {
+            return Ok(());
+        }
+
+        let available_keyboards = self.keyboard_manager.enumerate_devices()?;
+        for (src_device_key, path) in self
+            .keyboard_manager
+            .close_missing_devices(&available_keyboards)
+        {
+            info!("Closed keyboard {} (as {})", path.display(), src_device_key);
+            if !self.keyboard_manager.has_open_devices(&src_device_key) {
+                self.router.remove(&src_device_key);
+            }
+        }
 
-                if let Some(pattern) = &resolved_device.match_name_regex {
-                    let matched = self.mouse_manager.match_device(pattern, &available_mice);
-                    for device_info in matched {
-                        match self.mouse_manager.open_device(&device_info, src_device_key) {
-                            Ok(_) => {
-                                if self.debug {
-                                    info!(
-                                        "Opened Source Mouse: {} (as {})",
-                                        device_info.name, src_device_key
-                                    );
-                                }
-                                runtime_device_name_to_config_device_key
-                                    .insert(src_device_key.clone(), src_device_key.clone());
-                            }
-                            Err(e) => {
-                                warn!("Failed to open mouse '{}': {}", device_info.name, e)
-                            }
+        for (src_device_key, resolved_device) in sources {
+            let Some(pattern) = &resolved_device.match_name_regex else {
+                continue;
+            };
+            for device_info in self.keyboard_manager.match_device(
+                pattern,
+                &resolved_device.device_match,
+                &available_keyboards,
+            ) {
+                if self
+                    .keyboard_manager
+                    .is_device_open(&device_info, &src_device_key)
+                {
+                    continue;
+                }
+                match self.keyboard_manager.open_device(
+                    &device_info,
+                    &src_device_key,
+                    self.grab_controller_for(resolved_device.grab),
+                ) {
+                    Ok(_) => {
+                        if self.debug || self.running {
+                            info!(
+                                "Opened Source Keyboard: {} (as {})",
+                                device_info.name, src_device_key
+                            );
                         }
+                        self.route_source_device(&src_device_key, &src_device_key, None);
+                    }
+                    Err(e) => {
+                        warn!("Failed to open keyboard '{}': {}", device_info.name, e)
                     }
                 }
             }
         }
+        Ok(())
+    }
 
-        for mapping in all_mappings {
-            if !mapping.enabled {
+    /// Closes unplugged gamepads and opens the new ones matching active source devices.
+    fn sync_gamepad_devices(&mut self) -> Result<()> {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedGamepadDevice)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_gamepad_device(key)
+                    .map(|device| (key.clone(), device))
+            })
+            .collect();
+        if sources.is_empty() {
+            return Ok(());
+        }
+
+        let available_gamepads = self.gamepad_manager.enumerate_devices()?;
+        for (src_device_key, path) in self
+            .gamepad_manager
+            .close_missing_devices(&available_gamepads)
+        {
+            info!("Closed gamepad {} (as {})", path.display(), src_device_key);
+            if !self.gamepad_manager.has_open_devices(&src_device_key) {
+                self.router.remove(&src_device_key);
+            }
+        }
+
+        for (src_device_key, resolved_device) in sources {
+            let Some(pattern) = &resolved_device.match_name_regex else {
                 continue;
+            };
+            for device_info in self.gamepad_manager.match_device(
+                pattern,
+                &resolved_device.device_match,
+                &available_gamepads,
+            ) {
+                if self
+                    .gamepad_manager
+                    .is_device_open(&device_info, &src_device_key)
+                {
+                    continue;
+                }
+                let opened = check_axis_ranges(resolved_device, &device_info).and_then(|_| {
+                    self.gamepad_manager.open_device(
+                        &device_info,
+                        &src_device_key,
+                        self.grab_controller_for(resolved_device.grab),
+                    )
+                });
+                match opened {
+                    Ok(_) => {
+                        if self.debug || self.running {
+                            info!(
+                                "Opened Source Gamepad: {} (as {})",
+                                device_info.name, src_device_key
+                            );
+                        }
+                        self.register_source_axis_ranges(&src_device_key, &device_info.axes);
+                        self.route_source_device(&src_device_key, &src_device_key, None);
+                    }
+                    Err(e) => {
+                        warn!("Failed to open gamepad '{}': {:#}", device_info.name, e)
+                    }
+                }
             }
+        }
+        Ok(())
+    }
 
-            if !opened_virtual_joysticks.contains(&mapping.destination.device_key) {
+    /// Closes unplugged touch devices and opens the new ones matching active source devices.
+    fn sync_touch_devices(&mut self) -> Result<()> {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedTouchDevice)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_touch_device(key)
+                    .map(|device| (key.clone(), device))
+            })
+            .collect();
+        if sources.is_empty() {
+            return Ok(());
+        }
+
+        let available_touch_devices = self.touch_manager.enumerate_devices()?;
+        for (src_device_key, path) in self
+            .touch_manager
+            .close_missing_devices(&available_touch_devices)
+        {
+            info!(
+                "Closed touch device {} (as {})",
+                path.display(),
+                src_device_key
+            );
+            if !self.touch_manager.has_open_devices(&src_device_key) {
+                self.router.remove(&src_device_key);
+            }
+        }
+
+        for (src_device_key, resolved_device) in sources {
+            let Some(pattern) = &resolved_device.match_name_regex else {
                 continue;
+            };
+            for device_info in self.touch_manager.match_device(
+                pattern,
+                &resolved_device.device_match,
+                &available_touch_devices,
+            ) {
+                if self
+                    .touch_manager
+                    .is_device_open(&device_info, &src_device_key)
+                {
+                    continue;
+                }
+                match self.touch_manager.open_device(
+                    &device_info,
+                    &src_device_key,
+                    self.grab_controller_for(resolved_device.grab),
+                ) {
+                    Ok(_) => {
+                        if self.debug || self.running {
+                            info!(
+                                "Opened Source Touch Device: {} (as {})",
+                                device_info.name, src_device_key
+                            );
+                        }
+                        self.register_source_axis_ranges(&src_device_key, &device_info.axes);
+                        self.route_source_device(&src_device_key, &src_device_key, None);
+                    }
+                    Err(e) => {
+                        warn!("Failed to open touch device '{}': {}", device_info.name, e)
+                    }
+                }
             }
+        }
+        Ok(())
+    }
 
-            for (runtime_device_name, config_device_key) in
-                &runtime_device_name_to_config_device_key
+    /// While any source device is grabbed, opens all the devices able to press an emergency
+    /// combo key without grabbing them, so that the combo can't be grabbed away with them.
+    fn sync_combo_watch_devices(&mut self) -> Result<()> {
+        if !self.any_source_grabbed() {
+            return Ok(());
+        }
+
+        let available = self.combo_watch_manager.enumerate_devices()?;
+        self.combo_watch_manager.close_missing_devices(&available);
+
+        for device_info in available {
+            if self
+                .combo_watch_manager
+                .is_device_open(&device_info, COMBO_WATCH_DEVICE_KEY)
             {
-                if *config_device_key == mapping.source.device_key {
-                    self.router
-                        .entry(runtime_device_name.clone())
-                        .or_default()
-                        .push(mapping);
-
-                    if self.requires_idle_tick(mapping) {
-                        self.idle_tick_mappings.push(mapping);
+                continue;
+            }
+            match self.combo_watch_manager.open_device(
+                &device_info,
+                COMBO_WATCH_DEVICE_KEY,
+                Some(GrabRole::Watch(self.grab_controller.clone())),
+            ) {
+                Ok(_) => {
+                    if self.debug {
+                        info!(
+                            "Watching {} for the emergency release combo.",
+                            device_info.name
+                        );
                     }
                 }
+                Err(e) => warn!(
+                    "Failed to open '{}' to watch for the emergency release combo: {}",
+                    device_info.name, e
+                ),
             }
         }
-
-        info!("Router built. Active Source Devices: {}", self.router.len());
         Ok(())
     }
 
+    /// Binds the UDP sockets of active OSC sources, a failure only disables that source.
+    fn open_osc_sources(&mut self) {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedOscSource)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_osc_source(key)
+                    .map(|source| (key.clone(), source))
+            })
+            .collect();
+
+        for (src_device_key, resolved_source) in sources {
+            match self
+                .osc_manager
+                .open_source(&src_device_key, resolved_source.bind)
+            {
+                Ok(_) => self.route_source_device(&src_device_key, &src_device_key, None),
+                Err(e) => warn!("Failed to open OSC source '{}': {:#}", src_device_key, e),
+            }
+        }
+    }
+
+    /// Binds the sockets of active JSON sources, a failure only disables that source.
+    fn open_json_sources(&mut self) {
+        let config_manager = self.config_manager;
+        let sources: Vec<(String, &'cfg ResolvedJsonSource)> = self
+            .active_src_device_keys
+            .iter()
+            .filter_map(|key| {
+                config_manager
+                    .get_resolved_json_source(key)
+                    .map(|source| (key.clone(), source))
+            })
+            .collect();
+
+        for (src_device_key, resolved_source) in sources {
+            match self
+                .json_manager
+                .open_source(&src_device_key, resolved_source)
+            {
+                Ok(_) => self.route_source_device(&src_device_key, &src_device_key, None),
+                Err(e) => warn!("Failed to open JSON source '{}': {:#}", src_device_key, e),
+            }
+        }
+    }
+
+    /// Routes active generators, they have nothing to open and run on the idle tick.
+    fn open_generator_sources(&mut self) {
+        let config_manager = self.config_manager;
+        let keys: Vec<String> = self
+            .active_src_device_keys
+            .iter()
+            .filter(|key| config_manager.get_resolved_generator_source(key).is_some())
+            .cloned()
+            .collect();
+
+        for src_device_key in keys {
+            self.route_source_device(&src_device_key, &src_device_key, None);
+            self.generator_source_keys.push(src_device_key);
+        }
+    }
+
     pub(crate) async fn run(&mut self) -> Result<()> {
         self.running = true;
 
@@ -292,6 +944,21 @@ impl<'cfg> MappingEngine<'cfg> {
         let mut ticker = interval(Duration::from_secs_f64(1.0 / self.update_rate as f64));
         ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
+        let hotplug_poll_interval = self
+            .config_manager
+            .get_config()
+            .global
+            .hotplug_poll_interval;
+        let mut device_watcher = DeviceWatcher::new(
+            Duration::try_from_secs_f32(hotplug_poll_interval).unwrap_or_else(|_| {
+                warn!(
+                    "Invalid hotplug_poll_interval {}, device hot-plugging is disabled.",
+                    hotplug_poll_interval
+                );
+                Duration::ZERO
+            }),
+        );
+
         info!("Mapping engine running at {} Hz", self.update_rate);
 
         while self.running {
@@ -302,10 +969,29 @@ impl<'cfg> MappingEngine<'cfg> {
                 Some(mouse_event) = self.mouse_manager.get_event() => {
                     self.process_mouse_event(mouse_event).await?;
                 }
+                Some(keyboard_event) = self.keyboard_manager.get_event() => {
+                    self.process_keyboard_event(keyboard_event).await?;
+                }
+                Some(gamepad_event) = self.gamepad_manager.get_event() => {
+                    self.process_gamepad_event(gamepad_event).await?;
+                }
+                Some(touch_frame) = self.touch_manager.get_event() => {
+                    self.process_touch_frame(touch_frame).await?;
+                }
+                Some(osc_event) = self.osc_manager.get_event() => {
+                    self.process_osc_event(osc_event).await?;
+                }
+                Some(json_event) = self.json_manager.get_event() => {
+                    self.process_json_event(json_event).await?;
+                }
                 _ = ticker.tick() => {
                     self.process_idle_tick().await?;
                 }
+                scope = device_watcher.changed() => {
+                    self.rescan_devices(scope);
+                }
             }
+            self.process_internal_controls()?;
         }
 
         Ok(())
@@ -327,11 +1013,54 @@ impl<'cfg> MappingEngine<'cfg> {
             .mouse_manager
             .stop()
             .context("Failed to stop Mouse Manager.");
-
-        let errors: Vec<String> = [midi_stop_result, mouse_stop_result]
-            .into_iter()
-            .filter_map(|res| res.err().map(|e| format!("- {}", e)))
-            .collect();
+        let keyboard_stop_result = self
+            .keyboard_manager
+            .stop()
+            .context("Failed to stop Keyboard Manager.");
+        let gamepad_stop_result = self
+            .gamepad_manager
+            .stop()
+            .context("Failed to stop Gamepad Manager.");
+        let touch_stop_result = self
+            .touch_manager
+            .stop()
+            .context("Failed to stop Touch Manager.");
+        let combo_watch_stop_result = self
+            .combo_watch_manager
+            .stop()
+            .context("Failed to stop Emergency Combo Watch Manager.");
+        let osc_stop_result = self
+            .osc_manager
+            .stop()
+            .context("Failed to stop OSC Manager.");
+        let json_stop_result = self
+            .json_manager
+            .stop()
+            .context("Failed to stop JSON Manager.");
+        let midi_output_stop_result = self
+            .midi_output_manager
+            .stop()
+            .context("Failed to stop MIDI Output Manager.");
+        let midi_feedback_stop_result = self
+            .midi_feedback_manager
+            .stop()
+            .context("Failed to stop MIDI Feedback Manager.");
+
+        let errors: Vec<String> = [
+            midi_stop_result,
+            mouse_stop_result,
+            keyboard_stop_result,
+            gamepad_stop_result,
+            touch_stop_result,
+            combo_watch_stop_result,
+            osc_stop_result,
+            json_stop_result,
+            midi_output_stop_result,
+            midi_feedback_stop_result,
+        ]
+        .into_iter()
+        .filter_map(|res| res.err().map(|e| format!("- {}", e)))
+        .collect();
 
         if errors.is_empty() {
             Ok(())
@@ -349,7 +1078,7 @@ impl<'cfg> MappingEngine<'cfg> {
         let prev = mapping
             .destination
             .control
-            .idle_tick_enabled_flag
+            .idle_tick_enabled_flag()
             .swap(idle_tick_required, std::sync::atomic::Ordering::Relaxed);
         if idle_tick_required != prev && self.debug {
             debug!(
@@ -367,11 +1096,105 @@ impl<'cfg> MappingEngine<'cfg> {
             Some(m) => m,
             None => return Ok(()),
         };
+        self.record_midi_touches(&msg, device_mappings);
+        self.input_event_time.set(Some(msg.timestamp));
+        let result = device_mappings.iter().try_for_each(|mapping| {
+            if !self.midi_manager.midi_message_matches_spec(&msg, mapping) {
+                return Ok(());
+            }
+            match self.midi_manager.extract_mapping_value(&msg, mapping) {
+                Some(value) => {
+                    self.execute_mapping_on_active_input(msg.device_name.as_str(), mapping, value)
+                }
+                None => Ok(()),
+            }
+        });
+        self.input_event_time.set(None);
+        result
+    }
+
+    /// Holds back the feedback to the touch controls the message comes from.
+    fn record_midi_touches(&self, msg: &MidiMessage, device_mappings: &[&ResolvedMapping]) {
+        let all_feedback = self.config_manager.get_resolved_midi_feedback();
+        if all_feedback.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let held = msg.message_type == MidiMessageType::NoteOn && msg.velocity.unwrap_or(0) > 0;
+        let device_keys: BTreeSet<&str> = device_mappings
+            .iter()
+            .map(|mapping| mapping.source.device_key.as_str())
+            .collect();
+        for device_key in device_keys {
+            let (Some(feedback), Some(device)) = (
+                all_feedback.get(device_key),
+                self.config_manager.get_resolved_midi_device(device_key),
+            ) else {
+                continue;
+            };
+            for control_key in feedback
+                .controls
+                .values()
+                .flat_map(|control| &control.touch_controls)
+            {
+                if device
+                    .controls
+                    .get(control_key)
+                    .is_some_and(|control| self.midi_manager.midi_control_matches(msg, control))
+                {
+                    self.midi_feedback_manager
+                        .touch(device_key, control_key, held, now);
+                }
+            }
+        }
+    }
+
+    /// Time for the time based transformation steps: the device time of the input event
+    /// being mapped, else now.
+    fn step_time(&self) -> Instant {
+        self.input_event_time.get().unwrap_or_else(Instant::now)
+    }
+
+    async fn process_mouse_event(&self, event: EvdevEvent) -> Result<()> {
+        let device_mappings = match self.router.get(&event.device_key) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
         for mapping in device_mappings {
-            if self.midi_manager.midi_message_matches_spec(&msg, mapping) {
-                let value = self.midi_manager.extract_midi_value(&msg);
+            if let ControlReference::Mouse(mouse_ctrl) = &mapping.source.control {
+                if mouse_ctrl.r#type == event.control_type {
+                    self.execute_mapping_on_active_input(
+                        event.device_key.as_str(),
+                        mapping,
+                        event.value as f32,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_keyboard_event(&self, event: EvdevEvent) -> Result<()> {
+        let device_mappings = match self.router.get(&event.device_key) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        for mapping in device_mappings {
+            if let ControlReference::Keyboard(keyboard_ctrl) = &mapping.source.control {
+                if keyboard_ctrl.r#type != event.control_type {
+                    continue;
+                }
+                // Autorepeat is reported as a separate value, pass it on as a press if enabled.
+                let value = if event.value == KEY_VALUE_REPEAT {
+                    if !keyboard_ctrl.repeat {
+                        continue;
+                    }
+                    1
+                } else {
+                    event.value
+                };
                 self.execute_mapping_on_active_input(
-                    msg.device_name.as_str(),
+                    event.device_key.as_str(),
                     mapping,
                     value as f32,
                 )?;
@@ -380,14 +1203,14 @@ impl<'cfg> MappingEngine<'cfg> {
         Ok(())
     }
 
-    async fn process_mouse_event(&self, event: MouseEvent) -> Result<()> {
+    async fn process_gamepad_event(&self, event: EvdevEvent) -> Result<()> {
         let device_mappings = match self.router.get(&event.device_key) {
             Some(m) => m,
             None => return Ok(()),
         };
         for mapping in device_mappings {
-            if let ControlReference::Mouse(mouse_ctrl) = &mapping.source.control {
-                if mouse_ctrl.r#type == event.control_type {
+            if let ControlReference::Gamepad(gamepad_ctrl) = &mapping.source.control {
+                if gamepad_ctrl.r#type == event.control_type {
                     self.execute_mapping_on_active_input(
                         event.device_key.as_str(),
                         mapping,
@@ -399,6 +1222,139 @@ impl<'cfg> MappingEngine<'cfg> {
         Ok(())
     }
 
+    fn grab_controller_for(&self, grab: bool) -> Option<GrabRole> {
+        if !grab {
+            return None;
+        }
+        if self
+            .config_manager
+            .get_config()
+            .global
+            .emergency_release_combo
+            .is_empty()
+        {
+            warn!("Grabbing an input device with no emergency_release_combo configured.");
+        }
+        Some(GrabRole::Grab(self.grab_controller.clone()))
+    }
+
+    /// Whether any active mouse, keyboard, gamepad or touch source device is grabbed.
+    fn any_source_grabbed(&self) -> bool {
+        self.active_src_device_keys.iter().any(|key| {
+            let config_manager = self.config_manager;
+            [
+                config_manager
+                    .get_resolved_mouse_device(key)
+                    .map(|device| device.grab),
+                config_manager
+                    .get_resolved_keyboard_device(key)
+                    .map(|device| device.grab),
+                config_manager
+                    .get_resolved_gamepad_device(key)
+                    .map(|device| device.grab),
+                config_manager
+                    .get_resolved_touch_device(key)
+                    .map(|device| device.grab),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|grab| grab)
+        })
+    }
+
+    async fn process_touch_frame(&self, frame: TouchFrame) -> Result<()> {
+        let device_mappings = match self.router.get(&frame.device_key) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        for mapping in device_mappings {
+            if let ControlReference::Touch(touch_ctrl) = &mapping.source.control {
+                if let Some(value) = touch_control_value(touch_ctrl, &frame.contacts) {
+                    self.execute_mapping_on_active_input(
+                        frame.device_key.as_str(),
+                        mapping,
+                        value,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_osc_event(&self, event: OscEvent) -> Result<()> {
+        let device_mappings = match self.router.get(&event.source_key) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        for mapping in device_mappings {
+            if let ControlReference::Osc(osc_ctrl) = &mapping.source.control {
+                if !osc_address_matches(&osc_ctrl.address, &event.message.address) {
+                    continue;
+                }
+                let Some(value) = event
+                    .message
+                    .arguments
+                    .get(osc_ctrl.argument)
+                    .and_then(|argument| argument.as_f32())
+                else {
+                    if self.debug {
+                        debug!(
+                            "OSC message {} has no finite numeric argument #{} for {}/{}",
+                            event.message.address,
+                            osc_ctrl.argument,
+                            mapping.source.device_key,
+                            mapping.source.control_key
+                        );
+                    }
+                    continue;
+                };
+                self.execute_mapping_on_active_input(event.source_key.as_str(), mapping, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_json_event(&self, event: JsonEvent) -> Result<()> {
+        let device_mappings = match self.router.get(&event.source_key) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        for mapping in device_mappings {
+            if let ControlReference::Json(json_ctrl) = &mapping.source.control {
+                if json_ctrl.name == event.control {
+                    self.execute_mapping_on_active_input(
+                        event.source_key.as_str(),
+                        mapping,
+                        event.value,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn register_source_axis_ranges(
+        &mut self,
+        src_device_key: &str,
+        axes: &[(ControlType, NumInterval<i32>)],
+    ) {
+        let ranges = self
+            .source_axis_ranges
+            .entry(src_device_key.to_string())
+            .or_default();
+        for (control_type, range) in axes {
+            if let Some(prev) = ranges.insert(*control_type, *range) {
+                if prev != *range {
+                    warn!(
+                        "Devices matched as '{}' report different ranges for {}: {:?} vs {:?}, \
+                        using the latter. Consider setting the range explicitly.",
+                        src_device_key, control_type, prev, range
+                    );
+                }
+            }
+        }
+    }
+
     fn execute_mapping_on_active_input(
         &self,
         runtime_input_device_name: &str,
@@ -407,12 +1363,7 @@ impl<'cfg> MappingEngine<'cfg> {
     ) -> Result<()> {
         let final_value =
             self.apply_transformation(runtime_input_device_name, mapping, input_value, false)?;
-        self.joystick_manager.set_control_value(
-            &mapping.destination.device_key,
-            &mapping.destination.control_key,
-            final_value,
-            false,
-        )?;
+        self.set_destination_value(mapping, final_value, false)?;
         self.set_idle_tick_enabled_on_device_control_for_mapping(mapping);
         if self.debug {
             debug!(
@@ -428,30 +1379,229 @@ impl<'cfg> MappingEngine<'cfg> {
         Ok(())
     }
 
+    fn set_destination_value(
+        &self,
+        mapping: &ResolvedMapping,
+        value: f32,
+        silent: bool,
+    ) -> Result<()> {
+        let destination = &mapping.destination;
+        let control = match &destination.control {
+            DestinationReference::Internal(control) => control,
+            DestinationReference::Joystick(_) => {
+                return self.joystick_manager.set_control_value(
+                    &destination.device_key,
+                    &destination.control_key,
+                    value,
+                    silent,
+                );
+            }
+            DestinationReference::Hat { direction, .. } => {
+                return self.joystick_manager.set_hat_direction(
+                    &destination.device_key,
+                    &destination.control_key,
+                    *direction,
+                    value != 0.0,
+                    silent,
+                );
+            }
+            DestinationReference::MidiOutput(control) => {
+                return self.midi_output_manager.send(
+                    &destination.device_key,
+                    &destination.control_key,
+                    control,
+                    value,
+                );
+            }
+        };
+        let value = (value as i32).clamp(control.range.from, control.range.to);
+        self.internal_control_states
+            .borrow_mut()
+            .insert(destination.control_key.clone(), value);
+        self.pending_internal_controls
+            .borrow_mut()
+            .insert(destination.control_key.clone());
+        if self.debug && !silent {
+            debug!(
+                "[{}][{}] = {}",
+                INTERNAL_DEVICE_KEY, destination.control_key, value
+            );
+        }
+        Ok(())
+    }
+
+    /// Current value of a virtual joystick, MIDI output or internal control.
+    fn control_state(&self, device_key: &str, control_key: &str) -> i32 {
+        if device_key == INTERNAL_DEVICE_KEY {
+            if let Some(value) = self.internal_control_states.borrow().get(control_key) {
+                return *value;
+            }
+        }
+        if let Some(value) = self
+            .midi_output_manager
+            .get_control_state(device_key, control_key)
+        {
+            return value;
+        }
+        self.joystick_manager
+            .get_control_state(device_key, control_key)
+    }
+
+    /// Runs the mappings reading the internal controls written by the last event or tick,
+    /// in dependency order so that every internal control is computed from final values.
+    fn process_internal_controls(&self) -> Result<()> {
+        if self.pending_internal_controls.borrow().is_empty() {
+            return Ok(());
+        }
+        let device_mappings = self.router.get(INTERNAL_DEVICE_KEY);
+        for control_key in self.config_manager.get_internal_control_order() {
+            if !self
+                .pending_internal_controls
+                .borrow_mut()
+                .remove(control_key)
+            {
+                continue;
+            }
+            let value = self.control_state(INTERNAL_DEVICE_KEY, control_key);
+            for mapping in device_mappings.into_iter().flatten() {
+                if mapping.source.control_key == *control_key {
+                    self.execute_mapping_on_active_input(
+                        INTERNAL_DEVICE_KEY,
+                        mapping,
+                        value as f32,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn process_idle_tick(&self) -> Result<()> {
-        for mapping in &self.idle_tick_mappings {
+        self.process_generators()?;
+        self.joystick_manager.process_key_repeats()?;
+        for (runtime_device_name, mapping) in &self.idle_tick_mappings {
             if !mapping
                 .destination
                 .control
-                .idle_tick_enabled_flag
+                .idle_tick_enabled_flag()
                 .load(std::sync::atomic::Ordering::Relaxed)
             {
                 continue;
             }
 
-            let final_value =
-                self.apply_transformation("<-- idle tick device -->", mapping, 0.0, true)?;
+            let final_value = self.apply_transformation(runtime_device_name, mapping, 0.0, true)?;
 
-            self.joystick_manager.set_control_value(
-                &mapping.destination.device_key,
-                &mapping.destination.control_key,
+            self.set_destination_value(
+                mapping,
                 final_value,
                 /*silent:*/ !self.debug_idle_tick,
             )?;
         }
+        self.process_midi_feedback();
         Ok(())
     }
 
+    /// Sends the values of the feedback sources back to their MIDI devices.
+    fn process_midi_feedback(&self) {
+        let now = Instant::now();
+        for (device_key, feedback) in self.config_manager.get_resolved_midi_feedback() {
+            for (control_key, control) in &feedback.controls {
+                let state = self.control_state(&control.source_device, &control.source_control);
+                let value = control.message.range.map_from(
+                    control.source_range.clamp(state),
+                    &control.source_range,
+                    false,
+                );
+                self.midi_feedback_manager.send(
+                    device_key,
+                    control_key,
+                    feedback,
+                    control,
+                    value,
+                    now,
+                );
+            }
+        }
+    }
+
+    fn process_generators(&self) -> Result<()> {
+        let now = Instant::now();
+        let mut values: HashMap<(&str, &str), f32> = HashMap::new();
+        for source_key in &self.generator_source_keys {
+            let Some(device_mappings) = self.router.get(source_key) else {
+                continue;
+            };
+            for mapping in device_mappings {
+                let ControlReference::Generator(generator_control) = &mapping.source.control else {
+                    continue;
+                };
+                // Every mapping of a control sees the same value.
+                let value = *values
+                    .entry((source_key, &mapping.source.control_key))
+                    .or_insert_with(|| {
+                        self.evaluate_generator(
+                            source_key,
+                            &mapping.source.control_key,
+                            generator_control,
+                            now,
+                        )
+                    });
+                let final_value = self.apply_transformation(source_key, mapping, value, false)?;
+                self.set_destination_value(
+                    mapping,
+                    final_value,
+                    /*silent:*/ !self.debug_idle_tick,
+                )?;
+                self.set_idle_tick_enabled_on_device_control_for_mapping(mapping);
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate_generator(
+        &self,
+        source_key: &str,
+        control_key: &str,
+        control: &ResolvedGeneratorControl,
+        now: Instant,
+    ) -> f32 {
+        let mut generators = self.generators.borrow_mut();
+        let key = (source_key.to_string(), control_key.to_string());
+        let (generator, last_time) = generators.entry(key).or_insert_with(|| {
+            let seed = control
+                .seed
+                .unwrap_or_else(|| Generator::seed_from_key(source_key, control_key));
+            (Generator::new(seed), now)
+        });
+        let dt = (now - *last_time).as_secs_f32();
+        *last_time = now;
+
+        let wave = generator.advance(
+            control.waveform,
+            &control.steps,
+            self.generator_parameter(&control.frequency),
+            dt,
+        );
+        let value = self.generator_parameter(&control.offset)
+            + self.generator_parameter(&control.amplitude) * wave;
+        control.range.clamp(value)
+    }
+
+    fn generator_parameter(&self, parameter: &ResolvedGeneratorParameter) -> f32 {
+        match parameter {
+            ResolvedGeneratorParameter::Value(v) => *v,
+            ResolvedGeneratorParameter::Reference {
+                device,
+                control,
+                control_range,
+                range,
+            } => {
+                let val = self.control_state(device, control);
+                range.map_from(val as f32, control_range, false)
+            }
+        }
+    }
+
     fn requires_idle_tick(&self, mapping: &ResolvedMapping) -> bool {
         let mut idle_tick_requirement_info = mapping.idle_tick_requirement_info__.lock().unwrap();
         // TODO: must move to config parsing stage.
@@ -475,23 +1625,51 @@ impl<'cfg> MappingEngine<'cfg> {
         is_idle_tick: bool,
     ) -> Result<f32> {
         // TODO: simplify: both either optional or not.
+        let integer_range = |range: Option<NumInterval<i32>>| {
+            range
+                .unwrap_or(NumInterval::new(0, 127))
+                .cast::<f32>()
+                .unwrap()
+        };
         let src_range = match &mapping.source.control {
-            ControlReference::Mouse(mouse_control) => Some(mouse_control.range),
-            ControlReference::Midi(midi_control) => midi_control.range,
-        }
-        .unwrap_or(NumInterval::new(0, 127))
-        .cast::<f32>()
-        .unwrap();
+            ControlReference::Mouse(mouse_control) => integer_range(Some(mouse_control.range)),
+            ControlReference::Keyboard(keyboard_control) => {
+                integer_range(Some(keyboard_control.range))
+            }
+            ControlReference::Gamepad(gamepad_control) => {
+                integer_range(gamepad_control.range.or_else(|| {
+                    self.source_axis_ranges
+                        .get(&mapping.source.device_key)
+                        .and_then(|ranges| ranges.get(&gamepad_control.r#type))
+                        .copied()
+                }))
+            }
+            ControlReference::Touch(touch_control) => {
+                integer_range(touch_control.range.or_else(|| {
+                    self.source_axis_ranges
+                        .get(&mapping.source.device_key)
+                        .and_then(|ranges| touch_control_device_range(touch_control.r#type, ranges))
+                }))
+            }
+            ControlReference::Midi(midi_control) => integer_range(midi_control.range),
+            ControlReference::Osc(osc_control) => osc_control.range,
+            ControlReference::Json(json_control) => json_control.range,
+            ControlReference::Generator(generator_control) => generator_control.range,
+            ControlReference::Internal(internal_control) => {
+                integer_range(Some(internal_control.range))
+            }
+        };
 
         let dst_range = mapping
             .destination
             .control
-            .range
+            .range()
             .cast::<f32>()
             .unwrap_or(NumInterval::new(i32::MIN as f32, i32::MAX as f32));
 
         let mut current_value = value;
         let mut current_range = src_range;
+        let instance_index = self.device_instance_of(runtime_input_device_name);
 
         if !src_range.contains_inclusive(current_value) {
             warn!(
@@ -504,6 +1682,7 @@ impl<'cfg> MappingEngine<'cfg> {
         for step in mapping.transformation.iter() {
             (current_value, current_range) = self.apply_transformation_step(
                 mapping,
+                instance_index,
                 step,
                 current_value,
                 current_range,
@@ -522,9 +1701,11 @@ impl<'cfg> MappingEngine<'cfg> {
         Ok(dst_range.clamp(current_value))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn apply_transformation_step(
         &self,
         mapping: &'cfg ResolvedMapping,
+        instance_index: usize,
         step: &ResolvedTransformationStep,
         value: f32,
         current_range: NumInterval<f32>,
@@ -544,7 +1725,7 @@ impl<'cfg> MappingEngine<'cfg> {
                 }
                 Ok(self.apply_integrate_transform(
                     mapping,
-                    *runtime_state_id,
+                    (*runtime_state_id, instance_index),
                     integrate,
                     value,
                     current_range,
@@ -569,7 +1750,7 @@ impl<'cfg> MappingEngine<'cfg> {
                 steering,
             } => Ok(self.apply_steering_transform(
                 mapping,
-                *runtime_state_id,
+                (*runtime_state_id, instance_index),
                 steering,
                 value,
                 dst_range,
@@ -579,7 +1760,7 @@ impl<'cfg> MappingEngine<'cfg> {
                 pedal_smoother,
             } => Ok(self.apply_pedal_smoother_transform(
                 mapping,
-                *runtime_state_id,
+                (*runtime_state_id, instance_index),
                 pedal_smoother,
                 value,
                 current_range,
@@ -591,11 +1772,11 @@ impl<'cfg> MappingEngine<'cfg> {
             } => {
                 if is_idle_tick && !moving_average.on_idle.unwrap_or(true) {
                     let _ = // State update.
-                        self.apply_ema(*runtime_state_id, moving_average, value);
+                        self.apply_ema((*runtime_state_id, instance_index), moving_average, value);
                     return Ok((value, current_range));
                 }
                 Ok((
-                    self.apply_ema(*runtime_state_id, moving_average, value),
+                    self.apply_ema((*runtime_state_id, instance_index), moving_average, value),
                     current_range,
                 ))
             }
@@ -662,11 +1843,19 @@ impl<'cfg> MappingEngine<'cfg> {
             } => {
                 if is_idle_tick && !lowpass.on_idle.unwrap_or(true) {
                     // Update the state on idle.
-                    let _ = self.apply_low_pass_transform(*runtime_state_id, lowpass, value);
+                    let _ = self.apply_low_pass_transform(
+                        (*runtime_state_id, instance_index),
+                        lowpass,
+                        value,
+                    );
                     return Ok((value, current_range));
                 }
                 Ok((
-                    self.apply_low_pass_transform(*runtime_state_id, lowpass, value),
+                    self.apply_low_pass_transform(
+                        (*runtime_state_id, instance_index),
+                        lowpass,
+                        value,
+                    ),
                     current_range,
                 ))
             }
@@ -689,14 +1878,14 @@ impl<'cfg> MappingEngine<'cfg> {
 
     fn apply_ema(
         &self,
-        runtime_state_id: StepRuntimeStateId,
+        state_key: StepStateKey,
         moving_average: &crate::schemas::EmaFilterTransform,
         value: f32,
     ) -> f32 {
         let mut data = self.moving_average_step_data.borrow_mut();
-        let now = Instant::now();
-        let prev_time = data.time1.entry(runtime_state_id).or_insert(now).clone();
-        let prev_val = data.f32_1.entry(runtime_state_id).or_insert(value).clone();
+        let now = self.step_time();
+        let prev_time = *data.time1.entry(state_key).or_insert(now);
+        let prev_val = *data.f32_1.entry(state_key).or_insert(value);
         ValueFilter::ema(
             prev_val,
             value,
@@ -707,22 +1896,22 @@ impl<'cfg> MappingEngine<'cfg> {
 
     fn apply_low_pass_transform(
         &self,
-        runtime_state_id: StepRuntimeStateId,
+        state_key: StepStateKey,
         lowpass: &crate::schemas::LowPassTransform,
         current_input: f32,
     ) -> f32 {
         let mut data = self.transform_step_data.borrow_mut();
 
-        let now = Instant::now();
-        let prev_time = data.time1.entry(runtime_state_id).or_insert(now);
+        let now = self.step_time();
+        let prev_time = data.time1.entry(state_key).or_insert(now);
         let dt = (now - *prev_time).as_secs_f32();
         *prev_time = now;
 
-        let prev_val = *data.f32_1.entry(runtime_state_id).or_insert(current_input);
+        let prev_val = *data.f32_1.entry(state_key).or_insert(current_input);
         let time_constant = lowpass.time_constant.unwrap_or(0.1);
 
         let out = ValueFilter::lowpass(prev_val, current_input, dt, time_constant);
-        let _ = data.f32_1.insert(runtime_state_id, out);
+        let _ = data.f32_1.insert(state_key, out);
         out
     }
 
@@ -891,12 +2080,12 @@ impl<'cfg> MappingEngine<'cfg> {
     fn apply_integrate_transform(
         &self,
         _mapping: &'cfg ResolvedMapping,
-        runtime_state_id: StepRuntimeStateId,
-        integrate: &IntegrateTransform,
+        state_key: StepStateKey,
+        integrate: &ResolvedIntegrateTransform,
         mut delta_value: f32,
         _current_range: NumInterval<f32>,
     ) -> (f32, NumInterval<f32>) {
-        let integration_range = integrate.range.unwrap_or(NumInterval::new(0.0, 750.0));
+        let integration_range = integrate.range;
 
         let deadzone = integrate.deadzone_norm.unwrap_or(0.0).max(0.0);
         if delta_value.abs() < deadzone * integration_range.span() {
@@ -906,11 +2095,11 @@ impl<'cfg> MappingEngine<'cfg> {
         let mut data = self.transform_step_data.borrow_mut();
         let prev = *data
             .f32_1
-            .entry(runtime_state_id)
+            .entry(state_key)
             .or_insert((integration_range.from + integration_range.to) * 0.5);
 
         let out_val = integration_range.clamp(prev + delta_value);
-        *data.f32_1.entry(runtime_state_id).or_insert(0.0) = out_val;
+        *data.f32_1.entry(state_key).or_insert(0.0) = out_val;
 
         (out_val, integration_range)
     }
@@ -918,19 +2107,19 @@ impl<'cfg> MappingEngine<'cfg> {
     fn apply_steering_transform(
         &self,
         mapping: &'cfg ResolvedMapping,
-        runtime_state_id: StepRuntimeStateId,
+        state_key: StepStateKey,
         steering: &ResolvedSteeringTransform,
         value: f32,
         dst_range: NumInterval<f32>,
     ) -> (f32, NumInterval<f32>) {
-        let last_pipeline_out = self.joystick_manager.get_control_state(
+        let last_pipeline_out = self.control_state(
             &mapping.destination.device_key,
             &mapping.destination.control_key,
         );
 
         let mut pos_in_symm_unit = crate::common::SYMM_UNIT_INTERVAL.map_from(
             last_pipeline_out,
-            &mapping.destination.control.range.cast().unwrap(),
+            &mapping.destination.control.range().cast().unwrap(),
             false,
         );
 
@@ -939,12 +2128,12 @@ impl<'cfg> MappingEngine<'cfg> {
 
         let mut data = self.transform_step_data.borrow_mut();
 
-        let now = Instant::now();
-        let dt = match data.time1.get(&(runtime_state_id)) {
+        let now = self.step_time();
+        let dt = match data.time1.get(&state_key) {
             Some(prev) => (now - *prev).as_secs_f32(),
             _ => 0.0,
         };
-        data.time1.insert(runtime_state_id, now);
+        data.time1.insert(state_key, now);
 
         let counts_to_lock = steering.counts_to_lock.max(1.0);
         let delta = value / (counts_to_lock / 2.0);
@@ -954,7 +2143,7 @@ impl<'cfg> MappingEngine<'cfg> {
 
         // NB: this augments user_input_ema_filter with a simpler smoothing technique
         //     in case  we don't use the EMA below.
-        pos_in_symm_unit = (1.0 - steering.smoothing_alpha) * (last_pos_in_symm_unnit as f32)
+        pos_in_symm_unit = (1.0 - steering.smoothing_alpha) * last_pos_in_symm_unnit
             + steering.smoothing_alpha * pos_in_symm_unit;
 
         if delta != 0.0 {
@@ -968,7 +2157,7 @@ impl<'cfg> MappingEngine<'cfg> {
 
             if let Some(moving_average) = &steering.user_input_ema_filter_average {
                 pos_in_symm_unit = self.apply_ema(
-                    steering.user_input_ema_filter_runtime_state_id,
+                    (steering.user_input_ema_filter_runtime_state_id, state_key.1),
                     moving_average,
                     pos_in_symm_unit,
                 );
@@ -983,7 +2172,7 @@ impl<'cfg> MappingEngine<'cfg> {
                     control,
                     range,
                 }) => {
-                    let val = self.joystick_manager.get_control_state(device, control);
+                    let val = self.control_state(device, control);
                     crate::common::UNIT_INTERVAL.map_from(val as f32, &range.cast().unwrap(), false)
                 }
                 None => 0.0,
@@ -1050,7 +2239,7 @@ impl<'cfg> MappingEngine<'cfg> {
     fn apply_pedal_smoother_transform(
         &self,
         _mapping: &'cfg ResolvedMapping,
-        runtime_state_id: StepRuntimeStateId,
+        state_key: StepStateKey,
         pedal_smoother: &ResolvedPedalSmootherTransform,
         value: f32,
         current_range: NumInterval<f32>,
@@ -1059,26 +2248,26 @@ impl<'cfg> MappingEngine<'cfg> {
         let mut data = self.transform_step_data.borrow_mut();
 
         let initial_value = current_range.from;
-        let prev_out = *data.f32_2.entry(runtime_state_id).or_insert(initial_value);
-        let last_target = *data.f32_1.entry(runtime_state_id).or_insert(initial_value);
+        let prev_out = *data.f32_2.entry(state_key).or_insert(initial_value);
+        let last_target = *data.f32_1.entry(state_key).or_insert(initial_value);
 
-        let now = Instant::now();
-        let dt = if let Some(prev) = data.time1.get(&(runtime_state_id)) {
+        let now = self.step_time();
+        let dt = if let Some(prev) = data.time1.get(&state_key) {
             (now - *prev).as_secs_f32()
         } else {
             0.0
         };
 
-        let dt_user_input = if let Some(prev) = data.time2.get(&(runtime_state_id)) {
+        let dt_user_input = if let Some(prev) = data.time2.get(&state_key) {
             (now - *prev).as_secs_f32()
         } else {
             0.0
         };
 
-        data.time1.insert(runtime_state_id, now);
+        data.time1.insert(state_key, now);
 
         let target = if !is_idle_tick {
-            data.f32_1.insert(runtime_state_id, value);
+            data.f32_1.insert(state_key, value);
             value
         } else {
             last_target
@@ -1097,8 +2286,7 @@ impl<'cfg> MappingEngine<'cfg> {
                             control,
                             range,
                         }) => {
-                            let control_value =
-                                self.joystick_manager.get_control_state(device, control);
+                            let control_value = self.control_state(device, control);
                             range.normalize_to_unit(range.try_invert_value(control_value).unwrap())
                         }
                         Some(ResolvedHoldFactor::Value(v)) => *v,
@@ -1124,9 +2312,9 @@ impl<'cfg> MappingEngine<'cfg> {
             final_out = (smoothing_alpha) * final_out + (1.0 - smoothing_alpha) * prev_out;
 
             final_out = current_range.clamp(final_out);
-            data.f32_2.insert(runtime_state_id, final_out);
+            data.f32_2.insert(state_key, final_out);
         } else {
-            data.time2.insert(runtime_state_id, now);
+            data.time2.insert(state_key, now);
         }
 
         Ok((final_out, current_range))
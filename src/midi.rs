@@ -1,17 +1,43 @@
 use anyhow::{bail, Context, Result};
 use log::{debug, info, warn};
+use midir::os::unix::VirtualInput;
 use midir::{MidiInput, MidiInputConnection};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
 use crate::config;
+use crate::schemas::{MidiNoteGroup, MidiVelocityModulation, ResolvedMidiPortMatch};
 
 #[derive(Debug, Clone)]
 pub(crate) struct MidiDeviceInfo {
     pub(crate) name: String,
     pub(crate) port_index: usize,
+    /// ALSA sequencer client and port numbers, parsed from the port name.
+    pub(crate) client: Option<i32>,
+    pub(crate) port: Option<i32>,
+    /// Set for our own virtual input ports, created on open instead of connected to.
+    pub(crate) virtual_port_name: Option<String>,
+}
+
+/// Device name of a virtual input port, as other ALSA clients list it (without the address).
+pub(crate) fn virtual_port_device_name(port_name: &str) -> String {
+    format!("{} Virtual:{}", config::APP_NAME, port_name)
+}
+
+/// Parses the trailing "client:port" address ALSA port names end with, e.g. "... 24:0".
+pub(crate) fn parse_alsa_port_address(port_name: &str) -> Option<(i32, i32)> {
+    let (client, port) = port_name.rsplit(' ').next()?.split_once(':')?;
+    Some((client.parse().ok()?, port.parse().ok()?))
+}
+
+/// Raises a note velocity towards 127 by the aftertouch pressure (0 keeps the velocity).
+fn modulate_velocity(velocity: u8, pressure: u8) -> u8 {
+    let velocity = velocity.min(127) as u16;
+    let pressure = pressure.min(127) as u16;
+    (velocity + ((127 - velocity) * pressure + 63) / 127) as u8
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +50,16 @@ pub(crate) struct MidiMessage {
     pub(crate) control: Option<u8>,
     pub(crate) value: Option<u8>,
     pub(crate) pitch: Option<i16>,
+    /// NRPN/RPN parameter number.
+    pub(crate) parameter: Option<u16>,
+    /// Combined value of 14-bit controller pairs and NRPN/RPN data entry.
+    pub(crate) value_14bit: Option<u16>,
+    /// Complete system exclusive message, F0 to F7.
+    pub(crate) sysex: Option<Vec<u8>>,
+    /// Beats per minute measured from the clock.
+    pub(crate) tempo: Option<f32>,
+    /// When the device driver received the message.
+    pub(crate) timestamp: Instant,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -35,6 +71,384 @@ pub(crate) enum MidiMessageType {
     Aftertouch,
     PolyAftertouch,
     ProgramChange,
+    ControlChange14bit,
+    Nrpn,
+    Rpn,
+    SysEx,
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    /// Sixteenth notes since the song start, 0-16383.
+    SongPosition,
+    /// Measured from the clock ticks.
+    Tempo,
+}
+
+pub(crate) const MAX_14BIT_VALUE: u16 = 0x3FFF;
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_DATA_INCREMENT: u8 = 96;
+const CC_DATA_DECREMENT: u8 = 97;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+/// NRPN or RPN 127/127 deselects the parameter, so stray data entry doesn't change it.
+const PARAMETER_NULL: u16 = 0x3FFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ParameterKind {
+    Nrpn,
+    Rpn,
+}
+
+#[derive(Debug, Default)]
+struct ChannelDecoderState {
+    /// Last MSB of controllers 0-31, LSBs on 32-63 refine it.
+    cc_msb: [Option<u8>; 32],
+    /// Controllers 0-31 seen with an LSB, their values complete only on the LSB.
+    cc_paired: [bool; 32],
+    parameter_kind: Option<ParameterKind>,
+    parameter_msb: Option<u8>,
+    parameter_lsb: Option<u8>,
+    parameter_values: HashMap<(ParameterKind, u16), u16>,
+    /// Parameters seen with a data entry LSB, their values complete only on the LSB.
+    paired_parameters: HashSet<(ParameterKind, u16)>,
+}
+
+impl ChannelDecoderState {
+    fn selected_parameter(&self) -> Option<(ParameterKind, u16)> {
+        let kind = self.parameter_kind?;
+        let parameter = ((self.parameter_msb? as u16) << 7) | self.parameter_lsb? as u16;
+        if parameter == PARAMETER_NULL {
+            return None;
+        }
+        Some((kind, parameter))
+    }
+}
+
+/// Per-device state machine combining control change sequences into 14-bit values:
+/// MSB/LSB controller pairs (CC 0-31 with CC 32-63) and NRPN/RPN parameter data entry.
+/// NB: as the MIDI spec demands, a new MSB resets the LSB, devices send the LSB right after.
+/// Once a controller or parameter has sent an LSB, its MSB alone emits nothing, so that
+/// the value doesn't jump to the MSB step before the LSB arrives.
+#[derive(Debug, Default)]
+pub(crate) struct HighResolutionDecoder {
+    channels: [ChannelDecoderState; 16],
+}
+
+impl HighResolutionDecoder {
+    /// Returns the 14-bit message completed by a control change, if any.
+    pub(crate) fn decode(&mut self, msg: &MidiMessage) -> Option<MidiMessage> {
+        if msg.message_type != MidiMessageType::ControlChange {
+            return None;
+        }
+        let (control, value) = (msg.control?, msg.value?);
+        let state = &mut self.channels[(msg.channel & 0x0F) as usize];
+
+        match control {
+            CC_NRPN_MSB | CC_NRPN_LSB | CC_RPN_MSB | CC_RPN_LSB => {
+                let kind = if control >= CC_RPN_LSB {
+                    ParameterKind::Rpn
+                } else {
+                    ParameterKind::Nrpn
+                };
+                if state.parameter_kind != Some(kind) {
+                    state.parameter_kind = Some(kind);
+                    state.parameter_msb = None;
+                    state.parameter_lsb = None;
+                }
+                if control == CC_NRPN_MSB || control == CC_RPN_MSB {
+                    state.parameter_msb = Some(value);
+                } else {
+                    state.parameter_lsb = Some(value);
+                }
+                return None;
+            }
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB | CC_DATA_INCREMENT | CC_DATA_DECREMENT => {
+                if let Some((kind, parameter)) = state.selected_parameter() {
+                    let current = state.parameter_values.get(&(kind, parameter)).copied();
+                    let data = match control {
+                        CC_DATA_ENTRY_MSB => (value as u16) << 7,
+                        CC_DATA_ENTRY_LSB => (current.unwrap_or(0) & !0x7F) | value as u16,
+                        CC_DATA_INCREMENT => (current.unwrap_or(0) + 1).min(MAX_14BIT_VALUE),
+                        _ => current.unwrap_or(0).saturating_sub(1),
+                    };
+                    state.parameter_values.insert((kind, parameter), data);
+                    match control {
+                        CC_DATA_ENTRY_MSB
+                            if state.paired_parameters.contains(&(kind, parameter)) =>
+                        {
+                            return None;
+                        }
+                        CC_DATA_ENTRY_LSB => {
+                            state.paired_parameters.insert((kind, parameter));
+                        }
+                        _ => {}
+                    }
+                    return Some(MidiMessage {
+                        message_type: match kind {
+                            ParameterKind::Nrpn => MidiMessageType::Nrpn,
+                            ParameterKind::Rpn => MidiMessageType::Rpn,
+                        },
+                        control: None,
+                        value: None,
+                        parameter: Some(parameter),
+                        value_14bit: Some(data),
+                        ..msg.clone()
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        let (msb_control, data) = match control {
+            0..=31 => {
+                state.cc_msb[control as usize] = Some(value);
+                if state.cc_paired[control as usize] {
+                    return None;
+                }
+                (control, (value as u16) << 7)
+            }
+            32..=63 => {
+                let msb = state.cc_msb[(control - 32) as usize]?;
+                state.cc_paired[(control - 32) as usize] = true;
+                (control - 32, ((msb as u16) << 7) | value as u16)
+            }
+            _ => return None,
+        };
+        Some(MidiMessage {
+            message_type: MidiMessageType::ControlChange14bit,
+            control: Some(msb_control),
+            value: None,
+            value_14bit: Some(data),
+            ..msg.clone()
+        })
+    }
+}
+
+const CLOCKS_PER_BEAT: usize = 24;
+/// Clock ticks per song position step (a sixteenth note).
+const CLOCKS_PER_SONG_POSITION: u8 = 6;
+/// Clock tick interval at 20 BPM, a longer pause restarts the tempo measurement.
+const MAX_CLOCK_INTERVAL: Duration = Duration::from_millis(125);
+
+/// Per-device state of MIDI clock and transport: the tempo measured over the last beat
+/// of clock ticks, and the song position advanced by the clock while playing.
+#[derive(Debug, Default)]
+pub(crate) struct MidiClockTracker {
+    tick_times: VecDeque<Instant>,
+    tempo: Option<f32>,
+    playing: bool,
+    song_position: u16,
+    ticks_since_position: u8,
+}
+
+impl MidiClockTracker {
+    /// Returns the tempo and song position changes caused by a message.
+    pub(crate) fn track(&mut self, msg: &MidiMessage) -> Vec<MidiMessage> {
+        let mut changes = Vec::new();
+        match msg.message_type {
+            MidiMessageType::Clock => {
+                if self.tick_times.back().is_some_and(|last| {
+                    msg.timestamp.saturating_duration_since(*last) > MAX_CLOCK_INTERVAL
+                }) {
+                    self.tick_times.clear();
+                }
+                self.tick_times.push_back(msg.timestamp);
+                if self.tick_times.len() > CLOCKS_PER_BEAT + 1 {
+                    self.tick_times.pop_front();
+                }
+                if let Some(tempo) = self.measure_tempo() {
+                    if self.tempo != Some(tempo) {
+                        self.tempo = Some(tempo);
+                        changes.push(MidiMessage {
+                            message_type: MidiMessageType::Tempo,
+                            tempo: Some(tempo),
+                            ..msg.clone()
+                        });
+                    }
+                }
+                if self.playing {
+                    self.ticks_since_position += 1;
+                    if self.ticks_since_position == CLOCKS_PER_SONG_POSITION {
+                        self.ticks_since_position = 0;
+                        if self.song_position < MAX_14BIT_VALUE {
+                            self.song_position += 1;
+                            changes.push(self.song_position_message(msg));
+                        }
+                    }
+                }
+            }
+            MidiMessageType::Start => {
+                self.playing = true;
+                self.ticks_since_position = 0;
+                if self.song_position != 0 {
+                    self.song_position = 0;
+                    changes.push(self.song_position_message(msg));
+                }
+            }
+            MidiMessageType::Continue => self.playing = true,
+            MidiMessageType::Stop => self.playing = false,
+            MidiMessageType::SongPosition => {
+                self.song_position = msg.value_14bit.unwrap_or(0);
+                self.ticks_since_position = 0;
+            }
+            _ => {}
+        }
+        changes
+    }
+
+    /// Beats per minute, rounded to 0.1, none until two ticks were seen.
+    fn measure_tempo(&self) -> Option<f32> {
+        if self.tick_times.len() < 2 {
+            return None;
+        }
+        let (first, last) = (self.tick_times.front()?, self.tick_times.back()?);
+        let tick_interval = (*last - *first).as_secs_f32() / (self.tick_times.len() - 1) as f32;
+        if tick_interval <= 0.0 {
+            return None;
+        }
+        let tempo = 60.0 / (tick_interval * CLOCKS_PER_BEAT as f32);
+        Some((tempo * 10.0).round() / 10.0)
+    }
+
+    fn song_position_message(&self, msg: &MidiMessage) -> MidiMessage {
+        MidiMessage {
+            message_type: MidiMessageType::SongPosition,
+            value_14bit: Some(self.song_position),
+            ..msg.clone()
+        }
+    }
+}
+
+/// Transport buttons are held from their message until the next transport message.
+fn transport_button_value(
+    msg_type: &MidiMessageType,
+    spec_type: &crate::schemas::MidiMessageType,
+) -> Option<f32> {
+    use crate::schemas::MidiMessageType as SpecType;
+    let held = match spec_type {
+        SpecType::TransportStart => MidiMessageType::Start,
+        SpecType::TransportContinue => MidiMessageType::Continue,
+        SpecType::TransportStop => MidiMessageType::Stop,
+        _ => return None,
+    };
+    Some(if *msg_type == held { 127.0 } else { 0.0 })
+}
+
+/// Splits raw input into complete messages: expands running status, passes
+/// interleaved real-time bytes through and joins SysEx split over several callbacks.
+#[derive(Debug, Default)]
+struct MidiStreamParser {
+    running_status: Option<u8>,
+    pending: Vec<u8>,
+    sysex: Option<Vec<u8>>,
+}
+
+impl MidiStreamParser {
+    fn data_length(status: u8) -> usize {
+        match status {
+            0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+            0x80..=0xEF | 0xF2 => 2,
+            _ => 0,
+        }
+    }
+
+    fn feed(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        for &byte in data {
+            // Real-time messages may appear anywhere, even inside other messages.
+            if byte >= 0xF8 {
+                messages.push(vec![byte]);
+                continue;
+            }
+            if let Some(sysex) = &mut self.sysex {
+                if byte < 0x80 {
+                    sysex.push(byte);
+                    continue;
+                }
+                // Any status ends the SysEx, only F7 completes it.
+                let mut sysex = self.sysex.take().unwrap_or_default();
+                if byte == 0xF7 {
+                    sysex.push(byte);
+                    messages.push(sysex);
+                    continue;
+                }
+            }
+            if byte == 0xF0 {
+                self.sysex = Some(vec![byte]);
+                self.running_status = None;
+                self.pending.clear();
+                continue;
+            }
+            if byte >= 0x80 {
+                self.pending.clear();
+                self.pending.push(byte);
+                // System common messages cancel the running status.
+                self.running_status = (byte < 0xF0).then_some(byte);
+            } else {
+                if self.pending.is_empty() {
+                    let Some(status) = self.running_status else {
+                        continue;
+                    };
+                    self.pending.push(status);
+                }
+                self.pending.push(byte);
+            }
+            if self.pending.len() == 1 + Self::data_length(self.pending[0]) {
+                messages.push(std::mem::take(&mut self.pending));
+            }
+        }
+        messages
+    }
+}
+
+/// Longest delay expected between an event and its processing, older timestamps are
+/// treated as a drifted or missing driver clock.
+const MAX_TIMESTAMP_LATENCY: Duration = Duration::from_millis(50);
+
+/// Maps driver timestamps (microseconds from an arbitrary start) to `Instant`s.
+#[derive(Debug, Default)]
+struct MidiTimestampClock {
+    base: Option<Instant>,
+}
+
+impl MidiTimestampClock {
+    fn event_time(&mut self, stamp_micros: u64, now: Instant) -> Instant {
+        let stamp = Duration::from_micros(stamp_micros);
+        let Some(candidate) = now.checked_sub(stamp) else {
+            return now;
+        };
+        // Messages arrive after they happened, so the earliest base is the closest.
+        let mut event_time = self.base.map_or(candidate, |base| base.min(candidate)) + stamp;
+        if let Some(oldest) = now.checked_sub(MAX_TIMESTAMP_LATENCY) {
+            event_time = event_time.max(oldest);
+        }
+        self.base = Some(event_time - stamp);
+        event_time
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HeldNote {
+    channel: u8,
+    note: u8,
+    velocity: u8,
+}
+
+/// Value of a note group from its held notes, ordered by press time.
+/// `None` keeps the previous value (no note left to follow).
+fn note_group_value(held: &[HeldNote], group: MidiNoteGroup) -> Option<i32> {
+    let notes = held.iter().map(|held| held.note as i32);
+    match group {
+        MidiNoteGroup::Lowest => notes.min(),
+        MidiNoteGroup::Highest => notes.max(),
+        MidiNoteGroup::Last => held.last().map(|held| held.note as i32),
+        MidiNoteGroup::Count => Some(held.len() as i32),
+        MidiNoteGroup::AnyHeld => Some(if held.is_empty() { 0 } else { 127 }),
+    }
 }
 
 pub(crate) struct MidiManager {
@@ -44,7 +458,9 @@ pub(crate) struct MidiManager {
     message_sender: mpsc::UnboundedSender<MidiMessage>,
     _engine_stop_token: CancellationToken,
     message_receiver: mpsc::UnboundedReceiver<MidiMessage>,
-    note_states: Arc<Mutex<HashMap<String, HashSet<u8>>>>,
+    /// Held notes per device, ordered by press time.
+    note_states: Arc<Mutex<HashMap<String, Vec<HeldNote>>>>,
+    virtual_port_names: Vec<String>,
 }
 
 impl MidiManager {
@@ -62,18 +478,28 @@ impl MidiManager {
             message_sender: tx,
             message_receiver: rx,
             note_states: Arc::new(Mutex::new(HashMap::new())),
+            virtual_port_names: Vec::new(),
         })
     }
 
+    /// Virtual input ports to list along with the existing ones, each is created when opened.
+    pub(crate) fn set_virtual_ports(&mut self, port_names: &[String]) {
+        self.virtual_port_names = port_names.to_vec();
+    }
+
     pub(crate) fn enumerate_devices(&self) -> Vec<MidiDeviceInfo> {
         let ports = self.midi_input.ports();
         let mut devices = Vec::new();
         for (i, port) in ports.iter().enumerate() {
             match self.midi_input.port_name(port) {
                 Ok(name) => {
+                    let address = parse_alsa_port_address(&name);
                     devices.push(MidiDeviceInfo {
                         name,
                         port_index: i,
+                        client: address.map(|(client, _)| client),
+                        port: address.map(|(_, port)| port),
+                        virtual_port_name: None,
                     });
                 }
                 Err(error) => {
@@ -81,17 +507,33 @@ impl MidiManager {
                 }
             }
         }
+        // NB: our virtual ports are writable only, so they are never among the input ports.
+        for port_name in &self.virtual_port_names {
+            devices.push(MidiDeviceInfo {
+                name: virtual_port_device_name(port_name),
+                port_index: 0,
+                client: None,
+                port: None,
+                virtual_port_name: Some(port_name.clone()),
+            });
+        }
         devices
     }
 
     pub(crate) fn match_device(
         &self,
         device_name_regex: &regex::Regex,
+        port_match: &ResolvedMidiPortMatch,
         devices: &[MidiDeviceInfo],
     ) -> Vec<String> {
         let mut matched = Vec::new();
         for device in devices {
-            if device_name_regex.is_match(&device.name) {
+            if device_name_regex.is_match(&device.name)
+                && port_match
+                    .client
+                    .is_none_or(|client| device.client == Some(client))
+                && port_match.port.is_none_or(|port| device.port == Some(port))
+            {
                 matched.push(device.name.clone());
             }
         }
@@ -101,53 +543,22 @@ impl MidiManager {
     pub(crate) fn open_device(&mut self, device_name: &str) -> Result<()> {
         let devices = self.enumerate_devices();
         if let Some(device) = devices.iter().find(|d| d.name == device_name) {
-            let sender = self.message_sender.clone();
-            let note_states = self.note_states.clone();
-            let debug = self.debug;
-            let device_name_clone = device.name.clone();
-            let midi_in = MidiInput::new(&format!("{} {}", config::APP_NAME, device_name))?;
-            let ports = midi_in.ports();
-            if device.port_index >= ports.len() {
-                bail!("Invalid port index");
-            }
-            let port = ports[device.port_index].clone();
-            let device_name_for_closure = device_name_clone.clone();
-            let connection = midi_in
-                .connect(
-                    &port,
-                    &device_name_clone,
-                    move |_stamp, message, _| {
-                        if let Some(msg) =
-                            Self::parse_midi_message(message, &device_name_for_closure, debug)
-                        {
-                            // Update note states
-                            if msg.message_type == MidiMessageType::NoteOn {
-                                if let Some(note) = msg.note {
-                                    let mut states = note_states.lock().unwrap();
-                                    states
-                                        .entry(device_name_for_closure.clone())
-                                        .or_default()
-                                        .insert(note);
-                                }
-                            } else if msg.message_type == MidiMessageType::NoteOff {
-                                if let Some(note) = msg.note {
-                                    let mut states = note_states.lock().unwrap();
-                                    if let Some(notes) = states.get_mut(&device_name_for_closure) {
-                                        notes.remove(&note);
-                                    }
-                                }
-                            }
-
-                            if debug {
-                                debug!("MIDI: {:?}", msg);
-                            }
-
-                            let _ = sender.send(msg);
-                        }
-                    },
-                    (),
-                )
-                .map_err(|e| anyhow::anyhow!("Failed to connect to MIDI device: {}", e))?;
+            let connection = if let Some(port_name) = &device.virtual_port_name {
+                let midi_in = MidiInput::new(&format!("{} Virtual", config::APP_NAME))?;
+                midi_in
+                    .create_virtual(port_name, self.message_handler(device_name), ())
+                    .map_err(|e| anyhow::anyhow!("Failed to create virtual MIDI port: {}", e))?
+            } else {
+                let midi_in = MidiInput::new(&format!("{} {}", config::APP_NAME, device_name))?;
+                let ports = midi_in.ports();
+                if device.port_index >= ports.len() {
+                    bail!("Invalid port index");
+                }
+                let port = ports[device.port_index].clone();
+                midi_in
+                    .connect(&port, device_name, self.message_handler(device_name), ())
+                    .map_err(|e| anyhow::anyhow!("Failed to connect to MIDI device: {}", e))?
+            };
 
             self.connections
                 .lock()
@@ -162,16 +573,98 @@ impl MidiManager {
         }
     }
 
-    fn parse_midi_message(data: &[u8], device_name: &str, debug: bool) -> Option<MidiMessage> {
+    /// Input callback parsing messages of a device and passing them on to `get_message`.
+    fn message_handler(
+        &self,
+        device_name: &str,
+    ) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static {
+        let sender = self.message_sender.clone();
+        let note_states = self.note_states.clone();
+        let debug = self.debug;
+        let device_name = device_name.to_string();
+        let mut decoder = HighResolutionDecoder::default();
+        let mut clock_tracker = MidiClockTracker::default();
+        let mut parser = MidiStreamParser::default();
+        let mut clock = MidiTimestampClock::default();
+        move |stamp, message, _| {
+            let timestamp = clock.event_time(stamp, Instant::now());
+            for data in parser.feed(message) {
+                let Some(msg) = Self::parse_midi_message(&data, &device_name, timestamp, debug)
+                else {
+                    continue;
+                };
+                // Update note states
+                if let (MidiMessageType::NoteOn | MidiMessageType::NoteOff, Some(note)) =
+                    (&msg.message_type, msg.note)
+                {
+                    let mut states = note_states.lock().unwrap();
+                    let notes = states.entry(device_name.clone()).or_default();
+                    notes.retain(|held| (held.channel, held.note) != (msg.channel, note));
+                    if msg.message_type == MidiMessageType::NoteOn {
+                        notes.push(HeldNote {
+                            channel: msg.channel,
+                            note,
+                            velocity: msg.velocity.unwrap_or(0),
+                        });
+                    }
+                }
+
+                let mut derived: Vec<MidiMessage> = decoder.decode(&msg).into_iter().collect();
+                derived.extend(clock_tracker.track(&msg));
+
+                // Clock ticks only feed the tracker.
+                if msg.message_type != MidiMessageType::Clock {
+                    if debug {
+                        debug!("MIDI: {:?}", msg);
+                    }
+                    let _ = sender.send(msg);
+                }
+
+                for derived in derived {
+                    if debug {
+                        debug!("MIDI: {:?}", derived);
+                    }
+                    let _ = sender.send(derived);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_device_open(&self, device_name: &str) -> bool {
+        self.connections.lock().unwrap().contains_key(device_name)
+    }
+
+    /// Closes connections to ports missing from `available`, returns their names.
+    pub(crate) fn close_missing_devices(&mut self, available: &[MidiDeviceInfo]) -> Vec<String> {
+        let mut connections = self.connections.lock().unwrap();
+        let missing: Vec<String> = connections
+            .keys()
+            .filter(|name| !available.iter().any(|device| &device.name == *name))
+            .cloned()
+            .collect();
+        for device_name in &missing {
+            // Dropping the connection closes it.
+            connections.remove(device_name);
+            self.note_states.lock().unwrap().remove(device_name);
+        }
+        missing
+    }
+
+    /// Parses a complete message, see `MidiStreamParser`.
+    fn parse_midi_message(
+        data: &[u8],
+        device_name: &str,
+        timestamp: Instant,
+        debug: bool,
+    ) -> Option<MidiMessage> {
         let (status, data1, data2) = match data {
+            [0xF0, ..] => (&0xF0, None, None),
             [s] => (s, None, None),
             [s, d1] => (s, Some(*d1), None),
-            [s, d1, d2, ..] => (s, Some(*d1), Some(*d2)),
+            [s, d1, d2] => (s, Some(*d1), Some(*d2)),
             _ => {
                 if debug {
-                    log::warn!(
-                        "Midi messages of more than 3 bytes in size {data:#?} are unexpected."
-                    );
+                    log::warn!("Unexpected MIDI message {data:02X?}.");
                 }
                 return None;
             }
@@ -189,8 +682,38 @@ impl MidiManager {
             control: None,
             value: None,
             pitch: None,
+            parameter: None,
+            value_14bit: None,
+            sysex: None,
+            tempo: None,
+            timestamp,
         };
 
+        if *status >= 0xF0 {
+            message.channel = 0;
+            message.message_type = match (*status, data1, data2) {
+                (0xF0, _, _) => {
+                    message.sysex = Some(data.to_vec());
+                    MidiMessageType::SysEx
+                }
+                (0xF2, Some(lsb), Some(msb)) => {
+                    message.value_14bit = Some(((msb as u16) << 7) | lsb as u16);
+                    MidiMessageType::SongPosition
+                }
+                (0xF8, _, _) => MidiMessageType::Clock,
+                (0xFA, _, _) => MidiMessageType::Start,
+                (0xFB, _, _) => MidiMessageType::Continue,
+                (0xFC, _, _) => MidiMessageType::Stop,
+                _ => {
+                    if debug {
+                        log::debug!("Unhandeled MIDI system message {status:#04X}");
+                    }
+                    return None;
+                }
+            };
+            return Some(message);
+        }
+
         match (message_type_code, data1, data2) {
             (0x80, Some(note), Some(velocity)) => {
                 message.message_type = MidiMessageType::NoteOff;
@@ -259,8 +782,23 @@ impl MidiManager {
                 | (MidiMessageType::NoteOff, SpecType::Note)
                 | (MidiMessageType::NoteOff, SpecType::NoteOff)
                 | (MidiMessageType::Aftertouch, SpecType::Aftertouch)
-                // TODO: | (MidiMessageType::PolyAftertouch, ... )
+                | (MidiMessageType::PolyAftertouch, SpecType::PolyAftertouch)
                 | (MidiMessageType::ProgramChange, SpecType::ProgramChange)
+                | (
+                    MidiMessageType::ControlChange14bit,
+                    SpecType::ControlChange14bit
+                )
+                | (MidiMessageType::Nrpn, SpecType::Nrpn)
+                | (MidiMessageType::Rpn, SpecType::Rpn)
+                | (MidiMessageType::SysEx, SpecType::Sysex)
+                | (MidiMessageType::Tempo, SpecType::ClockTempo)
+                | (MidiMessageType::SongPosition, SpecType::SongPosition)
+                | (
+                    MidiMessageType::Start | MidiMessageType::Continue | MidiMessageType::Stop,
+                    SpecType::TransportStart
+                        | SpecType::TransportContinue
+                        | SpecType::TransportStop
+                )
         )
     }
 
@@ -270,13 +808,72 @@ impl MidiManager {
         mapping: &config::ResolvedMapping,
     ) -> bool {
         if let config::ControlReference::Midi(midi_control) = &mapping.source.control {
-            if let Some(spec) = &midi_control.midi_message {
-                return self.check_midi_spec(msg, spec);
-            }
+            return self.midi_control_matches(msg, midi_control);
         }
         false
     }
 
+    pub(crate) fn midi_control_matches(
+        &self,
+        msg: &MidiMessage,
+        midi_control: &crate::schemas::MidiControl,
+    ) -> bool {
+        if let Some(spec) = &midi_control.midi_message {
+            return self.check_midi_spec(msg, spec)
+                || midi_control.velocity_modulation.is_some_and(|modulation| {
+                    self.modulated_velocity(msg, spec, modulation).is_some()
+                });
+        }
+        false
+    }
+
+    /// Velocity of the held note(s) of a note spec modulated by an aftertouch message,
+    /// `None` if the message is not the modulating aftertouch or no such note is held.
+    fn modulated_velocity(
+        &self,
+        msg: &MidiMessage,
+        spec: &crate::schemas::MidiMessage,
+        modulation: MidiVelocityModulation,
+    ) -> Option<u8> {
+        let pressure = match (modulation, &msg.message_type) {
+            (MidiVelocityModulation::PolyAftertouch, MidiMessageType::PolyAftertouch)
+            | (MidiVelocityModulation::ChannelAftertouch, MidiMessageType::Aftertouch) => {
+                msg.value?
+            }
+            _ => return None,
+        };
+        if !self.midi_channel_matches(msg.channel, &spec.channel) {
+            return None;
+        }
+        self.held_notes(&msg.device_name, spec)
+            .iter()
+            .filter(|held| held.channel == msg.channel && msg.note.is_none_or(|n| n == held.note))
+            .map(|held| held.velocity)
+            .max()
+            .map(|velocity| modulate_velocity(velocity, pressure))
+    }
+
+    /// Held notes of a device matching the channel and number of a note spec.
+    fn held_notes(&self, device_name: &str, spec: &crate::schemas::MidiMessage) -> Vec<HeldNote> {
+        let states = self.note_states.lock().unwrap();
+        states
+            .get(device_name)
+            .map(|notes| {
+                notes
+                    .iter()
+                    .filter(|held| {
+                        self.midi_channel_matches(held.channel, &spec.channel)
+                            && spec
+                                .number
+                                .as_ref()
+                                .is_none_or(|number| number.contains(held.note as u16))
+                    })
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     fn check_midi_spec(&self, msg: &MidiMessage, spec: &crate::schemas::MidiMessage) -> bool {
         if !self.midi_type_matches(&msg.message_type, &spec.msg_type) {
             return false;
@@ -289,6 +886,15 @@ impl MidiManager {
                 return false;
             }
         }
+        if let Some(pattern) = &spec.pattern {
+            if !msg
+                .sysex
+                .as_deref()
+                .is_some_and(|data| pattern.matches(data))
+            {
+                return false;
+            }
+        }
         true
     }
 
@@ -309,67 +915,83 @@ impl MidiManager {
         msg: &MidiMessage,
         spec_number: &crate::schemas::MidiNumber,
     ) -> bool {
-        use crate::schemas::MidiNumber;
         let actual_number = match msg.message_type {
-            MidiMessageType::NoteOn | MidiMessageType::NoteOff => msg.note,
-            MidiMessageType::ControlChange => msg.control,
-            MidiMessageType::ProgramChange => msg.value,
+            MidiMessageType::NoteOn
+            | MidiMessageType::NoteOff
+            | MidiMessageType::PolyAftertouch => msg.note.map(u16::from),
+            MidiMessageType::ControlChange | MidiMessageType::ControlChange14bit => {
+                msg.control.map(u16::from)
+            }
+            MidiMessageType::ProgramChange => msg.value.map(u16::from),
+            MidiMessageType::Nrpn | MidiMessageType::Rpn => msg.parameter,
             _ => None,
         };
 
-        if let Some(number) = actual_number {
-            match spec_number {
-                MidiNumber::Single(n) => number == *n,
-                MidiNumber::Multiple(numbers) => numbers.contains(&number),
-                MidiNumber::Special(s) => s.to_lowercase() == "any",
+        actual_number.is_some_and(|number| spec_number.contains(number))
+    }
+
+    /// Value of a message for a mapping, relative encoder controls give signed deltas.
+    /// `None` leaves the mapping value unchanged (a note group with no note held).
+    pub(crate) fn extract_mapping_value(
+        &self,
+        msg: &MidiMessage,
+        mapping: &config::ResolvedMapping,
+    ) -> Option<f32> {
+        if let config::ControlReference::Midi(midi_control) = &mapping.source.control {
+            if let (Some(group), Some(spec)) = (midi_control.note_group, &midi_control.midi_message)
+            {
+                return note_group_value(&self.held_notes(&msg.device_name, spec), group)
+                    .map(|value| value as f32);
+            }
+            if let (Some(modulation), Some(spec)) =
+                (midi_control.velocity_modulation, &midi_control.midi_message)
+            {
+                if let Some(velocity) = self.modulated_velocity(msg, spec, modulation) {
+                    return Some(velocity as f32);
+                }
+            }
+            if let (Some(encoding), MidiMessageType::ControlChange) =
+                (midi_control.encoding, &msg.message_type)
+            {
+                return Some(encoding.decode(msg.value.unwrap_or(0)) as f32);
+            }
+            if let Some(spec) = &midi_control.midi_message {
+                if let (Some(offset), Some(data)) = (spec.value_offset, &msg.sysex) {
+                    return data.get(offset).map(|byte| (byte & 0x7F) as f32);
+                }
+                if let Some(value) = transport_button_value(&msg.message_type, &spec.msg_type) {
+                    return Some(value);
+                }
             }
-        } else {
-            false
         }
+        Some(self.extract_midi_value(msg))
     }
 
-    pub(crate) fn extract_midi_value(&self, msg: &MidiMessage) -> i32 {
+    pub(crate) fn extract_midi_value(&self, msg: &MidiMessage) -> f32 {
         match msg.message_type {
-            MidiMessageType::PitchWheel => msg.pitch.unwrap_or(0) as i32,
-            MidiMessageType::ControlChange => msg.value.unwrap_or(0) as i32,
-            MidiMessageType::NoteOn => msg.velocity.unwrap_or(0) as i32,
-            MidiMessageType::NoteOff => 0,
-            MidiMessageType::Aftertouch => msg.value.unwrap_or(0) as i32,
-            // TODO: poly aftertouch should be applied to modify velocity of
-            // already pressed associated note.
-            MidiMessageType::PolyAftertouch => msg.value.unwrap_or(0) as i32,
-            MidiMessageType::ProgramChange => msg.value.unwrap_or(0) as i32,
-        }
-    }
-
-    // pub(crate) fn get_special_note_value(&self, device_name: &str, note_type: &str) -> u8 {
-    //     let states = self.note_states.lock().unwrap();
-
-    //     if let Some(notes) = states.get(device_name) {
-    //         if notes.is_empty() {
-    //             return 0;
-    //         }
-
-    //         match note_type {
-    //             "lowest" => notes.iter().min().copied().unwrap_or(0),
-    //             "highest" => notes.iter().max().copied().unwrap_or(0),
-    //             "any" => {
-    //                 if notes.is_empty() {
-    //                     0
-    //                 } else {
-    //                     127
-    //                 }
-    //             }
-    //             _ => 0,
-    //         }
-    //     } else {
-    //         0
-    //     }
-    // }
+            MidiMessageType::PitchWheel => msg.pitch.unwrap_or(0) as f32,
+            MidiMessageType::ControlChange => msg.value.unwrap_or(0) as f32,
+            MidiMessageType::NoteOn => msg.velocity.unwrap_or(0) as f32,
+            MidiMessageType::NoteOff | MidiMessageType::Clock => 0.0,
+            MidiMessageType::Aftertouch | MidiMessageType::PolyAftertouch => {
+                msg.value.unwrap_or(0) as f32
+            }
+            MidiMessageType::ProgramChange => msg.value.unwrap_or(0) as f32,
+            MidiMessageType::ControlChange14bit
+            | MidiMessageType::Nrpn
+            | MidiMessageType::Rpn
+            | MidiMessageType::SongPosition => msg.value_14bit.unwrap_or(0) as f32,
+            MidiMessageType::SysEx
+            | MidiMessageType::Start
+            | MidiMessageType::Continue
+            | MidiMessageType::Stop => 127.0,
+            MidiMessageType::Tempo => msg.tempo.unwrap_or(0.0),
+        }
+    }
 
     pub(crate) async fn monitor(&mut self, name_regex: &regex::Regex) -> Result<()> {
         let devices = self.enumerate_devices();
-        let matched = self.match_device(name_regex, &devices);
+        let matched = self.match_device(name_regex, &ResolvedMidiPortMatch::default(), &devices);
 
         if matched.is_empty() {
             bail!("No devices found matching '{}'", name_regex);
@@ -465,6 +1087,77 @@ impl MidiManager {
                     );
                 }
             }
+            MidiMessageType::ControlChange14bit => {
+                if let (Some(control), Some(value)) = (msg.control, msg.value_14bit) {
+                    info!(
+                        "[{}][{}] CC 14-bit: {} (cc={}/{}, val={}, ch={})",
+                        timestamp,
+                        msg.device_name,
+                        Self::get_cc_name(control),
+                        control,
+                        control + 32,
+                        value,
+                        msg.channel
+                    );
+                }
+            }
+            MidiMessageType::Nrpn | MidiMessageType::Rpn => {
+                if let (Some(parameter), Some(value)) = (msg.parameter, msg.value_14bit) {
+                    info!(
+                        "[{}][{}] {}: {} (param={}, ch={})",
+                        timestamp,
+                        msg.device_name,
+                        if msg.message_type == MidiMessageType::Nrpn {
+                            "NRPN"
+                        } else {
+                            "RPN"
+                        },
+                        value,
+                        parameter,
+                        msg.channel
+                    );
+                }
+            }
+            MidiMessageType::SysEx => {
+                if let Some(data) = &msg.sysex {
+                    let bytes: Vec<String> = data.iter().map(|b| format!("{:02X}", b)).collect();
+                    info!(
+                        "[{}][{}] SysEx: {} ({} bytes)",
+                        timestamp,
+                        msg.device_name,
+                        bytes.join(" "),
+                        data.len()
+                    );
+                }
+            }
+            MidiMessageType::Tempo => {
+                if let Some(tempo) = msg.tempo {
+                    info!(
+                        "[{}][{}] Clock Tempo: {:.1} BPM",
+                        timestamp, msg.device_name, tempo
+                    );
+                }
+            }
+            MidiMessageType::SongPosition => {
+                if let Some(position) = msg.value_14bit {
+                    info!(
+                        "[{}][{}] Song Position: {} (bar {}, beat {}, 16th {})",
+                        timestamp,
+                        msg.device_name,
+                        position,
+                        position / 16 + 1,
+                        position / 4 % 4 + 1,
+                        position % 4 + 1
+                    );
+                }
+            }
+            MidiMessageType::Start | MidiMessageType::Continue | MidiMessageType::Stop => {
+                info!(
+                    "[{}][{}] Transport: {:?}",
+                    timestamp, msg.device_name, msg.message_type
+                );
+            }
+            MidiMessageType::Clock => {}
         }
     }
 
@@ -573,9 +1266,39 @@ impl MidiLearnMode {
                     return;
                 }
             }
+            MidiMessageType::ControlChange14bit => {
+                if let Some(control) = msg.control {
+                    format!("cc14_{}", control)
+                } else {
+                    return;
+                }
+            }
+            MidiMessageType::Nrpn | MidiMessageType::Rpn => {
+                if let Some(parameter) = msg.parameter {
+                    let kind = if msg.message_type == MidiMessageType::Nrpn {
+                        "nrpn"
+                    } else {
+                        "rpn"
+                    };
+                    format!("{}_{}", kind, parameter)
+                } else {
+                    return;
+                }
+            }
             MidiMessageType::PitchWheel => "pitch_wheel".to_string(),
             MidiMessageType::Aftertouch => "aftertouch".to_string(),
-            MidiMessageType::PolyAftertouch => "polyaftertouch".to_string(),
+            MidiMessageType::PolyAftertouch => {
+                if let Some(note) = msg.note {
+                    format!("poly_aftertouch_{}", note)
+                } else {
+                    return;
+                }
+            }
+            MidiMessageType::Tempo => "clock_tempo".to_string(),
+            MidiMessageType::SongPosition => "song_position".to_string(),
+            MidiMessageType::Start => "transport_start".to_string(),
+            MidiMessageType::Continue => "transport_continue".to_string(),
+            MidiMessageType::Stop => "transport_stop".to_string(),
             _ => return,
         };
 
@@ -592,3 +1315,264 @@ impl MidiLearnMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_alsa_port_address() {
+        assert_eq!(
+            parse_alsa_port_address("Launch Control:Launch Control MIDI 1 24:0"),
+            Some((24, 0))
+        );
+        assert_eq!(
+            parse_alsa_port_address("Midi Through:Midi Through Port-0 14:0"),
+            Some((14, 0))
+        );
+        assert_eq!(parse_alsa_port_address("IAC Driver Bus 1"), None);
+    }
+
+    #[test]
+    fn test_stream_parser() {
+        let mut parser = MidiStreamParser::default();
+        // Running status, with a clock tick inside the second note.
+        assert_eq!(
+            parser.feed(&[0x90, 60, 100, 62, 0xF8, 90, 0xC1, 5]),
+            vec![
+                vec![0x90, 60, 100],
+                vec![0xF8],
+                vec![0x90, 62, 90],
+                vec![0xC1, 5]
+            ]
+        );
+        assert_eq!(parser.feed(&[7]), vec![vec![0xC1, 7]]);
+        // SysEx split over callbacks cancels the running status.
+        assert!(parser.feed(&[0xF0, 0x7E, 0x00]).is_empty());
+        assert_eq!(
+            parser.feed(&[0x06, 0xF7, 60]),
+            vec![vec![0xF0, 0x7E, 0x00, 0x06, 0xF7]]
+        );
+        // Unterminated SysEx is dropped.
+        assert_eq!(
+            parser.feed(&[0xF0, 1, 2, 0xB0, 7, 64]),
+            vec![vec![0xB0, 7, 64]]
+        );
+    }
+
+    #[test]
+    fn test_clock_tracker() {
+        let start = Instant::now();
+        let system = |data: &[u8], timestamp| {
+            MidiManager::parse_midi_message(data, "test", timestamp, false).unwrap()
+        };
+        let positions = |changes: &[MidiMessage]| -> Vec<u16> {
+            changes
+                .iter()
+                .filter(|change| change.message_type == MidiMessageType::SongPosition)
+                .filter_map(|change| change.value_14bit)
+                .collect()
+        };
+        let mut tracker = MidiClockTracker::default();
+        let tick_interval = Duration::from_micros(20_833); // 120 BPM
+
+        // Song position pointer while stopped, then continue from there.
+        let locate = system(&[0xF2, 0x10, 0x01], start);
+        assert_eq!(locate.message_type, MidiMessageType::SongPosition);
+        assert_eq!(locate.value_14bit, Some(144));
+        assert!(tracker.track(&locate).is_empty());
+        tracker.track(&system(&[0xFB], start));
+        // A single tick has no tempo yet.
+        let mut changes = tracker.track(&system(&[0xF8], start));
+        assert!(changes.iter().all(|change| change.tempo.is_none()));
+        for tick in 1..=CLOCKS_PER_BEAT as u32 {
+            changes.extend(tracker.track(&system(&[0xF8], start + tick_interval * tick)));
+        }
+        let last_tempo = changes
+            .iter()
+            .rev()
+            .find_map(|change| change.tempo)
+            .unwrap();
+        assert_eq!(last_tempo, 120.0);
+        assert_eq!(positions(&changes), vec![145, 146, 147, 148]);
+
+        // Stop holds the position, start rewinds it.
+        tracker.track(&system(&[0xFC], start));
+        let held = tracker.track(&system(&[0xF8], start + tick_interval * 25));
+        assert!(positions(&held).is_empty());
+        assert_eq!(positions(&tracker.track(&system(&[0xFA], start))), vec![0]);
+    }
+
+    #[test]
+    fn test_timestamp_clock() {
+        let mut clock = MidiTimestampClock::default();
+        let start = Instant::now();
+        assert_eq!(clock.event_time(1_000_000, start), start);
+        // Delivered late: the event time stays on the driver clock.
+        let late = start + Duration::from_millis(30);
+        assert_eq!(
+            clock.event_time(1_010_000, late),
+            start + Duration::from_millis(10)
+        );
+        // Arrived earlier than expected: the base moves back.
+        let early = start + Duration::from_millis(35);
+        assert_eq!(clock.event_time(1_040_000, early), early);
+        // Never older than the latency bound (drift, or no driver timestamps).
+        let stalled = start + Duration::from_secs(1);
+        assert_eq!(
+            clock.event_time(1_040_000, stalled),
+            stalled - MAX_TIMESTAMP_LATENCY
+        );
+    }
+
+    #[test]
+    fn test_sysex_pattern() {
+        use crate::schemas::SysexPattern;
+        let pattern: SysexPattern = "F0 7F ?? 06 ?? F7".parse().unwrap();
+        assert!(pattern.matches(&[0xF0, 0x7F, 0x10, 0x06, 0x44, 0xF7]));
+        assert!(!pattern.matches(&[0xF0, 0x7F, 0x10, 0x07, 0x44, 0xF7]));
+        assert!(!pattern.matches(&[0xF0, 0x7F, 0x10, 0x06, 0x44, 0x00, 0xF7]));
+        let prefix: SysexPattern = "f0 43 *".parse().unwrap();
+        assert!(prefix.matches(&[0xF0, 0x43, 0x10, 0xF7]));
+        assert_eq!(prefix.to_string(), "F0 43 *");
+        assert!("7F 06".parse::<SysexPattern>().is_err());
+        assert!("F0 XX".parse::<SysexPattern>().is_err());
+    }
+
+    #[test]
+    fn test_note_group_value() {
+        let held = |note| HeldNote {
+            channel: 0,
+            note,
+            velocity: 100,
+        };
+        let notes = [held(52), held(48), held(55)];
+        assert_eq!(note_group_value(&notes, MidiNoteGroup::Lowest), Some(48));
+        assert_eq!(note_group_value(&notes, MidiNoteGroup::Highest), Some(55));
+        assert_eq!(note_group_value(&notes, MidiNoteGroup::Last), Some(55));
+        assert_eq!(note_group_value(&notes, MidiNoteGroup::Count), Some(3));
+        assert_eq!(note_group_value(&notes, MidiNoteGroup::AnyHeld), Some(127));
+        assert_eq!(note_group_value(&[], MidiNoteGroup::Last), None);
+        assert_eq!(note_group_value(&[], MidiNoteGroup::Count), Some(0));
+        assert_eq!(note_group_value(&[], MidiNoteGroup::AnyHeld), Some(0));
+    }
+
+    #[test]
+    fn test_midi_number_ranges() {
+        use crate::schemas::MidiNumber;
+        let range = MidiNumber::Special("48-59".to_string());
+        assert!(range.contains(48) && range.contains(59) && !range.contains(60));
+        assert_eq!(range.bounds(), Some((48, 59)));
+        assert_eq!(range.count(), Some(12));
+        assert!(MidiNumber::Special("ANY".to_string()).contains(3));
+        assert_eq!(MidiNumber::parse_range("59-48"), None);
+        assert_eq!(MidiNumber::parse_range("any"), None);
+    }
+
+    #[test]
+    fn test_modulate_velocity() {
+        assert_eq!(modulate_velocity(64, 0), 64);
+        assert_eq!(modulate_velocity(64, 127), 127);
+        assert_eq!(modulate_velocity(0, 64), 64);
+        assert_eq!(modulate_velocity(100, 64), 114);
+        assert_eq!(modulate_velocity(127, 90), 127);
+    }
+
+    #[test]
+    fn test_relative_encoder_decoding() {
+        use crate::schemas::MidiValueEncoding;
+        let twos = MidiValueEncoding::RelativeTwosComplement;
+        assert_eq!(
+            [1, 63, 127, 65, 64].map(|v| twos.decode(v)),
+            [1, 63, -1, -63, -64]
+        );
+        let signed_bit = MidiValueEncoding::RelativeSignedBit;
+        assert_eq!(
+            [1, 63, 65, 127, 64].map(|v| signed_bit.decode(v)),
+            [1, 63, -1, -63, 0]
+        );
+        let offset = MidiValueEncoding::RelativeOffset64;
+        assert_eq!(
+            [64, 65, 127, 63, 0].map(|v| offset.decode(v)),
+            [0, 1, 63, -1, -64]
+        );
+    }
+
+    fn cc(channel: u8, control: u8, value: u8) -> MidiMessage {
+        MidiManager::parse_midi_message(
+            &[0xB0 | channel, control, value],
+            "test",
+            Instant::now(),
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decode_14bit_control_change() {
+        let mut decoder = HighResolutionDecoder::default();
+        // LSB without a preceding MSB is ambiguous.
+        assert!(decoder.decode(&cc(0, 39, 5)).is_none());
+
+        let coarse = decoder.decode(&cc(0, 7, 0x40)).unwrap();
+        assert_eq!(coarse.message_type, MidiMessageType::ControlChange14bit);
+        assert_eq!(
+            (coarse.control, coarse.value_14bit),
+            (Some(7), Some(0x2000))
+        );
+
+        let fine = decoder.decode(&cc(0, 39, 0x11)).unwrap();
+        assert_eq!((fine.control, fine.value_14bit), (Some(7), Some(0x2011)));
+
+        // Once paired, the MSB waits for its LSB.
+        assert!(decoder.decode(&cc(0, 7, 0x41)).is_none());
+        let next = decoder.decode(&cc(0, 39, 0)).unwrap();
+        assert_eq!((next.control, next.value_14bit), (Some(7), Some(0x2080)));
+
+        // Channels are independent, controllers above 63 are plain 7-bit ones.
+        assert!(decoder.decode(&cc(1, 39, 0x11)).is_none());
+        assert!(decoder.decode(&cc(0, 74, 0x11)).is_none());
+    }
+
+    #[test]
+    fn test_decode_nrpn_and_rpn() {
+        let mut decoder = HighResolutionDecoder::default();
+        assert!(decoder.decode(&cc(2, CC_NRPN_MSB, 1)).is_none());
+        assert!(decoder.decode(&cc(2, CC_NRPN_LSB, 8)).is_none());
+
+        let coarse = decoder.decode(&cc(2, CC_DATA_ENTRY_MSB, 0x7F)).unwrap();
+        assert_eq!(coarse.message_type, MidiMessageType::Nrpn);
+        assert_eq!(
+            (coarse.parameter, coarse.value_14bit),
+            (Some(136), Some(0x3F80))
+        );
+        let fine = decoder.decode(&cc(2, CC_DATA_ENTRY_LSB, 0x7F)).unwrap();
+        assert_eq!(fine.value_14bit, Some(MAX_14BIT_VALUE));
+        assert!(decoder.decode(&cc(2, CC_DATA_ENTRY_MSB, 0x7F)).is_none());
+        let fine = decoder.decode(&cc(2, CC_DATA_ENTRY_LSB, 0x7F)).unwrap();
+        assert_eq!(fine.value_14bit, Some(MAX_14BIT_VALUE));
+        let incremented = decoder.decode(&cc(2, CC_DATA_INCREMENT, 0)).unwrap();
+        assert_eq!(incremented.value_14bit, Some(MAX_14BIT_VALUE));
+        let decremented = decoder.decode(&cc(2, CC_DATA_DECREMENT, 0)).unwrap();
+        assert_eq!(decremented.value_14bit, Some(MAX_14BIT_VALUE - 1));
+
+        // Selecting an RPN drops the NRPN selection, the RPN null deselects.
+        assert!(decoder.decode(&cc(2, CC_RPN_MSB, 0)).is_none());
+        assert!(decoder.decode(&cc(2, CC_RPN_LSB, 0)).is_none());
+        let rpn = decoder.decode(&cc(2, CC_DATA_ENTRY_MSB, 2)).unwrap();
+        assert_eq!(rpn.message_type, MidiMessageType::Rpn);
+        assert_eq!((rpn.parameter, rpn.value_14bit), (Some(0), Some(256)));
+        decoder.decode(&cc(2, CC_RPN_MSB, 0x7F));
+        decoder.decode(&cc(2, CC_RPN_LSB, 0x7F));
+        let data_entry = decoder.decode(&cc(2, CC_DATA_ENTRY_MSB, 3)).unwrap();
+        assert_eq!(data_entry.message_type, MidiMessageType::ControlChange14bit);
+
+        // So does the NRPN null.
+        decoder.decode(&cc(3, CC_NRPN_MSB, 1));
+        decoder.decode(&cc(3, CC_NRPN_LSB, 8));
+        decoder.decode(&cc(3, CC_NRPN_MSB, 0x7F));
+        decoder.decode(&cc(3, CC_NRPN_LSB, 0x7F));
+        let data_entry = decoder.decode(&cc(3, CC_DATA_ENTRY_MSB, 3)).unwrap();
+        assert_eq!(data_entry.message_type, MidiMessageType::ControlChange14bit);
+    }
+}
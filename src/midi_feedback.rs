@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use midir::{MidiOutput, MidiOutputConnection};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::midi::parse_alsa_port_address;
+use crate::midi_output::encode_messages;
+use crate::schemas::{ResolvedMidiFeedback, ResolvedMidiFeedbackControl};
+
+/// Last input seen on a touch control.
+#[derive(Debug, Clone, Copy)]
+struct Touch {
+    at: Instant,
+    /// A note still held down.
+    held: bool,
+}
+
+impl Touch {
+    fn holds_back(&self, now: Instant, timeout: Duration) -> bool {
+        self.held || now.saturating_duration_since(self.at) < timeout
+    }
+}
+
+#[derive(Debug, Default)]
+struct FeedbackState {
+    sent: Option<i32>,
+    sent_at: Option<Instant>,
+}
+
+impl FeedbackState {
+    /// A value is sent when it differs from the one sent last, no sooner than `min_interval`
+    /// after it. A value held back is sent on a later call, the latest one wins.
+    fn is_due(&self, value: i32, now: Instant, min_interval: Duration) -> bool {
+        self.sent != Some(value)
+            && self
+                .sent_at
+                .is_none_or(|at| now.saturating_duration_since(at) >= min_interval)
+    }
+}
+
+/// Sends virtual joystick and internal control values back to the MIDI devices
+/// they are mapped from, to keep LEDs and motorized faders in sync.
+pub(crate) struct MidiFeedbackManager {
+    debug: bool,
+    /// Open output ports by device key, then port name.
+    ports: Mutex<HashMap<String, HashMap<String, MidiOutputConnection>>>,
+    /// By device and feedback control key.
+    states: Mutex<HashMap<(String, String), FeedbackState>>,
+    /// By device and touch control key.
+    touches: Mutex<HashMap<(String, String), Touch>>,
+}
+
+impl MidiFeedbackManager {
+    pub(crate) fn new(debug: bool) -> Result<Self> {
+        Ok(Self {
+            debug,
+            ports: Mutex::new(HashMap::new()),
+            states: Mutex::new(HashMap::new()),
+            touches: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Closes the ports gone or no longer matching and opens the new matching ones.
+    /// Everything is sent again to the devices with a new port.
+    pub(crate) fn sync_ports(
+        &self,
+        feedback: &BTreeMap<String, ResolvedMidiFeedback>,
+    ) -> Result<()> {
+        let midi_output = MidiOutput::new(&format!("{} MIDI Feedback", config::APP_NAME))
+            .context("Failed to create MIDI output")?;
+        let available: Vec<String> = midi_output
+            .ports()
+            .iter()
+            .filter_map(|port| midi_output.port_name(port).ok())
+            .collect();
+
+        let mut ports = self.ports.lock().unwrap();
+        ports.retain(|device_key, _| feedback.contains_key(device_key));
+        for (device_key, device_feedback) in feedback {
+            let matched: Vec<&String> = available
+                .iter()
+                .filter(|name| {
+                    let address = parse_alsa_port_address(name);
+                    let port_match = &device_feedback.port_match;
+                    device_feedback.output_name_regex.is_match(name)
+                        && port_match
+                            .client
+                            .is_none_or(|client| address.is_some_and(|(c, _)| c == client))
+                        && port_match
+                            .port
+                            .is_none_or(|port| address.is_some_and(|(_, p)| p == port))
+                })
+                .collect();
+            let device_ports = ports.entry(device_key.clone()).or_default();
+            device_ports.retain(|port_name, _| {
+                let keep = matched.contains(&port_name);
+                if !keep {
+                    info!("MIDI feedback port disconnected: {}", port_name);
+                }
+                keep
+            });
+            let mut opened = false;
+            for port_name in matched {
+                if device_ports.contains_key(port_name) {
+                    continue;
+                }
+                match Self::connect(device_key, port_name) {
+                    Ok(connection) => {
+                        info!(
+                            "Opened MIDI feedback port for '{}': {}",
+                            device_key, port_name
+                        );
+                        device_ports.insert(port_name.clone(), connection);
+                        opened = true;
+                    }
+                    Err(error) => {
+                        warn!(
+                            "Failed to open MIDI feedback port '{}': {:#}",
+                            port_name, error
+                        );
+                    }
+                }
+            }
+            if opened {
+                self.states
+                    .lock()
+                    .unwrap()
+                    .retain(|(state_device_key, _), _| state_device_key != device_key);
+            }
+        }
+        Ok(())
+    }
+
+    fn connect(device_key: &str, port_name: &str) -> Result<MidiOutputConnection> {
+        let midi_output =
+            MidiOutput::new(&format!("{} {} Feedback", config::APP_NAME, device_key))?;
+        let port = midi_output
+            .ports()
+            .into_iter()
+            .find(|port| {
+                midi_output
+                    .port_name(port)
+                    .is_ok_and(|name| name == port_name)
+            })
+            .context("MIDI output port is gone")?;
+        midi_output
+            .connect(&port, device_key)
+            .map_err(|e| anyhow!("Failed to connect to MIDI output port: {}", e))
+    }
+
+    /// Records input on a control of a device, holding back the feedback it's a touch control of.
+    pub(crate) fn touch(&self, device_key: &str, control_key: &str, held: bool, now: Instant) {
+        self.touches.lock().unwrap().insert(
+            (device_key.to_string(), control_key.to_string()),
+            Touch { at: now, held },
+        );
+    }
+
+    /// Sends `value` of a feedback control unless it's sent already, too soon after the last
+    /// one or while the control is touched.
+    pub(crate) fn send(
+        &self,
+        device_key: &str,
+        control_key: &str,
+        feedback: &ResolvedMidiFeedback,
+        control: &ResolvedMidiFeedbackControl,
+        value: i32,
+        now: Instant,
+    ) {
+        let mut ports = self.ports.lock().unwrap();
+        let Some(device_ports) = ports.get_mut(device_key).filter(|ports| !ports.is_empty()) else {
+            return;
+        };
+        {
+            let touches = self.touches.lock().unwrap();
+            let touched = control.touch_controls.iter().any(|touch_control| {
+                touches
+                    .get(&(device_key.to_string(), touch_control.clone()))
+                    .is_some_and(|touch| touch.holds_back(now, feedback.touch_timeout))
+            });
+            if touched {
+                return;
+            }
+        }
+
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .entry((device_key.to_string(), control_key.to_string()))
+            .or_default();
+        if !state.is_due(value, now, feedback.min_interval) {
+            return;
+        }
+        for message in encode_messages(&control.message, state.sent, value) {
+            device_ports.retain(|port_name, connection| match connection.send(&message) {
+                Ok(()) => true,
+                Err(error) => {
+                    warn!(
+                        "Failed to send to MIDI feedback port '{}', closing it: {}",
+                        port_name, error
+                    );
+                    false
+                }
+            });
+            if self.debug {
+                debug!(
+                    "MIDI feedback [{}][{}]: {:02x?}",
+                    device_key, control_key, message
+                );
+            }
+        }
+        state.sent = Some(value);
+        state.sent_at = Some(now);
+    }
+
+    pub(crate) fn stop(&self) -> Result<()> {
+        // Dropping the connections closes them.
+        self.ports.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feedback_rate_limit_and_touch() {
+        let start = Instant::now();
+        let ms = |millis| start + Duration::from_millis(millis);
+        let min_interval = Duration::from_millis(40);
+
+        let mut state = FeedbackState::default();
+        assert!(state.is_due(0, start, min_interval));
+        state.sent = Some(64);
+        state.sent_at = Some(start);
+        // Unchanged values are never sent, changed ones wait for the interval.
+        assert!(!state.is_due(64, ms(100), min_interval));
+        assert!(!state.is_due(70, ms(39), min_interval));
+        assert!(state.is_due(70, ms(40), min_interval));
+
+        let timeout = Duration::from_millis(500);
+        let moved = Touch {
+            at: start,
+            held: false,
+        };
+        assert!(moved.holds_back(ms(499), timeout));
+        assert!(!moved.holds_back(ms(500), timeout));
+        let held = Touch {
+            at: start,
+            held: true,
+        };
+        assert!(held.holds_back(ms(10_000), timeout));
+    }
+}
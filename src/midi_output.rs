@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use midir::os::unix::VirtualOutput;
+use midir::{MidiOutput, MidiOutputConnection};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::config;
+use crate::schemas::{MidiOutputMessageType, ResolvedMidiOutputControl, ResolvedMidiOutputDevice};
+
+/// Status bytes of the channel messages sent, the channel is or-ed in.
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xb0;
+const PITCH_WHEEL: u8 = 0xe0;
+
+/// Messages moving a control from the `previous` sent value (`None` before the first one) to
+/// `value`, which must be within the message value range.
+/// Notes are gates: note on with the value as velocity when it leaves 0, note off when it
+/// returns to 0, nothing while the note is held.
+pub(crate) fn encode_messages(
+    control: &ResolvedMidiOutputControl,
+    previous: Option<i32>,
+    value: i32,
+) -> Vec<[u8; 3]> {
+    let channel = control.channel & 0x0f;
+    match control.msg_type {
+        MidiOutputMessageType::ControlChange => {
+            vec![[CONTROL_CHANGE | channel, control.number, value as u8]]
+        }
+        MidiOutputMessageType::ControlChange14bit => vec![
+            // NB: receivers reset the LSB on a new MSB, so the MSB goes first.
+            [CONTROL_CHANGE | channel, control.number, (value >> 7) as u8],
+            [
+                CONTROL_CHANGE | channel,
+                control.number + 32,
+                (value & 0x7f) as u8,
+            ],
+        ],
+        MidiOutputMessageType::PitchWheel => {
+            let value = value + 8192;
+            vec![[
+                PITCH_WHEEL | channel,
+                (value & 0x7f) as u8,
+                (value >> 7) as u8,
+            ]]
+        }
+        MidiOutputMessageType::Note => match (previous.unwrap_or(0) > 0, value > 0) {
+            (false, true) => vec![[NOTE_ON | channel, control.number, value as u8]],
+            (true, false) => vec![[NOTE_OFF | channel, control.number, 0]],
+            _ => Vec::new(),
+        },
+    }
+}
+
+struct MidiOutputPort {
+    /// None after a failed send, until the port is reopened on a MIDI ports rescan.
+    connection: Option<MidiOutputConnection>,
+    /// Last value sent per control key.
+    sent_values: HashMap<String, i32>,
+    /// Note off messages of the notes held, sent when the port closes.
+    held_notes: HashMap<String, [u8; 3]>,
+}
+
+/// Sends mapped values to the `midi_outputs`, only when they change.
+pub(crate) struct MidiOutputManager {
+    debug: bool,
+    ports: Mutex<HashMap<String, MidiOutputPort>>,
+}
+
+impl MidiOutputManager {
+    pub(crate) fn new(debug: bool) -> Result<Self> {
+        Ok(Self {
+            debug,
+            ports: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn midi_output(client_name: &str) -> Result<MidiOutput> {
+        MidiOutput::new(client_name).context("Failed to create MIDI output")
+    }
+
+    pub(crate) fn enumerate_ports(&self) -> Result<Vec<String>> {
+        let midi_output = Self::midi_output(&format!("{} MIDI Output", config::APP_NAME))?;
+        let mut names = Vec::new();
+        for port in midi_output.ports() {
+            match midi_output.port_name(&port) {
+                Ok(name) => names.push(name),
+                Err(error) => warn!("Can't open MIDI output port: {error}."),
+            }
+        }
+        Ok(names)
+    }
+
+    /// Connects to the first port matching the device, or creates its virtual port.
+    pub(crate) fn open(&self, device_key: &str, device: &ResolvedMidiOutputDevice) -> Result<()> {
+        let connection = Self::connect(device_key, device)?;
+        self.ports.lock().unwrap().insert(
+            device_key.to_string(),
+            MidiOutputPort {
+                connection: Some(connection),
+                sent_values: HashMap::new(),
+                held_notes: HashMap::new(),
+            },
+        );
+        info!("Opened MIDI output: {}", device_key);
+        Ok(())
+    }
+
+    /// Reopens the ports closed after a failed send, everything is sent again to them.
+    pub(crate) fn reopen_closed_ports(&self, devices: &BTreeMap<String, ResolvedMidiOutputDevice>) {
+        let mut ports = self.ports.lock().unwrap();
+        for (device_key, port) in ports.iter_mut() {
+            if port.connection.is_some() {
+                continue;
+            }
+            let Some(device) = devices.get(device_key) else {
+                continue;
+            };
+            match Self::connect(device_key, device) {
+                Ok(connection) => {
+                    info!("Reopened MIDI output: {}", device_key);
+                    port.connection = Some(connection);
+                    port.sent_values.clear();
+                }
+                Err(error) => {
+                    debug!(
+                        "MIDI output '{}' is still unavailable: {:#}",
+                        device_key, error
+                    );
+                }
+            }
+        }
+    }
+
+    fn connect(
+        device_key: &str,
+        device: &ResolvedMidiOutputDevice,
+    ) -> Result<MidiOutputConnection> {
+        let midi_output = Self::midi_output(&format!("{} {}", config::APP_NAME, device_key))?;
+        let connection = if let Some(port_name) = &device.virtual_port {
+            midi_output
+                .create_virtual(port_name)
+                .map_err(|e| anyhow!("Failed to create virtual MIDI output port: {}", e))?
+        } else {
+            let name_regex = device
+                .match_name_regex
+                .as_ref()
+                .context("MIDI output has neither a port name regex nor a virtual port")?;
+            let port = midi_output
+                .ports()
+                .into_iter()
+                .find(|port| {
+                    midi_output
+                        .port_name(port)
+                        .is_ok_and(|name| name_regex.is_match(&name))
+                })
+                .with_context(|| format!("No MIDI output port matches {:?}", name_regex))?;
+            let port_name = midi_output.port_name(&port)?;
+            let connection = midi_output
+                .connect(&port, device_key)
+                .map_err(|e| anyhow!("Failed to connect to MIDI output port: {}", e))?;
+            info!("Connected MIDI output '{}' to: {}", device_key, port_name);
+            connection
+        };
+        Ok(connection)
+    }
+
+    /// Sends `value`, clamped to the control range, unless it's the value sent last.
+    /// A port failing to send is closed, with a warning, until it's reopened.
+    pub(crate) fn send(
+        &self,
+        device_key: &str,
+        control_key: &str,
+        control: &ResolvedMidiOutputControl,
+        value: f32,
+    ) -> Result<()> {
+        let mut ports = self.ports.lock().unwrap();
+        let Some(port) = ports.get_mut(device_key) else {
+            return Ok(());
+        };
+        let Some(connection) = &mut port.connection else {
+            return Ok(());
+        };
+        let value = (value.round() as i32).clamp(control.range.from, control.range.to);
+        let previous = port.sent_values.get(control_key).copied();
+        if previous == Some(value) {
+            return Ok(());
+        }
+        for message in encode_messages(control, previous, value) {
+            if let Err(error) = connection.send(&message) {
+                warn!(
+                    "Failed to send to MIDI output '{}', closing it: {}",
+                    device_key, error
+                );
+                // Dropping the connection closes it, the notes held are gone with it.
+                port.connection = None;
+                port.held_notes.clear();
+                return Ok(());
+            }
+            match message[0] & 0xf0 {
+                NOTE_ON => {
+                    port.held_notes.insert(
+                        control_key.to_string(),
+                        [NOTE_OFF | (message[0] & 0x0f), message[1], 0],
+                    );
+                }
+                NOTE_OFF => {
+                    port.held_notes.remove(control_key);
+                }
+                _ => {}
+            }
+            if self.debug {
+                debug!(
+                    "MIDI out [{}][{}]: {:02x?}",
+                    device_key, control_key, message
+                );
+            }
+        }
+        port.sent_values.insert(control_key.to_string(), value);
+        Ok(())
+    }
+
+    /// Last value sent to a control.
+    pub(crate) fn get_control_state(&self, device_key: &str, control_key: &str) -> Option<i32> {
+        self.ports
+            .lock()
+            .unwrap()
+            .get(device_key)?
+            .sent_values
+            .get(control_key)
+            .copied()
+    }
+
+    /// Releases the held notes and closes all ports.
+    pub(crate) fn stop(&self) -> Result<()> {
+        for (device_key, port) in self.ports.lock().unwrap().drain() {
+            let Some(mut connection) = port.connection else {
+                continue;
+            };
+            for note_off in port.held_notes.values() {
+                if let Err(error) = connection.send(note_off) {
+                    warn!("Failed to release a note on MIDI output '{device_key}': {error}.");
+                }
+            }
+            connection.close();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    fn control(msg_type: MidiOutputMessageType, number: u8) -> ResolvedMidiOutputControl {
+        ResolvedMidiOutputControl {
+            msg_type,
+            channel: 2,
+            number,
+            range: msg_type.value_range(),
+            idle_tick_enabled_flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn test_encode_messages() {
+        let cc = control(MidiOutputMessageType::ControlChange, 7);
+        assert_eq!(encode_messages(&cc, None, 100), vec![[0xb2, 7, 100]]);
+
+        let cc14 = control(MidiOutputMessageType::ControlChange14bit, 1);
+        assert_eq!(
+            encode_messages(&cc14, Some(0), 0x2abc),
+            vec![[0xb2, 1, 0x55], [0xb2, 33, 0x3c]]
+        );
+
+        let pitch = control(MidiOutputMessageType::PitchWheel, 0);
+        assert_eq!(encode_messages(&pitch, None, 0), vec![[0xe2, 0x00, 0x40]]);
+        assert_eq!(encode_messages(&pitch, None, -8192), vec![[0xe2, 0, 0]]);
+        assert_eq!(
+            encode_messages(&pitch, None, 8191),
+            vec![[0xe2, 0x7f, 0x7f]]
+        );
+
+        let note = control(MidiOutputMessageType::Note, 60);
+        assert_eq!(encode_messages(&note, None, 90), vec![[0x92, 60, 90]]);
+        assert!(encode_messages(&note, Some(90), 100).is_empty());
+        assert_eq!(encode_messages(&note, Some(100), 0), vec![[0x82, 60, 0]]);
+        assert!(encode_messages(&note, None, 0).is_empty());
+    }
+}
@@ -0,0 +1,479 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task;
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const OSC_BUNDLE_TAG: &[u8] = b"#bundle\0";
+/// Largest datagram accepted, OSC over UDP packets are never fragmented across datagrams.
+const MAX_OSC_PACKET_SIZE: usize = 65536;
+/// Pause after a failed receive, so a persistent socket error doesn't spin the task.
+const RECEIVE_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum OscArgument {
+    Int(i32),
+    Float(f32),
+    Int64(i64),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+    Impulse,
+    TimeTag(u64),
+    Char(char),
+    Rgba(u32),
+    Midi([u8; 4]),
+}
+
+impl OscArgument {
+    /// Numeric value of the argument, booleans and impulses map to 1/0.
+    /// None for NaN and infinite values, as for non-numeric arguments.
+    pub(crate) fn as_f32(&self) -> Option<f32> {
+        let value = match self {
+            OscArgument::Int(v) => Some(*v as f32),
+            OscArgument::Float(v) => Some(*v),
+            OscArgument::Int64(v) => Some(*v as f32),
+            OscArgument::Double(v) => Some(*v as f32),
+            OscArgument::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            OscArgument::Impulse => Some(1.0),
+            OscArgument::Char(_)
+            | OscArgument::String(_)
+            | OscArgument::Blob(_)
+            | OscArgument::Nil
+            | OscArgument::TimeTag(_)
+            | OscArgument::Rgba(_)
+            | OscArgument::Midi(_) => None,
+        };
+        value.filter(|value| value.is_finite())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OscMessage {
+    pub(crate) address: String,
+    pub(crate) arguments: Vec<OscArgument>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct OscEvent {
+    pub(crate) source_key: String,
+    pub(crate) message: OscMessage,
+}
+
+/// Big-endian reader over an OSC packet, all items are 4-byte aligned.
+struct OscReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> OscReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            bail!(
+                "OSC packet truncated: need {} bytes at offset {}, have {}",
+                len,
+                self.pos,
+                self.data.len() - self.pos
+            );
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn take_padded(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.take(len)?;
+        self.take((4 - len % 4) % 4)?;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Null-terminated string padded with nulls to a multiple of 4 bytes.
+    fn read_string(&mut self) -> Result<String> {
+        let rest = &self.data[self.pos..];
+        let Some(len) = rest.iter().position(|b| *b == 0) else {
+            bail!("OSC string at offset {} is not terminated", self.pos);
+        };
+        let bytes = self.take_padded(len + 1)?;
+        String::from_utf8(bytes[..len].to_vec()).context("OSC string is not valid UTF-8")
+    }
+
+    fn read_blob(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take_padded(len)?.to_vec())
+    }
+}
+
+/// Decodes an OSC packet into its messages, bundles are flattened in order.
+/// NB: bundle time tags are ignored, messages are applied as soon as they arrive.
+pub(crate) fn decode_osc_packet(data: &[u8]) -> Result<Vec<OscMessage>> {
+    let mut messages = Vec::new();
+    decode_osc_packet_into(data, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_osc_packet_into(data: &[u8], messages: &mut Vec<OscMessage>) -> Result<()> {
+    if data.starts_with(OSC_BUNDLE_TAG) {
+        let mut reader = OscReader::new(&data[OSC_BUNDLE_TAG.len()..]);
+        let _time_tag = reader.read_u64()?;
+        while !reader.is_empty() {
+            let len = reader.read_u32()? as usize;
+            decode_osc_packet_into(reader.take(len)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let mut reader = OscReader::new(data);
+    let address = reader.read_string()?;
+    if !address.starts_with('/') {
+        bail!("OSC address '{}' doesn't start with '/'", address);
+    }
+
+    // NB: very old implementations omit the type tag string, such messages carry no arguments.
+    let type_tags = if reader.is_empty() {
+        String::new()
+    } else {
+        reader.read_string()?
+    };
+    let Some(type_tags) = type_tags
+        .strip_prefix(',')
+        .or(type_tags.is_empty().then_some(""))
+    else {
+        bail!("OSC type tag string '{}' doesn't start with ','", type_tags);
+    };
+
+    let mut arguments = Vec::with_capacity(type_tags.len());
+    for tag in type_tags.chars() {
+        let argument = match tag {
+            'i' => OscArgument::Int(reader.read_u32()? as i32),
+            'f' => OscArgument::Float(f32::from_bits(reader.read_u32()?)),
+            'h' => OscArgument::Int64(reader.read_u64()? as i64),
+            'd' => OscArgument::Double(f64::from_bits(reader.read_u64()?)),
+            's' | 'S' => OscArgument::String(reader.read_string()?),
+            'b' => OscArgument::Blob(reader.read_blob()?),
+            't' => OscArgument::TimeTag(reader.read_u64()?),
+            'c' => OscArgument::Char(char::from_u32(reader.read_u32()?).unwrap_or('\0')),
+            'r' => OscArgument::Rgba(reader.read_u32()?),
+            'm' => OscArgument::Midi(reader.take(4)?.try_into().unwrap()),
+            'T' => OscArgument::Bool(true),
+            'F' => OscArgument::Bool(false),
+            'N' => OscArgument::Nil,
+            'I' => OscArgument::Impulse,
+            // Array delimiters, array items are taken as plain arguments.
+            '[' | ']' => continue,
+            _ => bail!("Unsupported OSC type tag '{}' in {}", tag, address),
+        };
+        arguments.push(argument);
+    }
+
+    messages.push(OscMessage { address, arguments });
+    Ok(())
+}
+
+/// Matches an OSC address against an OSC 1.0 address pattern (`?`, `*`, `[a-z]`, `[!abc]`,
+/// `{foo,bar}`), wildcards never match across '/'.
+pub(crate) fn osc_address_matches(pattern: &str, address: &str) -> bool {
+    let pattern_parts: Vec<&str> = pattern.split('/').collect();
+    let address_parts: Vec<&str> = address.split('/').collect();
+    pattern_parts.len() == address_parts.len()
+        && pattern_parts
+            .iter()
+            .zip(&address_parts)
+            .all(|(pattern_part, address_part)| {
+                let pattern_chars: Vec<char> = pattern_part.chars().collect();
+                let address_chars: Vec<char> = address_part.chars().collect();
+                osc_part_matches(&pattern_chars, &address_chars)
+            })
+}
+
+fn osc_part_matches(pattern: &[char], text: &[char]) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return text.is_empty();
+    };
+    match first {
+        '*' => (0..=text.len()).any(|skip| osc_part_matches(rest, &text[skip..])),
+        '?' => !text.is_empty() && osc_part_matches(rest, &text[1..]),
+        '[' => {
+            let Some(end) = rest.iter().position(|c| *c == ']') else {
+                return false;
+            };
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+            let (negated, set) = match rest[..end].split_first() {
+                Some(('!', set)) => (true, set),
+                _ => (false, &rest[..end]),
+            };
+            let mut in_set = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    in_set |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    in_set |= set[i] == c;
+                    i += 1;
+                }
+            }
+            in_set != negated && osc_part_matches(&rest[end + 1..], text_rest)
+        }
+        '{' => {
+            let Some(end) = rest.iter().position(|c| *c == '}') else {
+                return false;
+            };
+            rest[..end].split(|c| *c == ',').any(|alternative| {
+                let mut candidate = alternative.to_vec();
+                candidate.extend_from_slice(&rest[end + 1..]);
+                osc_part_matches(&candidate, text)
+            })
+        }
+        c => text.first() == Some(&c) && osc_part_matches(rest, &text[1..]),
+    }
+}
+
+pub(crate) struct OscManager {
+    debug: bool,
+    sources: HashMap<String, task::JoinHandle<()>>,
+    sender: mpsc::UnboundedSender<OscEvent>,
+    receiver: Arc<AsyncMutex<mpsc::UnboundedReceiver<OscEvent>>>,
+    engine_stop_token: CancellationToken,
+}
+
+impl OscManager {
+    pub(crate) fn new(debug: bool) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Ok(Self {
+            debug,
+            sources: HashMap::new(),
+            sender: tx,
+            receiver: Arc::new(AsyncMutex::new(rx)),
+            engine_stop_token: CancellationToken::new(),
+        })
+    }
+
+    /// Binds the UDP socket right away, so a busy port is reported to the caller.
+    pub(crate) fn open_source(&mut self, key_name: &str, bind: SocketAddr) -> Result<()> {
+        let socket = std::net::UdpSocket::bind(bind)
+            .with_context(|| format!("Failed to bind OSC source '{}' to {}", key_name, bind))?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+
+        let sender = self.sender.clone();
+        let source_key = key_name.to_string();
+        let debug = self.debug;
+        let stop_token = self.engine_stop_token.child_token();
+
+        let handle = task::spawn(async move {
+            let mut buf = vec![0u8; MAX_OSC_PACKET_SIZE];
+            let mut receive_failing = false;
+            loop {
+                let result = tokio::select! {
+                    _ = stop_token.cancelled() => {
+                        info!("Stopping OSC source {}", source_key);
+                        return;
+                    }
+                    result = socket.recv_from(&mut buf) => result,
+                };
+                let (len, peer) = match result {
+                    Ok(received) => {
+                        if receive_failing {
+                            info!("Receiving OSC packets for {} again", source_key);
+                            receive_failing = false;
+                        }
+                        received
+                    }
+                    Err(e) => {
+                        if !receive_failing {
+                            error!(
+                                "Error receiving OSC packets for {}: {}, retrying every {:?}",
+                                source_key, e, RECEIVE_ERROR_BACKOFF
+                            );
+                            receive_failing = true;
+                        }
+                        tokio::select! {
+                            _ = stop_token.cancelled() => {
+                                info!("Stopping OSC source {}", source_key);
+                                return;
+                            }
+                            _ = tokio::time::sleep(RECEIVE_ERROR_BACKOFF) => continue,
+                        }
+                    }
+                };
+                match decode_osc_packet(&buf[..len]) {
+                    Ok(messages) => {
+                        for message in messages {
+                            if debug {
+                                debug!("OSC from {}: {:?}", peer, message);
+                            }
+                            let _ = sender.send(OscEvent {
+                                source_key: source_key.clone(),
+                                message,
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        if debug {
+                            warn!("Malformed OSC packet from {}: {:#}", peer, e);
+                        }
+                    }
+                }
+            }
+        });
+
+        self.sources.insert(key_name.to_string(), handle);
+
+        info!("Listening for OSC on {} as {}", bind, key_name);
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_event(&self) -> Option<OscEvent> {
+        self.receiver.lock().await.recv().await
+    }
+
+    pub(crate) async fn monitor(&mut self, bind: SocketAddr) -> Result<()> {
+        self.open_source(&bind.to_string(), bind)?;
+
+        println!("Press Ctrl+C to stop monitoring...");
+
+        while let Some(event) = self.get_event().await {
+            let arguments: Vec<String> = event
+                .message
+                .arguments
+                .iter()
+                .map(|argument| format!("{:?}", argument))
+                .collect();
+            log::info!(
+                "[{}] {} {}",
+                event.source_key,
+                event.message.address,
+                arguments.join(" ")
+            );
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn stop(&mut self) -> Result<()> {
+        self.engine_stop_token.cancel();
+
+        for (_, handle) in self.sources.drain() {
+            handle.abort();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn osc_string(s: &str) -> Vec<u8> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        while !bytes.len().is_multiple_of(4) {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn fader_message(address: &str, value: f32) -> Vec<u8> {
+        let mut bytes = osc_string(address);
+        bytes.extend(osc_string(",f"));
+        bytes.extend(value.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_decode_message() {
+        let mut packet = osc_string("/1/xy1");
+        packet.extend(osc_string(",ffiTs"));
+        packet.extend(0.25_f32.to_be_bytes());
+        packet.extend(0.75_f32.to_be_bytes());
+        packet.extend((-3_i32).to_be_bytes());
+        packet.extend(osc_string("label"));
+
+        let messages = decode_osc_packet(&packet).unwrap();
+        assert_eq!(
+            messages,
+            vec![OscMessage {
+                address: "/1/xy1".to_string(),
+                arguments: vec![
+                    OscArgument::Float(0.25),
+                    OscArgument::Float(0.75),
+                    OscArgument::Int(-3),
+                    OscArgument::Bool(true),
+                    OscArgument::String("label".to_string()),
+                ],
+            }]
+        );
+        assert_eq!(messages[0].arguments[3].as_f32(), Some(1.0));
+        assert_eq!(messages[0].arguments[4].as_f32(), None);
+        assert_eq!(OscArgument::Float(f32::NAN).as_f32(), None);
+        assert_eq!(OscArgument::Float(f32::NEG_INFINITY).as_f32(), None);
+        // Finite, but out of the f32 range.
+        assert_eq!(OscArgument::Double(1e300).as_f32(), None);
+
+        // Truncated argument data.
+        assert!(decode_osc_packet(&packet[..packet.len() - 12]).is_err());
+    }
+
+    #[test]
+    fn test_decode_nested_bundle() {
+        let first = fader_message("/1/fader1", 0.5);
+        let second = fader_message("/1/fader2", 1.0);
+
+        let mut inner = OSC_BUNDLE_TAG.to_vec();
+        inner.extend(1_u64.to_be_bytes());
+        inner.extend((second.len() as u32).to_be_bytes());
+        inner.extend(&second);
+
+        let mut packet = OSC_BUNDLE_TAG.to_vec();
+        packet.extend(1_u64.to_be_bytes());
+        packet.extend((first.len() as u32).to_be_bytes());
+        packet.extend(&first);
+        packet.extend((inner.len() as u32).to_be_bytes());
+        packet.extend(&inner);
+
+        let messages = decode_osc_packet(&packet).unwrap();
+        let addresses: Vec<&str> = messages.iter().map(|m| m.address.as_str()).collect();
+        assert_eq!(addresses, vec!["/1/fader1", "/1/fader2"]);
+        assert_eq!(messages[1].arguments, vec![OscArgument::Float(1.0)]);
+    }
+
+    #[test]
+    fn test_address_pattern_matching() {
+        assert!(osc_address_matches("/1/fader1", "/1/fader1"));
+        assert!(!osc_address_matches("/1/fader1", "/1/fader10"));
+        assert!(osc_address_matches("/*/fader?", "/2/fader3"));
+        assert!(!osc_address_matches("/*", "/1/fader1"));
+        assert!(osc_address_matches("/1/fader[1-4]", "/1/fader4"));
+        assert!(!osc_address_matches("/1/fader[!1-4]", "/1/fader4"));
+        assert!(osc_address_matches("/mix/{gain,pan}", "/mix/pan"));
+        assert!(!osc_address_matches("/mix/{gain,pan}", "/mix/mute"));
+    }
+}
@@ -39,7 +39,7 @@ pub fn run_steering_indicator_window_overlay(
             Ok(Box::new(SteeringIndicatorWindow {
                 cancellation_token,
                 steering_pos_val: steering_val,
-                steering_hold_val: steering_hold_val,
+                steering_hold_val,
             }))
         }),
     );
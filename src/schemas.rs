@@ -4,9 +4,10 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     collections::HashMap,
     sync::{atomic::AtomicBool, Arc},
+    time::Duration,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 #[doc_impl]
 pub(crate) struct GlobalSettings {
@@ -16,12 +17,48 @@ pub(crate) struct GlobalSettings {
     /// If true, all virtual joysticks will be persistent (not destroyed on hot-reload) by default.
     #[serde(default)]
     pub(crate) persistent_joysticks: bool,
+    /// Holding all of these keys/buttons together releases all grabbed input devices.
+    #[serde(default = "default_emergency_release_combo")]
+    pub(crate) emergency_release_combo: Vec<crate::common::ControlType>,
+    /// Interval in seconds between MIDI port scans for plugged/unplugged devices
+    /// (`/dev/input` is watched for changes instead), 0 disables hot-plugging.
+    #[serde(default = "default_hotplug_poll_interval")]
+    pub(crate) hotplug_poll_interval: f32,
+    /// Names of virtual ALSA MIDI input ports to create, e.g. for a DAW or `aplaymidi` to send to.
+    /// Each is matched by `midi_devices` regexes as "MMVJ Virtual:<name>".
+    #[serde(default)]
+    pub(crate) virtual_midi_ports: Vec<String>,
+}
+
+impl Default for GlobalSettings {
+    fn default() -> Self {
+        Self {
+            idle_tick_update_rate: default_update_rate(),
+            persistent_joysticks: false,
+            emergency_release_combo: default_emergency_release_combo(),
+            hotplug_poll_interval: default_hotplug_poll_interval(),
+            virtual_midi_ports: Vec::new(),
+        }
+    }
 }
 
 fn default_update_rate() -> u32 {
     1000
 }
 
+fn default_hotplug_poll_interval() -> f32 {
+    2.0
+}
+
+fn default_emergency_release_combo() -> Vec<crate::common::ControlType> {
+    use crate::common::ControlType;
+    vec![
+        ControlType::KeyLeftctrl,
+        ControlType::KeyLeftalt,
+        ControlType::KeyEsc,
+    ]
+}
+
 fn default_true() -> bool {
     true
 }
@@ -105,7 +142,57 @@ pub(crate) struct MidiDevice {
     pub(crate) enabled: bool,
     #[serde(with = "serde_regex")]
     pub(crate) match_name_regex: Option<regex::Regex>,
+    /// ALSA sequencer client number of the port, e.g. 24 for "... 24:0" (see `enum-midi`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_client: Option<i32>,
+    /// ALSA sequencer port number within the client, e.g. 0 for "... 24:0".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_port: Option<i32>,
     pub(crate) controls: HashMap<String, ControlEntry<MidiControl>>,
+    /// Values sent back to the device, e.g. to its LED rings and motorized faders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) feedback: Option<MidiFeedback>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MidiFeedback {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    /// Output ports to send to (see `enum-midi-outputs`), by default the ones matching the
+    /// device `match_name_regex`, `match_client` and `match_port`.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) output_name_regex: Option<regex::Regex>,
+    /// Most messages per second sent to a control.
+    #[serde(default = "default_feedback_rate_limit")]
+    pub(crate) rate_limit: f32,
+    /// Seconds the feedback to a control waits after the last input of its `touch_controls`.
+    #[serde(default = "default_feedback_touch_timeout")]
+    pub(crate) touch_timeout: f32,
+    pub(crate) controls: HashMap<String, MidiFeedbackControl>,
+}
+
+fn default_feedback_rate_limit() -> f32 {
+    30.0
+}
+
+fn default_feedback_touch_timeout() -> f32 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MidiFeedbackControl {
+    /// Virtual joystick or internal control whose value is sent, mapped from its range
+    /// to the message range.
+    pub(crate) source: MappingSource,
+    /// The message to send, as in `midi_outputs`.
+    pub(crate) message: MidiOutputControl,
+    /// Controls of this device touching which holds the feedback back, e.g. the fader itself
+    /// and its touch sensor note. A held note holds it back until released.
+    /// By default the controls of this device mapped to the `source`, `[]` turns it off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) touch_controls: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -115,12 +202,83 @@ pub(crate) struct MidiControl {
     pub(crate) merge_from: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) midi_message: Option<MidiMessage>,
+    /// Decode control change values of endless encoders into signed deltas.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) encoding: Option<MidiValueEncoding>,
+    /// Note controls: while the note is held, raise its velocity value by the aftertouch pressure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) velocity_modulation: Option<MidiVelocityModulation>,
+    /// Note controls: follow the notes of `number` still held instead of the last note event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) note_group: Option<MidiNoteGroup>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) range: Option<NumInterval<i32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) description: Option<String>,
 }
 
+/// Aftertouch source modulating the velocity of a held note,
+/// the value goes from the note velocity (no pressure) up to 127 (full pressure).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MidiVelocityModulation {
+    /// Pressure of the note itself.
+    PolyAftertouch,
+    /// Pressure of the note channel, for keyboards without polyphonic aftertouch.
+    ChannelAftertouch,
+}
+
+/// Value of a group of notes, computed from the notes of the group still held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MidiNoteGroup {
+    /// Number of the lowest held note, kept when all notes are released.
+    Lowest,
+    /// Number of the highest held note, kept when all notes are released.
+    Highest,
+    /// Number of the most recently pressed held note, kept when all notes are released.
+    Last,
+    /// How many notes are held.
+    Count,
+    /// 127 while any note is held, 0 otherwise.
+    AnyHeld,
+}
+
+/// How relative (endless encoder) control changes encode their increments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MidiValueEncoding {
+    /// 1..63 clockwise, 127..64 counter-clockwise (-1..-64).
+    RelativeTwosComplement,
+    /// Bit 6 is the sign: 1..63 clockwise, 65..127 counter-clockwise (-1..-63).
+    RelativeSignedBit,
+    /// 64 means no movement: 65..127 clockwise (+1..+63), 63..0 counter-clockwise (-1..-64).
+    RelativeOffset64,
+}
+
+impl MidiValueEncoding {
+    pub(crate) fn decode(&self, value: u8) -> i32 {
+        let value = (value & 0x7F) as i32;
+        match self {
+            MidiValueEncoding::RelativeTwosComplement => {
+                if value < 64 {
+                    value
+                } else {
+                    value - 128
+                }
+            }
+            MidiValueEncoding::RelativeSignedBit => {
+                if value & 0x40 == 0 {
+                    value
+                } else {
+                    -(value & 0x3F)
+                }
+            }
+            MidiValueEncoding::RelativeOffset64 => value - 64,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MidiMessage {
@@ -133,6 +291,89 @@ pub(crate) struct MidiMessage {
     pub(crate) channel: MidiChannel,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) number: Option<MidiNumber>,
+    /// SysEx messages: the bytes to match, e.g. "F0 7F ?? 06 ?? F7".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pattern: Option<SysexPattern>,
+    /// SysEx messages: index of the byte giving the value (0 is the F0 status), else 127 on match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) value_offset: Option<usize>,
+}
+
+/// Hex bytes with `??` matching any byte, a trailing `*` matches any remaining bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct SysexPattern {
+    pub(crate) bytes: Vec<Option<u8>>,
+    pub(crate) any_tail: bool,
+}
+
+impl SysexPattern {
+    pub(crate) fn matches(&self, data: &[u8]) -> bool {
+        let length_matches = if self.any_tail {
+            data.len() >= self.bytes.len()
+        } else {
+            data.len() == self.bytes.len()
+        };
+        length_matches
+            && self
+                .bytes
+                .iter()
+                .zip(data)
+                .all(|(pattern, byte)| pattern.is_none_or(|pattern| pattern == *byte))
+    }
+}
+
+impl std::fmt::Display for SysexPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| byte.map_or("??".to_string(), |byte| format!("{:02X}", byte)))
+            .collect();
+        if self.any_tail {
+            parts.push("*".to_string());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl std::str::FromStr for SysexPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens: Vec<&str> = s.split_whitespace().collect();
+        let any_tail = tokens.last() == Some(&"*");
+        if any_tail {
+            tokens.pop();
+        }
+        let bytes = tokens
+            .iter()
+            .map(|token| match *token {
+                "??" => Ok(None),
+                _ => u8::from_str_radix(token, 16).map(Some).map_err(|_| {
+                    anyhow::anyhow!("Invalid SysEx pattern byte '{}' in '{}'", token, s)
+                }),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if bytes.first() != Some(&Some(0xF0)) {
+            anyhow::bail!("SysEx pattern '{}' must start with F0", s);
+        }
+        Ok(SysexPattern { bytes, any_tail })
+    }
+}
+
+impl TryFrom<String> for SysexPattern {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SysexPattern> for String {
+    fn from(pattern: SysexPattern) -> Self {
+        pattern.to_string()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -145,7 +386,52 @@ pub(crate) enum MidiMessageType {
     NoteOn,
     NoteOff,
     Aftertouch,
+    /// Pressure of a held note (`number`), 0-127.
+    PolyAftertouch,
     ProgramChange,
+    /// Controller 0-31 (`number`) combined with its LSB controller 32-63, 0-16383.
+    #[serde(rename = "control_change_14bit")]
+    ControlChange14bit,
+    /// Non-registered parameter (`number` 0-16383) set via data entry, 0-16383.
+    Nrpn,
+    /// Registered parameter (`number` 0-16383) set via data entry, 0-16383.
+    Rpn,
+    /// System exclusive message matched by `pattern`.
+    Sysex,
+    /// Beats per minute measured from the MIDI clock.
+    ClockTempo,
+    /// Sixteenth notes since the song start (0-16383), set by song position pointer
+    /// messages and advanced by the clock while playing.
+    SongPosition,
+    /// Transport buttons: 127 from their message until the next start, continue or stop.
+    TransportStart,
+    TransportContinue,
+    TransportStop,
+}
+
+impl MidiMessageType {
+    pub(crate) fn is_14bit(&self) -> bool {
+        matches!(
+            self,
+            MidiMessageType::ControlChange14bit
+                | MidiMessageType::Nrpn
+                | MidiMessageType::Rpn
+                | MidiMessageType::SongPosition
+        )
+    }
+
+    /// System messages, not addressed to a channel and without numbers.
+    pub(crate) fn is_system(&self) -> bool {
+        matches!(
+            self,
+            MidiMessageType::Sysex
+                | MidiMessageType::ClockTempo
+                | MidiMessageType::SongPosition
+                | MidiMessageType::TransportStart
+                | MidiMessageType::TransportContinue
+                | MidiMessageType::TransportStop
+        )
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -192,63 +478,659 @@ where
     }
 }
 
-impl Serialize for MidiChannel {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        match self {
-            MidiChannel::Any => serializer.serialize_str("any"),
-            MidiChannel::Number(n) => serializer.serialize_u8(*n),
-        }
-    }
+impl Serialize for MidiChannel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            MidiChannel::Any => serializer.serialize_str("any"),
+            MidiChannel::Number(n) => serializer.serialize_u8(*n),
+        }
+    }
+}
+
+fn default_channel() -> MidiChannel {
+    MidiChannel::Any
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+#[serde(deny_unknown_fields)]
+pub(crate) enum MidiNumber {
+    Single(u16),
+    Multiple(Vec<u16>),
+    /// "any" or an inclusive range like "48-59".
+    Special(String),
+}
+
+impl MidiNumber {
+    /// Bounds of an inclusive range like "48-59".
+    pub(crate) fn parse_range(spec: &str) -> Option<(u16, u16)> {
+        let (low, high) = spec.split_once('-')?;
+        let (low, high): (u16, u16) = (low.trim().parse().ok()?, high.trim().parse().ok()?);
+        (low <= high).then_some((low, high))
+    }
+
+    pub(crate) fn contains(&self, number: u16) -> bool {
+        match self {
+            MidiNumber::Single(n) => number == *n,
+            MidiNumber::Multiple(numbers) => numbers.contains(&number),
+            MidiNumber::Special(s) => {
+                s.eq_ignore_ascii_case("any")
+                    || Self::parse_range(s)
+                        .is_some_and(|(low, high)| (low..=high).contains(&number))
+            }
+        }
+    }
+
+    /// Lowest and highest number, `None` for "any".
+    pub(crate) fn bounds(&self) -> Option<(u16, u16)> {
+        match self {
+            MidiNumber::Single(n) => Some((*n, *n)),
+            MidiNumber::Multiple(numbers) => Some((*numbers.iter().min()?, *numbers.iter().max()?)),
+            MidiNumber::Special(s) => Self::parse_range(s),
+        }
+    }
+
+    /// Count of distinct numbers, `None` for "any".
+    pub(crate) fn count(&self) -> Option<usize> {
+        match self {
+            MidiNumber::Single(_) => Some(1),
+            MidiNumber::Multiple(numbers) => Some(
+                numbers
+                    .iter()
+                    .collect::<std::collections::HashSet<_>>()
+                    .len(),
+            ),
+            MidiNumber::Special(s) => {
+                Self::parse_range(s).map(|(low, high)| (high - low) as usize + 1)
+            }
+        }
+    }
+}
+
+// ----------------
+// Mouse Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MouseDevice {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    #[serde(with = "serde_regex")]
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    /// Vendor ID reported by the device, e.g. 0x046d (see `enum-mice`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_vendor_id: Option<u16>,
+    /// Product ID reported by the device, e.g. 0xc08b.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_product_id: Option<u16>,
+    /// Matched against the physical path, e.g. "usb-0000:00:14.0-2/input0".
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_phys_regex: Option<regex::Regex>,
+    /// Matched against the unique identifier (usually a serial number) if the device reports one.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_uniq_regex: Option<regex::Regex>,
+    /// Matched against the names of the device links in /dev/input/by-id.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_by_id_regex: Option<regex::Regex>,
+    /// Give each matched device its own runtime identity instead of merging their events,
+    /// mappings select one as `device: key[N]` or `device: key[<phys>]`, plain `key` is `key[0]`.
+    #[serde(default)]
+    pub(crate) instances: bool,
+    /// Take the device exclusively while the engine runs (other apps and the desktop don't see its events).
+    #[serde(default)]
+    pub(crate) grab: bool,
+    /// Counts reported per centimeter of movement (written by `calibrate-mouse`), lets mappings of this
+    /// device give relative distances like `counts_to_lock: "12cm"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) counts_per_cm: Option<f32>,
+    pub(crate) controls: HashMap<String, ControlEntry<MouseControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MouseControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) r#type: Option<crate::common::ControlType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+/// Resolved mouse control with all required fields populated
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedMouseControl {
+    pub(crate) r#type: crate::common::ControlType,
+    pub(crate) range: NumInterval<i32>,
+    pub(crate) _description: Option<String>,
+}
+
+// ----------------
+// Keyboard Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeyboardDevice {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    #[serde(with = "serde_regex")]
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    /// Vendor ID reported by the device, e.g. 0x046d (see `enum-keyboards`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_vendor_id: Option<u16>,
+    /// Product ID reported by the device, e.g. 0xc08b.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_product_id: Option<u16>,
+    /// Matched against the physical path, e.g. "usb-0000:00:14.0-2/input0".
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_phys_regex: Option<regex::Regex>,
+    /// Matched against the unique identifier (usually a serial number) if the device reports one.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_uniq_regex: Option<regex::Regex>,
+    /// Matched against the names of the device links in /dev/input/by-id.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_by_id_regex: Option<regex::Regex>,
+    /// Take the device exclusively while the engine runs (other apps and the desktop don't see its events).
+    #[serde(default)]
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ControlEntry<KeyboardControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeyboardControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) r#type: Option<crate::common::ControlType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<i32>>,
+    /// If true, autorepeat events of a held key are passed to mappings as repeated presses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) repeat: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+/// Resolved keyboard control with all required fields populated
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedKeyboardControl {
+    pub(crate) r#type: crate::common::ControlType,
+    pub(crate) range: NumInterval<i32>,
+    pub(crate) repeat: bool,
+    pub(crate) _description: Option<String>,
+}
+
+// ----------------
+// Gamepad / Physical Joystick Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GamepadDevice {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    #[serde(with = "serde_regex")]
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    /// Vendor ID reported by the device, e.g. 0x046d (see `enum-gamepads`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_vendor_id: Option<u16>,
+    /// Product ID reported by the device, e.g. 0xc08b.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_product_id: Option<u16>,
+    /// Matched against the physical path, e.g. "usb-0000:00:14.0-2/input0".
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_phys_regex: Option<regex::Regex>,
+    /// Matched against the unique identifier (usually a serial number) if the device reports one.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_uniq_regex: Option<regex::Regex>,
+    /// Matched against the names of the device links in /dev/input/by-id.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_by_id_regex: Option<regex::Regex>,
+    /// Take the device exclusively while the engine runs (other apps and the desktop don't see its events).
+    #[serde(default)]
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ControlEntry<GamepadControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GamepadControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) r#type: Option<crate::common::ControlType>,
+    /// If omitted, absolute axis range is read from the device (AbsInfo) when it's opened.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+/// Resolved gamepad control, range stays optional until the device is opened.
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedGamepadControl {
+    pub(crate) r#type: crate::common::ControlType,
+    pub(crate) range: Option<NumInterval<i32>>,
+    pub(crate) _description: Option<String>,
+}
+
+// ----------------
+// Multitouch (Touchpad / Tablet) Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TouchDevice {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    #[serde(with = "serde_regex")]
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    /// Vendor ID reported by the device, e.g. 0x046d (see `enum-touch`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_vendor_id: Option<u16>,
+    /// Product ID reported by the device, e.g. 0xc08b.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) match_product_id: Option<u16>,
+    /// Matched against the physical path, e.g. "usb-0000:00:14.0-2/input0".
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_phys_regex: Option<regex::Regex>,
+    /// Matched against the unique identifier (usually a serial number) if the device reports one.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_uniq_regex: Option<regex::Regex>,
+    /// Matched against the names of the device links in /dev/input/by-id.
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    pub(crate) match_by_id_regex: Option<regex::Regex>,
+    /// Take the device exclusively while the engine runs (other apps and the desktop don't see its events).
+    #[serde(default)]
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ControlEntry<TouchControl>>,
+}
+
+/// Value derived from the set of fingers currently touching the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TouchControlType {
+    PositionX,
+    PositionY,
+    Pressure,
+    FingerCount,
+    Touching,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TouchControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) r#type: Option<TouchControlType>,
+    /// 1-based finger in the order of touching the surface. If omitted, position and pressure
+    /// are averaged over all touching fingers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) finger: Option<u8>,
+    /// Only produce values while exactly this many fingers touch the surface.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fingers: Option<u8>,
+    /// If omitted, taken from the device (AbsInfo of the related ABS_MT_* axis).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+/// Resolved touch control, range stays optional until the device is opened.
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedTouchControl {
+    pub(crate) r#type: TouchControlType,
+    pub(crate) finger: Option<u8>,
+    pub(crate) fingers: Option<u8>,
+    pub(crate) range: Option<NumInterval<i32>>,
+    pub(crate) _description: Option<String>,
+}
+
+// ----------------
+// OSC Source Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OscSource {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    /// Local UDP address to receive OSC packets on, e.g. "0.0.0.0:9000".
+    pub(crate) bind: std::net::SocketAddr,
+    pub(crate) controls: HashMap<String, ControlEntry<OscControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OscControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    /// OSC address pattern, e.g. "/1/fader1" or "/*/fader[1-4]".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) address: Option<String>,
+    /// 0-based index of the message argument carrying the value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) argument: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedOscControl {
+    pub(crate) address: String,
+    pub(crate) argument: usize,
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) _description: Option<String>,
+}
+
+// ----------------
+// JSON Source Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JsonSource {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    /// Local UDP address to receive datagrams on, e.g. "127.0.0.1:9100".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) udp: Option<std::net::SocketAddr>,
+    /// Unix stream socket path to accept connections on, messages are newline separated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) unix: Option<std::path::PathBuf>,
+    /// Seconds between message rate reports in the log, 0 disables them.
+    #[serde(default)]
+    pub(crate) stats_interval: f32,
+    pub(crate) controls: HashMap<String, ControlEntry<JsonControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JsonControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    /// Value of the "control" field of the messages, defaults to the control key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedJsonControl {
+    pub(crate) name: String,
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) _description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonEndpoint {
+    Udp(std::net::SocketAddr),
+    Unix(std::path::PathBuf),
+}
+
+impl std::fmt::Display for JsonEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonEndpoint::Udp(addr) => write!(f, "udp:{}", addr),
+            JsonEndpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl std::str::FromStr for JsonEndpoint {
+    type Err = anyhow::Error;
+
+    /// Parses the `Display` form, "udp:127.0.0.1:9100" or "unix:/run/user/1000/mmvj.sock".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(bind) = s.strip_prefix("udp:") {
+            Ok(JsonEndpoint::Udp(bind.parse().map_err(|e| {
+                anyhow::anyhow!("Invalid UDP address '{}': {}", bind, e)
+            })?))
+        } else if let Some(path) = s.strip_prefix("unix:") {
+            Ok(JsonEndpoint::Unix(path.into()))
+        } else {
+            anyhow::bail!(
+                "Expected 'udp:<address>:<port>' or 'unix:<path>', got '{}'",
+                s
+            )
+        }
+    }
+}
+
+// ----------------
+// Generator Source Types
+// ----------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GeneratorSource {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    pub(crate) controls: HashMap<String, ControlEntry<GeneratorControl>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GeneratorControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) merge_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) waveform: Option<GeneratorWaveform>,
+    /// Cycles per second, a step sequence cycle plays all of its steps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) frequency: Option<GeneratorParameter>,
+    /// The output is `offset + amplitude * waveform`, waveforms swing in -1..1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) amplitude: Option<GeneratorParameter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) offset: Option<GeneratorParameter>,
+    /// Waveform values of a step sequence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) steps: Option<Vec<f32>>,
+    /// Seed of the `random_walk` noise, derived from the source and control keys if omitted,
+    /// so the same config wanders the same way on every run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GeneratorWaveform {
+    Sine,
+    Triangle,
+    Square,
+    /// Rises from -1 to 1 every cycle.
+    Ramp,
+    /// Always 0, so the output is the `offset`.
+    Constant,
+    /// Wanders randomly within -1..1, `frequency` sets how fast.
+    RandomWalk,
+    StepSequence,
+}
+
+/// A fixed value, or the state of another control mapped into `range` (default 0..1).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+#[serde(deny_unknown_fields)]
+pub(crate) enum GeneratorParameter {
+    Value(f32),
+    Reference {
+        device: String,
+        control: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<NumInterval<f32>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ResolvedGeneratorParameter {
+    Value(f32),
+    Reference {
+        device: String,
+        control: String,
+        control_range: NumInterval<f32>,
+        range: NumInterval<f32>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedGeneratorControl {
+    pub(crate) waveform: GeneratorWaveform,
+    pub(crate) frequency: ResolvedGeneratorParameter,
+    pub(crate) amplitude: ResolvedGeneratorParameter,
+    pub(crate) offset: ResolvedGeneratorParameter,
+    pub(crate) steps: Vec<f32>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) _description: Option<String>,
 }
 
-fn default_channel() -> MidiChannel {
-    MidiChannel::Any
+/// Fully resolved generator source configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedGeneratorSource {
+    pub(crate) enabled: bool,
+    pub(crate) controls: HashMap<String, ResolvedGeneratorControl>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
+// ----------------
+// Internal Control Types
+// ----------------
+/// Named value that mappings write to and read from as device `internal`,
+/// it never appears on a virtual joystick.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
-pub(crate) enum MidiNumber {
-    Single(u8),
-    Multiple(Vec<u8>),
-    Special(String),
+pub(crate) struct InternalControl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) range: Option<NumInterval<i32>>,
+    #[serde(default)]
+    pub(crate) initial_value: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedInternalControl {
+    pub(crate) range: NumInterval<i32>,
+    pub(crate) initial_value: i32,
+    pub(crate) idle_tick_enabled_flag: Arc<AtomicBool>,
+    pub(crate) _description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedMidiFeedback {
+    pub(crate) output_name_regex: regex::Regex,
+    pub(crate) port_match: ResolvedMidiPortMatch,
+    pub(crate) min_interval: Duration,
+    pub(crate) touch_timeout: Duration,
+    pub(crate) controls: HashMap<String, ResolvedMidiFeedbackControl>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedMidiFeedbackControl {
+    pub(crate) source_device: String,
+    pub(crate) source_control: String,
+    pub(crate) source_range: NumInterval<i32>,
+    pub(crate) message: ResolvedMidiOutputControl,
+    pub(crate) touch_controls: Vec<String>,
 }
 
 // ----------------
-// Mouse Types
+// MIDI Output Types
 // ----------------
+/// A MIDI port mappings send values to, as destination `joystick: <key>`.
+/// Either an existing port matched by name or a virtual port created for other applications.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub(crate) struct MouseDevice {
+pub(crate) struct MidiOutputDevice {
     #[serde(default = "default_true")]
     pub(crate) enabled: bool,
-    #[serde(with = "serde_regex")]
+    /// Name of the port to connect to (see `enum-midi-outputs`).
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
     pub(crate) match_name_regex: Option<regex::Regex>,
-    pub(crate) controls: HashMap<String, ControlEntry<MouseControl>>,
+    /// Name of a virtual ALSA port to create instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) virtual_port: Option<String>,
+    pub(crate) controls: HashMap<String, MidiOutputControl>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Message template a mapped value is sent with. The value is sent as is, clamped to `range`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub(crate) struct MouseControl {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) merge_from: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) r#type: Option<crate::common::ControlType>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+pub(crate) struct MidiOutputControl {
+    #[serde(rename = "type")]
+    pub(crate) msg_type: MidiOutputMessageType,
+    /// 1 to 16.
+    #[serde(default = "default_midi_output_channel")]
+    pub(crate) channel: u8,
+    /// Controller or note number, not for `pitch_wheel`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) number: Option<u8>,
+    /// Defaults to the full value range of the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub(crate) range: Option<NumInterval<i32>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub(crate) description: Option<String>,
 }
 
-/// Resolved mouse control with all required fields populated
+fn default_midi_output_channel() -> u8 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MidiOutputMessageType {
+    /// 0-127.
+    ControlChange,
+    /// Controller 0-31 and its LSB controller 32-63, 0-16383.
+    #[serde(rename = "control_change_14bit")]
+    ControlChange14bit,
+    /// The value is the velocity: note on from 0 to above, note off back to 0.
+    Note,
+    /// -8192-8191.
+    PitchWheel,
+}
+
+impl MidiOutputMessageType {
+    /// Values the message can carry.
+    pub(crate) fn value_range(&self) -> NumInterval<i32> {
+        match self {
+            MidiOutputMessageType::ControlChange | MidiOutputMessageType::Note => {
+                NumInterval::new(0, 127)
+            }
+            MidiOutputMessageType::ControlChange14bit => NumInterval::new(0, 16383),
+            MidiOutputMessageType::PitchWheel => NumInterval::new(-8192, 8191),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub(crate) struct ResolvedMouseControl {
-    pub(crate) r#type: crate::common::ControlType,
+pub(crate) struct ResolvedMidiOutputDevice {
+    pub(crate) enabled: bool,
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) virtual_port: Option<String>,
+    pub(crate) controls: HashMap<String, ResolvedMidiOutputControl>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedMidiOutputControl {
+    pub(crate) msg_type: MidiOutputMessageType,
+    /// 0 to 15.
+    pub(crate) channel: u8,
+    pub(crate) number: u8,
     pub(crate) range: NumInterval<i32>,
-    pub(crate) _description: Option<String>,
+    pub(crate) idle_tick_enabled_flag: Arc<AtomicBool>,
 }
 
 // ----------------
@@ -265,6 +1147,49 @@ pub(crate) struct VirtualJoystick {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) force_feedback: Option<FFCapabilities>,
     pub(crate) controls: HashMap<String, ControlEntry<JoystickControl>>,
+    /// Hat switches composed from direction mappings, see `HatSwitch`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) hats: HashMap<String, HatSwitch>,
+}
+
+/// A hat switch on a pair of ABS_HAT axes (values -1, 0, 1), written by mappings with
+/// a `direction` so that up/down/left/right sources combine into one hat position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HatSwitch {
+    /// Hat number: 0 for ABS_HAT0X/ABS_HAT0Y up to 3.
+    #[serde(default)]
+    pub(crate) hat: u8,
+    /// What opposite directions held together give.
+    #[serde(default)]
+    pub(crate) socd: SocdPolicy,
+    /// If false, the hat is 4-way: of two perpendicular directions held together only one counts.
+    #[serde(default = "default_true")]
+    pub(crate) diagonals: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+/// Simultaneous opposite cardinal directions resolution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SocdPolicy {
+    /// The direction pressed last wins.
+    #[default]
+    LastWins,
+    /// The direction pressed first wins.
+    FirstWins,
+    /// Both cancel out.
+    Neutral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HatDirection {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -323,6 +1248,17 @@ pub(crate) struct JoystickControl {
     pub(crate) properties: Option<AxisProperties>,
     #[serde(default)]
     pub(crate) initial_value: i32,
+    /// Buttons and keys only: the value at or above which the control gets pressed.
+    /// If unset, any non-zero value presses it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) press_threshold: Option<f32>,
+    /// Buttons and keys only: the value below which a pressed control gets released,
+    /// defaults to `press_threshold`. A lower value avoids chattering around the threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) release_threshold: Option<f32>,
+    /// Buttons and keys only: emit autorepeat events while the control is held.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) repeat: Option<KeyRepeat>,
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub(crate) idle_tick_enabled_flag: Arc<AtomicBool>,
@@ -336,11 +1272,58 @@ impl Default for JoystickControl {
             range: None,
             properties: None,
             initial_value: 0,
+            press_threshold: None,
+            release_threshold: None,
+            repeat: None,
             idle_tick_enabled_flag: Arc::new(AtomicBool::new(false)),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeyRepeat {
+    /// Seconds from the press to the first autorepeat event.
+    #[serde(default = "default_key_repeat_delay")]
+    pub(crate) delay: f32,
+    /// Autorepeat events per second.
+    #[serde(default = "default_key_repeat_rate")]
+    pub(crate) rate: f32,
+}
+
+fn default_key_repeat_delay() -> f32 {
+    0.25
+}
+fn default_key_repeat_rate() -> f32 {
+    30.0
+}
+
+/// Autorepeat timing of a button or key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ResolvedKeyRepeat {
+    pub(crate) delay: Duration,
+    /// Time between autorepeat events, the inverse of the rate.
+    pub(crate) interval: Duration,
+}
+
+/// Press/release hysteresis of a button or key driven by arbitrary values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct KeyThresholds {
+    pub(crate) press: f32,
+    pub(crate) release: f32,
+}
+
+impl KeyThresholds {
+    /// The pressed state after `value` arrives. Without thresholds any non-zero value presses.
+    pub(crate) fn is_pressed(thresholds: Option<&Self>, was_pressed: bool, value: f32) -> bool {
+        match thresholds {
+            None => value != 0.0,
+            Some(t) if was_pressed => value >= t.release,
+            Some(t) => value >= t.press,
+        }
+    }
+}
+
 /// Resolved joystick control with all required fields populated
 #[derive(Debug, Clone)]
 pub(crate) struct ResolvedJoystickControl {
@@ -348,6 +1331,8 @@ pub(crate) struct ResolvedJoystickControl {
     pub(crate) range: NumInterval<i32>,
     pub(crate) properties: Option<AxisProperties>,
     pub(crate) initial_value: i32,
+    pub(crate) thresholds: Option<KeyThresholds>,
+    pub(crate) repeat: Option<ResolvedKeyRepeat>,
     pub(crate) idle_tick_enabled_flag: Arc<AtomicBool>,
 }
 
@@ -361,9 +1346,19 @@ pub(crate) struct ResolvedVirtualJoystick {
     pub(crate) name: String,
     pub(crate) properties: JoystickProperties,
     pub(crate) controls: HashMap<String, ResolvedJoystickControl>,
+    pub(crate) hats: HashMap<String, ResolvedHatSwitch>,
     pub(crate) force_feedback: Option<FFCapabilities>,
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedHatSwitch {
+    pub(crate) x: crate::common::ControlType,
+    pub(crate) y: crate::common::ControlType,
+    pub(crate) socd: SocdPolicy,
+    pub(crate) diagonals: bool,
+    pub(crate) idle_tick_enabled_flag: Arc<AtomicBool>,
+}
+
 impl ResolvedVirtualJoystick {
     pub(crate) fn is_ff_enabled(&self) -> bool {
         self.force_feedback
@@ -378,17 +1373,86 @@ impl ResolvedVirtualJoystick {
 pub(crate) struct ResolvedMidiDevice {
     pub(crate) enabled: bool,
     pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) port_match: ResolvedMidiPortMatch,
     pub(crate) controls: HashMap<String, MidiControl>,
 }
 
+/// MIDI port address matchers, each one given must match along with the name regex.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResolvedMidiPortMatch {
+    pub(crate) client: Option<i32>,
+    pub(crate) port: Option<i32>,
+}
+
 /// Fully resolved mouse device configuration - all controls expanded
 #[derive(Debug, Clone)]
 pub(crate) struct ResolvedMouseDevice {
     pub(crate) enabled: bool,
     pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) device_match: ResolvedEvdevDeviceMatch,
+    pub(crate) instances: bool,
+    pub(crate) grab: bool,
+    pub(crate) counts_per_cm: Option<f32>,
     pub(crate) controls: HashMap<String, ResolvedMouseControl>,
 }
 
+/// Evdev device attribute matchers, each one given must match along with the name regex.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResolvedEvdevDeviceMatch {
+    pub(crate) vendor_id: Option<u16>,
+    pub(crate) product_id: Option<u16>,
+    pub(crate) phys_regex: Option<regex::Regex>,
+    pub(crate) uniq_regex: Option<regex::Regex>,
+    pub(crate) by_id_regex: Option<regex::Regex>,
+}
+
+/// Fully resolved keyboard device configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedKeyboardDevice {
+    pub(crate) enabled: bool,
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) device_match: ResolvedEvdevDeviceMatch,
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ResolvedKeyboardControl>,
+}
+
+/// Fully resolved gamepad device configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedGamepadDevice {
+    pub(crate) enabled: bool,
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) device_match: ResolvedEvdevDeviceMatch,
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ResolvedGamepadControl>,
+}
+
+/// Fully resolved touch device configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedTouchDevice {
+    pub(crate) enabled: bool,
+    pub(crate) match_name_regex: Option<regex::Regex>,
+    pub(crate) device_match: ResolvedEvdevDeviceMatch,
+    pub(crate) grab: bool,
+    pub(crate) controls: HashMap<String, ResolvedTouchControl>,
+}
+
+/// Fully resolved JSON source configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedJsonSource {
+    pub(crate) enabled: bool,
+    pub(crate) endpoint: JsonEndpoint,
+    pub(crate) stats_interval: Option<std::time::Duration>,
+    pub(crate) controls: HashMap<String, ResolvedJsonControl>,
+}
+
+/// Fully resolved OSC source configuration - all controls expanded
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedOscSource {
+    pub(crate) enabled: bool,
+    pub(crate) bind: std::net::SocketAddr,
+    pub(crate) controls: HashMap<String, ResolvedOscControl>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct AxisProperties {
     #[serde(default = "default_resolution")]
@@ -431,6 +1495,9 @@ pub(crate) struct MappingSource {
 pub(crate) struct MappingDestination {
     pub(crate) joystick: String,
     pub(crate) control: String,
+    /// The direction a mapping to a hat switch presses, with any non-zero value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) direction: Option<HatDirection>,
 }
 
 pub(crate) type Transformation = Vec<TransformationStep>;
@@ -708,11 +1775,153 @@ pub(crate) struct InvertTransform {
 //     pub(crate) params: Option<HashMap<String, f32>>,
 // }
 
+/// Distance in relative input counts, or a physical one like "12cm", "45mm" or "2in"
+/// converted with the `counts_per_cm` of the source mouse.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum RelativeDistance {
+    Counts(f32),
+    Physical(PhysicalDistance),
+}
+
+impl<'de> Deserialize<'de> for RelativeDistance {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // NB: parsed by hand so that a bad unit is reported instead of "no variant matched".
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Counts(f32),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Counts(counts) => Ok(RelativeDistance::Counts(counts)),
+            Raw::Text(text) => text
+                .parse()
+                .map(RelativeDistance::Physical)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl RelativeDistance {
+    pub(crate) fn to_counts(self, counts_per_cm: Option<f32>) -> anyhow::Result<f32> {
+        match self {
+            RelativeDistance::Counts(counts) => Ok(counts),
+            RelativeDistance::Physical(distance) => match counts_per_cm {
+                Some(counts_per_cm) => Ok(distance.cm() * counts_per_cm),
+                None => anyhow::bail!(
+                    "Distance '{}' needs `counts_per_cm` on the source mouse device \
+                    (measure it with `calibrate-mouse`)",
+                    distance
+                ),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct PhysicalDistance {
+    pub(crate) value: f32,
+    pub(crate) unit: DistanceUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DistanceUnit {
+    Cm,
+    Mm,
+    In,
+}
+
+impl DistanceUnit {
+    const ALL: [(DistanceUnit, &'static str, f32); 3] = [
+        (DistanceUnit::Cm, "cm", 1.0),
+        (DistanceUnit::Mm, "mm", 0.1),
+        (DistanceUnit::In, "in", 2.54),
+    ];
+}
+
+impl PhysicalDistance {
+    pub(crate) fn cm(&self) -> f32 {
+        let (_, _, cm_per_unit) = DistanceUnit::ALL
+            .into_iter()
+            .find(|(unit, _, _)| *unit == self.unit)
+            .unwrap();
+        self.value * cm_per_unit
+    }
+}
+
+impl std::fmt::Display for PhysicalDistance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (_, suffix, _) = DistanceUnit::ALL
+            .into_iter()
+            .find(|(unit, _, _)| *unit == self.unit)
+            .unwrap();
+        write!(f, "{}{}", self.value, suffix)
+    }
+}
+
+impl std::str::FromStr for PhysicalDistance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let Some((unit, number)) = DistanceUnit::ALL
+            .into_iter()
+            .find_map(|(unit, suffix, _)| s.strip_suffix(suffix).map(|number| (unit, number)))
+        else {
+            anyhow::bail!("Distance '{}' must end with a unit: cm, mm or in", s);
+        };
+        let value: f32 = number
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid distance '{}'", s))?;
+        Ok(PhysicalDistance { value, unit })
+    }
+}
+
+impl TryFrom<String> for PhysicalDistance {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PhysicalDistance> for String {
+    fn from(distance: PhysicalDistance) -> Self {
+        distance.to_string()
+    }
+}
+
+/// `[from, to]` pair of relative distances.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(
+    from = "(RelativeDistance, RelativeDistance)",
+    into = "(RelativeDistance, RelativeDistance)"
+)]
+pub(crate) struct RelativeRange {
+    pub(crate) from: RelativeDistance,
+    pub(crate) to: RelativeDistance,
+}
+
+impl From<(RelativeDistance, RelativeDistance)> for RelativeRange {
+    fn from((from, to): (RelativeDistance, RelativeDistance)) -> Self {
+        RelativeRange { from, to }
+    }
+}
+
+impl From<RelativeRange> for (RelativeDistance, RelativeDistance) {
+    fn from(range: RelativeRange) -> Self {
+        (range.from, range.to)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct IntegrateTransform {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) range: Option<NumInterval<f32>>,
+    pub(crate) range: Option<RelativeRange>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) deadzone_norm: Option<f32>,
@@ -781,9 +1990,9 @@ pub(crate) struct EmaFilterTransform {
 #[serde(deny_unknown_fields)]
 pub(crate) struct SteeringTransform {
     #[serde(default = "default_counts_to_lock")]
-    pub(crate) counts_to_lock: f32,
-    #[serde(default)]
-    pub(crate) deadzone_counts: f32,
+    pub(crate) counts_to_lock: RelativeDistance,
+    #[serde(default = "default_deadzone_counts")]
+    pub(crate) deadzone_counts: RelativeDistance,
     #[serde(default = "default_smoothing_alpha")]
     pub(crate) smoothing_alpha: f32,
     #[serde(default = "default_auto_center_halflife")]
@@ -799,8 +2008,11 @@ pub(crate) struct SteeringTransform {
     pub(crate) user_input_ema_filter: Option<EmaFilterTransform>,
 }
 
-fn default_counts_to_lock() -> f32 {
-    600.0
+fn default_counts_to_lock() -> RelativeDistance {
+    RelativeDistance::Counts(600.0)
+}
+fn default_deadzone_counts() -> RelativeDistance {
+    RelativeDistance::Counts(0.0)
 }
 fn default_smoothing_alpha() -> f32 {
     1.0
@@ -829,12 +2041,26 @@ pub(crate) struct ControlsPredefined {
     pub(crate) joystick_controls: HashMap<String, JoystickControlPredefined>,
     #[serde(default)]
     pub(crate) mouse_controls: HashMap<String, MouseControlPredefined>,
+    #[serde(default)]
+    pub(crate) keyboard_controls: HashMap<String, KeyboardControlPredefined>,
+    #[serde(default)]
+    pub(crate) gamepad_controls: HashMap<String, GamepadControlPredefined>,
+    #[serde(default)]
+    pub(crate) touch_controls: HashMap<String, TouchControlPredefined>,
+    #[serde(default)]
+    pub(crate) osc_controls: HashMap<String, OscControlPredefined>,
+    #[serde(default)]
+    pub(crate) json_controls: HashMap<String, JsonControlPredefined>,
+    #[serde(default)]
+    pub(crate) generator_controls: HashMap<String, GeneratorControlPredefined>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MidiControlPredefined {
     pub(crate) midi_message: MidiMessage,
+    #[serde(default)]
+    pub(crate) encoding: Option<MidiValueEncoding>,
     pub(crate) range: NumInterval<i32>,
     pub(crate) description: String,
 }
@@ -857,6 +2083,72 @@ pub(crate) struct MouseControlPredefined {
     pub(crate) description: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeyboardControlPredefined {
+    pub(crate) r#type: crate::common::ControlType,
+    pub(crate) range: NumInterval<i32>,
+    #[serde(default)]
+    pub(crate) repeat: bool,
+    pub(crate) description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GamepadControlPredefined {
+    pub(crate) r#type: crate::common::ControlType,
+    #[serde(default)]
+    pub(crate) range: Option<NumInterval<i32>>,
+    pub(crate) description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TouchControlPredefined {
+    pub(crate) r#type: TouchControlType,
+    #[serde(default)]
+    pub(crate) finger: Option<u8>,
+    #[serde(default)]
+    pub(crate) fingers: Option<u8>,
+    #[serde(default)]
+    pub(crate) range: Option<NumInterval<i32>>,
+    pub(crate) description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OscControlPredefined {
+    #[serde(default)]
+    pub(crate) address: Option<String>,
+    #[serde(default)]
+    pub(crate) argument: usize,
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JsonControlPredefined {
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GeneratorControlPredefined {
+    pub(crate) waveform: GeneratorWaveform,
+    #[serde(default)]
+    pub(crate) frequency: Option<f32>,
+    #[serde(default)]
+    pub(crate) amplitude: Option<f32>,
+    #[serde(default)]
+    pub(crate) offset: Option<f32>,
+    #[serde(default)]
+    pub(crate) steps: Option<Vec<f32>>,
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) description: String,
+}
+
 // ----------------
 // Resolved Transformation Types
 // ----------------
@@ -871,7 +2163,7 @@ pub(crate) enum ResolvedTransformationStep {
     },
     Integrate {
         runtime_state_id: StepRuntimeStateId,
-        integrate: IntegrateTransform,
+        integrate: ResolvedIntegrateTransform,
     },
     // Curve {
     //     curve: CurveTransform,
@@ -939,6 +2231,15 @@ pub(crate) struct ResolvedSteeringTransform {
     pub(crate) user_input_ema_filter_average: Option<EmaFilterTransform>,
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedIntegrateTransform {
+    /// In input counts.
+    pub(crate) range: NumInterval<f32>,
+    pub(crate) deadzone_norm: Option<f32>,
+    #[allow(dead_code)]
+    pub(crate) smoothing_alpha: Option<f32>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct ResolvedPedalSmootherTransform {
     pub(crate) rise_rate: f32,
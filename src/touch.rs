@@ -0,0 +1,525 @@
+use evdev::raw_stream::RawDevice;
+use evdev::{AbsoluteAxisCode, Device, EventType, SynchronizationCode};
+use log::warn;
+use nix::ioctl_read_buf;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::os::fd::AsRawFd;
+
+use crate::common::{ControlType, NumInterval};
+use crate::evdev_source::{EvdevDeviceInfo, EvdevSource, EvdevSourceManager};
+use crate::schemas::{ResolvedTouchControl, TouchControlType};
+
+/// Slot count used if the device doesn't report ABS_MT_SLOT range.
+const DEFAULT_MT_SLOT_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct TouchContact {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) pressure: i32,
+}
+
+/// State of all touching fingers after a complete (SYN_REPORT terminated) evdev frame.
+#[derive(Debug, Clone)]
+pub(crate) struct TouchFrame {
+    pub(crate) device_key: String,
+    /// Ordered by the time a finger started touching the surface.
+    pub(crate) contacts: Vec<TouchContact>,
+}
+
+impl Display for TouchFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] fingers={}", self.device_key, self.contacts.len())?;
+        for (i, c) in self.contacts.iter().enumerate() {
+            write!(f, " #{} x={} y={} p={}", i + 1, c.x, c.y, c.pressure)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MtSlot {
+    /// -1 means the slot is not in use (kernel protocol B semantics).
+    tracking_id: i32,
+    /// Order of contact start, used to number fingers.
+    touch_seq: u64,
+    contact: TouchContact,
+}
+
+/// Multitouch protocol B state machine: follows ABS_MT_SLOT / ABS_MT_TRACKING_ID
+/// and produces the list of active contacts on every SYN_REPORT.
+#[derive(Debug)]
+pub(crate) struct MtSlotTracker {
+    slots: Vec<MtSlot>,
+    current_slot: usize,
+    next_touch_seq: u64,
+    dropped: bool,
+    /// Set once the frame following a SYN_DROPPED is complete, the slots must be reloaded.
+    resync_pending: bool,
+}
+
+impl MtSlotTracker {
+    pub(crate) fn new(slot_count: usize) -> Self {
+        Self {
+            slots: vec![
+                MtSlot {
+                    tracking_id: -1,
+                    ..Default::default()
+                };
+                slot_count.max(1)
+            ],
+            current_slot: 0,
+            next_touch_seq: 0,
+            dropped: false,
+            resync_pending: false,
+        }
+    }
+
+    /// Feeds one evdev event, returns active contacts when a frame is complete.
+    pub(crate) fn handle_event(&mut self, event: &evdev::InputEvent) -> Option<Vec<TouchContact>> {
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            let code = SynchronizationCode(event.code());
+            if code == SynchronizationCode::SYN_DROPPED {
+                // NB: events until the next SYN_REPORT are incomplete, skip them;
+                // NB: lifted fingers may be lost as well, the slots need a `resync`.
+                self.dropped = true;
+            } else if code == SynchronizationCode::SYN_REPORT {
+                if self.dropped {
+                    self.dropped = false;
+                    self.resync_pending = true;
+                    return None;
+                }
+                return Some(self.contacts());
+            }
+            return None;
+        }
+
+        if self.dropped || event.event_type() != EventType::ABSOLUTE {
+            return None;
+        }
+
+        let value = event.value();
+        match AbsoluteAxisCode(event.code()) {
+            AbsoluteAxisCode::ABS_MT_SLOT => {
+                let slot = value.max(0) as usize;
+                if slot >= self.slots.len() {
+                    self.slots.resize(
+                        slot + 1,
+                        MtSlot {
+                            tracking_id: -1,
+                            ..Default::default()
+                        },
+                    );
+                }
+                self.current_slot = slot;
+            }
+            AbsoluteAxisCode::ABS_MT_TRACKING_ID => {
+                let slot = &mut self.slots[self.current_slot];
+                if value < 0 {
+                    slot.tracking_id = -1;
+                } else if slot.tracking_id != value {
+                    slot.tracking_id = value;
+                    slot.touch_seq = self.next_touch_seq;
+                    self.next_touch_seq += 1;
+                }
+            }
+            AbsoluteAxisCode::ABS_MT_POSITION_X => self.slots[self.current_slot].contact.x = value,
+            AbsoluteAxisCode::ABS_MT_POSITION_Y => self.slots[self.current_slot].contact.y = value,
+            AbsoluteAxisCode::ABS_MT_PRESSURE => {
+                self.slots[self.current_slot].contact.pressure = value
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// True once after a dropped frame, the caller then calls `resync` or `clear`.
+    pub(crate) fn take_resync_pending(&mut self) -> bool {
+        std::mem::take(&mut self.resync_pending)
+    }
+
+    /// Loads all the slots as read from the device, fingers still touching keep their order.
+    pub(crate) fn resync(&mut self, state: &MtSlotsState) {
+        if state.tracking_ids.len() > self.slots.len() {
+            self.slots.resize(
+                state.tracking_ids.len(),
+                MtSlot {
+                    tracking_id: -1,
+                    ..Default::default()
+                },
+            );
+        }
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let tracking_id = state.tracking_ids.get(index).copied().unwrap_or(-1);
+            if tracking_id < 0 {
+                slot.tracking_id = -1;
+                continue;
+            }
+            if slot.tracking_id != tracking_id {
+                slot.tracking_id = tracking_id;
+                slot.touch_seq = self.next_touch_seq;
+                self.next_touch_seq += 1;
+            }
+            let value = |values: &[i32]| values.get(index).copied().unwrap_or_default();
+            slot.contact = TouchContact {
+                x: value(&state.x),
+                y: value(&state.y),
+                pressure: value(&state.pressure),
+            };
+        }
+        self.current_slot = state.current_slot.min(self.slots.len() - 1);
+    }
+
+    /// Forgets all the contacts, used if the slots can't be read from the device.
+    pub(crate) fn clear(&mut self) {
+        for slot in &mut self.slots {
+            slot.tracking_id = -1;
+        }
+    }
+
+    pub(crate) fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub(crate) fn contacts(&self) -> Vec<TouchContact> {
+        let mut active: Vec<&MtSlot> = self.slots.iter().filter(|s| s.tracking_id >= 0).collect();
+        active.sort_by_key(|s| s.touch_seq);
+        active.into_iter().map(|s| s.contact).collect()
+    }
+}
+
+/// Multitouch slot values read from the device, indexed by slot.
+#[derive(Debug, Default)]
+pub(crate) struct MtSlotsState {
+    pub(crate) current_slot: usize,
+    pub(crate) tracking_ids: Vec<i32>,
+    pub(crate) x: Vec<i32>,
+    pub(crate) y: Vec<i32>,
+    pub(crate) pressure: Vec<i32>,
+}
+
+// EVIOCGMTSLOTS: the buffer holds the ABS_MT_* code followed by the values of all slots.
+ioctl_read_buf!(eviocgmtslots, b'E', 0x0a, i32);
+
+/// Reads the current values of all the slots (EVIOCGMTSLOTS).
+fn read_mt_slots(device: &RawDevice, slot_count: usize) -> std::io::Result<MtSlotsState> {
+    let read = |code: AbsoluteAxisCode| -> std::io::Result<Vec<i32>> {
+        let mut buf = vec![0; slot_count + 1];
+        buf[0] = code.0 as i32;
+        // SAFETY: the request size is `buf.len() * size_of::<i32>()`. The kernel reads the
+        // code from `buf[0]` and writes the values of at most `buf.len() - 1` slots to
+        // `buf[1..]` (`struct input_mt_request_layout`), so it never writes past the buffer.
+        unsafe { eviocgmtslots(device.as_raw_fd(), &mut buf) }.map_err(std::io::Error::from)?;
+        buf.remove(0);
+        Ok(buf)
+    };
+    let current_slot = device.get_abs_state()?[AbsoluteAxisCode::ABS_MT_SLOT.0 as usize].value;
+    Ok(MtSlotsState {
+        current_slot: current_slot.max(0) as usize,
+        tracking_ids: read(AbsoluteAxisCode::ABS_MT_TRACKING_ID)?,
+        x: read(AbsoluteAxisCode::ABS_MT_POSITION_X)?,
+        y: read(AbsoluteAxisCode::ABS_MT_POSITION_Y)?,
+        pressure: read(AbsoluteAxisCode::ABS_MT_PRESSURE)?,
+    })
+}
+
+/// Computes the value of a touch control for the given frame contacts.
+/// Returns None if the control has no value for this frame (e.g. finger count condition not met).
+pub(crate) fn touch_control_value(
+    control: &ResolvedTouchControl,
+    contacts: &[TouchContact],
+) -> Option<f32> {
+    if let Some(fingers) = control.fingers {
+        if contacts.len() != fingers as usize {
+            return None;
+        }
+    }
+
+    let field = |c: &TouchContact| match control.r#type {
+        TouchControlType::PositionX => c.x as f32,
+        TouchControlType::PositionY => c.y as f32,
+        _ => c.pressure as f32,
+    };
+
+    match control.r#type {
+        TouchControlType::FingerCount => Some(contacts.len() as f32),
+        TouchControlType::Touching => {
+            let needed = control.finger.unwrap_or(1) as usize;
+            Some(if contacts.len() >= needed { 1.0 } else { 0.0 })
+        }
+        TouchControlType::PositionX | TouchControlType::PositionY | TouchControlType::Pressure => {
+            match control.finger {
+                Some(finger) => contacts.get(finger as usize - 1).map(field),
+                None if contacts.is_empty() => None,
+                None => Some(contacts.iter().map(field).sum::<f32>() / contacts.len() as f32),
+            }
+        }
+    }
+}
+
+/// Range of a touch control as reported by the device, used if none is configured.
+pub(crate) fn touch_control_device_range(
+    control_type: TouchControlType,
+    axes: &HashMap<ControlType, NumInterval<i32>>,
+) -> Option<NumInterval<i32>> {
+    match control_type {
+        TouchControlType::PositionX => axes.get(&ControlType::AbsMtPositionX).copied(),
+        TouchControlType::PositionY => axes.get(&ControlType::AbsMtPositionY).copied(),
+        TouchControlType::Pressure => axes.get(&ControlType::AbsMtPressure).copied(),
+        TouchControlType::FingerCount => axes
+            .get(&ControlType::AbsMtSlot)
+            .map(|slots| NumInterval::new(0, slots.to + 1)),
+        TouchControlType::Touching => Some(NumInterval::new(0, 1)),
+    }
+}
+
+/// Multitouch protocol B (slotted) touchpads, touchscreens and tablets.
+#[derive(Debug)]
+pub(crate) struct TouchSource {
+    tracker: MtSlotTracker,
+}
+
+impl Default for TouchSource {
+    fn default() -> Self {
+        Self {
+            tracker: MtSlotTracker::new(DEFAULT_MT_SLOT_COUNT),
+        }
+    }
+}
+
+impl Clone for TouchSource {
+    /// A fresh tracker, every opened device follows its own slots.
+    fn clone(&self) -> Self {
+        Self {
+            tracker: MtSlotTracker::new(self.tracker.slots.len()),
+        }
+    }
+}
+
+impl EvdevSource for TouchSource {
+    type Event = TouchFrame;
+
+    // NB: the synced device hides SYN_DROPPED and restores only the current slot.
+    type Stream = evdev::raw_stream::EventStream;
+
+    const DEVICE_KIND: &'static str = "touch";
+
+    fn supports(&self, device: &Device) -> bool {
+        // Only multitouch protocol B (slotted) devices are supported.
+        device
+            .supported_absolute_axes()
+            .is_some_and(|axes| axes.contains(AbsoluteAxisCode::ABS_MT_SLOT))
+    }
+
+    fn init(&mut self, device_info: &EvdevDeviceInfo) {
+        let slot_count = device_info
+            .axes
+            .iter()
+            .find(|(control_type, _)| *control_type == ControlType::AbsMtSlot)
+            .map(|(_, slots)| slots.to as usize + 1)
+            .unwrap_or(DEFAULT_MT_SLOT_COUNT);
+        self.tracker = MtSlotTracker::new(slot_count);
+    }
+
+    fn handle_event(
+        &mut self,
+        stream: &evdev::raw_stream::EventStream,
+        device_key: &str,
+        event: evdev::InputEvent,
+        _debug: bool,
+    ) -> Option<TouchFrame> {
+        let contacts = match self.tracker.handle_event(&event) {
+            Some(contacts) => contacts,
+            None if self.tracker.take_resync_pending() => {
+                match read_mt_slots(stream.device(), self.tracker.slot_count()) {
+                    Ok(state) => self.tracker.resync(&state),
+                    Err(e) => {
+                        warn!(
+                            "Can't read touch slots of {} after dropped events, \
+                            releasing all fingers: {}",
+                            device_key, e
+                        );
+                        self.tracker.clear();
+                    }
+                }
+                self.tracker.contacts()
+            }
+            None => return None,
+        };
+        Some(TouchFrame {
+            device_key: device_key.to_string(),
+            contacts,
+        })
+    }
+}
+
+pub(crate) type TouchManager = EvdevSourceManager<TouchSource>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::InputEvent;
+
+    fn abs(code: AbsoluteAxisCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE.0, code.0, value)
+    }
+
+    fn syn() -> InputEvent {
+        InputEvent::new(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_REPORT.0,
+            0,
+        )
+    }
+
+    fn feed(tracker: &mut MtSlotTracker, events: &[InputEvent]) -> Option<Vec<TouchContact>> {
+        events.iter().filter_map(|e| tracker.handle_event(e)).last()
+    }
+
+    fn control(
+        r#type: TouchControlType,
+        finger: Option<u8>,
+        fingers: Option<u8>,
+    ) -> ResolvedTouchControl {
+        ResolvedTouchControl {
+            r#type,
+            finger,
+            fingers,
+            range: None,
+            _description: None,
+        }
+    }
+
+    #[test]
+    fn test_slot_tracking_orders_fingers_by_touch_start() {
+        let mut tracker = MtSlotTracker::new(5);
+
+        // Finger in slot 1 touches first, then slot 0.
+        let contacts = feed(
+            &mut tracker,
+            &[
+                abs(AbsoluteAxisCode::ABS_MT_SLOT, 1),
+                abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, 10),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_X, 100),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_Y, 200),
+                syn(),
+                abs(AbsoluteAxisCode::ABS_MT_SLOT, 0),
+                abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, 11),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_X, 300),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_Y, 400),
+                syn(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].x, 100);
+        assert_eq!(contacts[1].x, 300);
+
+        // First finger lifts, second becomes finger #1.
+        let contacts = feed(
+            &mut tracker,
+            &[
+                abs(AbsoluteAxisCode::ABS_MT_SLOT, 1),
+                abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, -1),
+                syn(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].y, 400);
+    }
+
+    #[test]
+    fn test_slot_tracking_skips_dropped_frame() {
+        let mut tracker = MtSlotTracker::new(2);
+        let dropped = InputEvent::new(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_DROPPED.0,
+            0,
+        );
+        assert!(feed(
+            &mut tracker,
+            &[dropped, abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, 1), syn()]
+        )
+        .is_none());
+        assert!(tracker.take_resync_pending());
+        assert!(!tracker.take_resync_pending());
+        assert_eq!(feed(&mut tracker, &[syn()]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_slot_tracking_resync_after_drop() {
+        let mut tracker = MtSlotTracker::new(3);
+        let contacts = feed(
+            &mut tracker,
+            &[
+                abs(AbsoluteAxisCode::ABS_MT_SLOT, 1),
+                abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, 7),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_X, 100),
+                syn(),
+                abs(AbsoluteAxisCode::ABS_MT_SLOT, 0),
+                abs(AbsoluteAxisCode::ABS_MT_TRACKING_ID, 8),
+                abs(AbsoluteAxisCode::ABS_MT_POSITION_X, 200),
+                syn(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(contacts.len(), 2);
+
+        // The lift of the first finger (slot 1) is lost, a third one touches in slot 2.
+        let dropped = InputEvent::new(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_DROPPED.0,
+            0,
+        );
+        assert!(feed(&mut tracker, &[dropped, syn()]).is_none());
+        assert!(tracker.take_resync_pending());
+        tracker.resync(&MtSlotsState {
+            current_slot: 2,
+            tracking_ids: vec![8, -1, 9],
+            x: vec![210, 0, 300],
+            y: vec![0, 0, 0],
+            pressure: vec![0, 0, 0],
+        });
+        let contacts = tracker.contacts();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].x, 210);
+        assert_eq!(contacts[1].x, 300);
+
+        // Without the device state all fingers are released.
+        tracker.clear();
+        assert!(tracker.contacts().is_empty());
+    }
+
+    #[test]
+    fn test_touch_control_value() {
+        let contacts = [
+            TouchContact {
+                x: 100,
+                y: 10,
+                pressure: 5,
+            },
+            TouchContact {
+                x: 300,
+                y: 30,
+                pressure: 7,
+            },
+        ];
+
+        let count = control(TouchControlType::FingerCount, None, None);
+        assert_eq!(touch_control_value(&count, &contacts), Some(2.0));
+
+        let centroid_y = control(TouchControlType::PositionY, None, Some(2));
+        assert_eq!(touch_control_value(&centroid_y, &contacts), Some(20.0));
+        assert_eq!(touch_control_value(&centroid_y, &contacts[..1]), None);
+
+        let second_x = control(TouchControlType::PositionX, Some(2), None);
+        assert_eq!(touch_control_value(&second_x, &contacts), Some(300.0));
+        assert_eq!(touch_control_value(&second_x, &contacts[..1]), None);
+
+        let touching = control(TouchControlType::Touching, None, None);
+        assert_eq!(touch_control_value(&touching, &[]), Some(0.0));
+        assert_eq!(touch_control_value(&touching, &contacts), Some(1.0));
+    }
+}